@@ -0,0 +1,19 @@
+use bench::{implementations, workloads};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::hint::black_box;
+
+/// Every interpreter on every real input it can run, in instructions per
+/// second.
+fn interpreters(c: &mut Criterion) {
+    for w in workloads() {
+        let mut group = c.benchmark_group(w.name);
+        group.throughput(Throughput::Elements(w.steps()));
+        for (name, runner) in implementations(w.subset) {
+            group.bench_function(name, |b| b.iter(|| w.run(black_box(runner))));
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, interpreters);
+criterion_main!(benches);
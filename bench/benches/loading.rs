@@ -0,0 +1,24 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use intcode::Program;
+use std::hint::black_box;
+
+/// Loading the largest real input from text and from the binary form, in
+/// bytes per second.
+fn loading(c: &mut Criterion) {
+    let text = include_str!("../../day9/src/input");
+    let bytes = text.parse::<Program>().unwrap().to_bytes();
+
+    let mut group = c.benchmark_group("day9");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.bench_function("text", |b| {
+        b.iter(|| black_box(text).parse::<Program>().unwrap())
+    });
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+    group.bench_function("binary", |b| {
+        b.iter(|| Program::from_bytes(black_box(&bytes)).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, loading);
+criterion_main!(benches);
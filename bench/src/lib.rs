@@ -0,0 +1,177 @@
+//! The real puzzle inputs as benchmark workloads, to run on each of the
+//! interpreters the fuzz harness compares.
+//!
+//! `cargo bench -p bench` times each interpreter on each workload it
+//! understands and reports instructions per second. To check a change to
+//! `step` or the decoding for regressions, save a baseline first and
+//! compare against it afterwards:
+//!
+//! ```text
+//! cargo bench -p bench -- --save-baseline before
+//! cargo bench -p bench -- --baseline before
+//! ```
+
+use fuzz::gen::Case;
+use fuzz::harness::{Implementation, Outcome};
+use intcode::{Machine, Program, State};
+
+pub use fuzz::gen::Subset;
+pub use fuzz::harness::implementations;
+
+pub struct Workload {
+    pub name: &'static str,
+    pub subset: Subset,
+    pub prog: Vec<isize>,
+    /// The program is run once per entry, given those inputs followed by
+    /// the previous run's answer.
+    pub runs: Vec<Vec<isize>>,
+    /// what the last run should answer
+    pub answer: isize,
+}
+
+fn load(text: &str) -> Vec<isize> {
+    text.parse::<Program>().unwrap().to_vec()
+}
+
+pub fn workloads() -> Vec<Workload> {
+    let mut day2 = load(include_str!("../../day2/src/input"));
+    day2[1] = 12;
+    day2[2] = 2;
+    let day5 = load(include_str!("../../day5/src/input"));
+    let day7 = load(include_str!("../../day7/src/input"));
+    let day9 = load(include_str!("../../day9/src/input"));
+
+    vec![
+        Workload {
+            name: "day2",
+            subset: Subset::Arithmetic,
+            prog: day2,
+            runs: vec![vec![]],
+            answer: 2894520,
+        },
+        Workload {
+            name: "day5-part1",
+            subset: Subset::Day5,
+            prog: day5.clone(),
+            runs: vec![vec![1]],
+            answer: 7566643,
+        },
+        Workload {
+            name: "day5-part2",
+            subset: Subset::Day5,
+            prog: day5,
+            runs: vec![vec![5]],
+            answer: 9265694,
+        },
+        Workload {
+            name: "day7-chain",
+            subset: Subset::Day5,
+            prog: day7,
+            runs: vec![vec![1, 0], vec![3], vec![0], vec![4], vec![2]],
+            answer: 24625,
+        },
+        Workload {
+            name: "day9-part1",
+            subset: Subset::Day9,
+            prog: day9.clone(),
+            runs: vec![vec![1]],
+            answer: 3839402290,
+        },
+        Workload {
+            name: "day9-part2",
+            subset: Subset::Day9,
+            prog: day9,
+            runs: vec![vec![2]],
+            answer: 35734,
+        },
+    ]
+}
+
+impl Workload {
+    /// The answer `run` comes to: the last output of the last run, or what
+    /// it left at 0 for programs that don't output.
+    pub fn run(&self, run: Implementation) -> isize {
+        let mut answer = None;
+        for inputs in &self.runs {
+            let mut case = Case {
+                prog: self.prog.clone(),
+                inputs: inputs.clone(),
+            };
+            case.inputs.extend(answer);
+            answer = match run(&case, usize::MAX) {
+                Outcome::Halted {
+                    outputs, memory, ..
+                } => outputs.last().or_else(|| memory.first()).copied(),
+                outcome => panic!("{}: {:?}", self.name, outcome),
+            };
+        }
+        answer.unwrap()
+    }
+
+    /// how many instructions a run of the whole workload executes
+    pub fn steps(&self) -> u64 {
+        let mut steps = 0;
+        let mut answer = None;
+        for inputs in &self.runs {
+            let mut m = Machine::new(&self.prog, inputs);
+            if let Some(answer) = answer {
+                m.push_input(answer);
+            }
+            loop {
+                match m.step().unwrap() {
+                    State::Halt => break,
+                    State::Output(o) => answer = Some(o),
+                    _ => {}
+                }
+                steps += 1;
+            }
+        }
+        steps
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_answers() {
+        for w in workloads() {
+            for (name, runner) in implementations(w.subset) {
+                assert_eq!(w.answer, w.run(runner), "{} on {}", name, w.name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_implementations() {
+        let names = |subset| {
+            implementations(subset)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec!["day2", "day5", "day7", "intcode"],
+            names(Subset::Arithmetic)
+        );
+        assert_eq!(vec!["day5", "day7", "intcode"], names(Subset::Day5));
+        assert_eq!(vec!["intcode"], names(Subset::Day9));
+    }
+
+    #[test]
+    fn test_steps() {
+        let steps: Vec<_> = workloads().iter().map(|w| (w.name, w.steps())).collect();
+        assert_eq!(
+            vec![
+                ("day2", 31),
+                ("day5-part1", 61),
+                ("day5-part2", 103),
+                ("day7-chain", 41),
+                ("day9-part1", 204),
+                ("day9-part2", 371205),
+            ],
+            steps
+        );
+    }
+}
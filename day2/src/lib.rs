@@ -0,0 +1,94 @@
+pub fn run(xs: &[usize], noun: usize, verb: usize) -> usize {
+    let mut prog: Vec<usize> = xs.to_vec();
+    prog[1] = noun;
+    prog[2] = verb;
+
+    execute(&mut prog);
+    prog[0]
+}
+
+/// run the program in place until it halts
+pub fn execute(prog: &mut [usize]) {
+    let mut pc = 0;
+
+    loop {
+        let op = prog[pc];
+        // println!("{:?}", prog);
+        match op {
+            1 => {
+                // sum
+                let left_pos = prog[pc + 1];
+                let right_pos = prog[pc + 2];
+                let dest_pos = prog[pc + 3];
+
+                let left = prog[left_pos];
+                let right = prog[right_pos];
+
+                let acc = left + right;
+
+                // println!(
+                //     "[{}]\t {} {} {} {} => {} + {} = {}",
+                //     pc, op, left_pos, right_pos, dest_pos, left, right, acc
+                // );
+
+                prog[dest_pos] = acc;
+
+                pc += 4;
+            }
+            2 => {
+                // product
+                let left_pos = prog[pc + 1];
+                let right_pos = prog[pc + 2];
+                let dest_pos = prog[pc + 3];
+
+                let left = prog[left_pos];
+                let right = prog[right_pos];
+
+                let acc = left * right;
+
+                // println!(
+                //     "[{}]\t {} {} {} {} => {} + {} = {}",
+                //     pc, op, left_pos, right_pos, dest_pos, left, right, acc
+                // );
+
+                prog[dest_pos] = acc;
+
+                pc += 4;
+            }
+            99 => break,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use intcode::conformance::{check, load_dir, Run, Subset, DIR};
+    use std::convert::TryFrom;
+    use std::panic::{self, AssertUnwindSafe};
+
+    #[test]
+    fn test_conformance() {
+        // only ever moves forward, so it can't run forever
+        let run = |prog: &[isize], _inputs: &[isize]| {
+            let mut prog: Vec<usize> = prog
+                .iter()
+                .map(|&x| usize::try_from(x).map_err(|_| "negative".to_string()))
+                .collect::<Result<_, _>>()?;
+            panic::catch_unwind(AssertUnwindSafe(|| execute(&mut prog)))
+                .map_err(|_| "panicked".to_string())?;
+            Ok(Run {
+                outputs: Vec::new(),
+                memory: prog.iter().map(|&x| x as isize).collect(),
+            })
+        };
+
+        let cases = load_dir(DIR).unwrap();
+        let failures: Vec<String> = check(&cases, Subset::Arithmetic, run)
+            .iter()
+            .map(|f| f.to_string())
+            .collect();
+        assert_eq!(Vec::<String>::new(), failures);
+    }
+}
@@ -1,78 +1,28 @@
-fn main() {
-    let input = include_str!("input");
+use day2::run;
+use intcode::symbolic::{solve, Machine};
+use intcode::Program;
 
-    let nums: Vec<usize> = input
-        .split(",")
-        .filter_map(|x| x.trim().parse::<usize>().ok())
-        .collect();
+fn main() {
+    let prog = Program::load_arg_or(concat!(env!("CARGO_MANIFEST_DIR"), "/src/input"));
+    let nums: Vec<usize> = prog.iter().map(|&x| x as usize).collect();
 
     // part1(&vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
     let target = 19690720;
     println!("part1: {}", run(&nums, 12, 2));
-    for noun in 0..=99 {
-        for verb in 0..=99 {
-            if run(&nums, noun, verb) == target {
-                println!("part2: {} (n:{} v:{})", 100 * noun + verb, noun, verb);
-            }
-        }
-    }
-} // 93042 too high
-
-fn run(xs: &[usize], noun: usize, verb: usize) -> usize {
-    let mut pc = 0;
-    let mut prog: Vec<usize> = xs.to_vec();
-    prog[1] = noun;
-    prog[2] = verb;
-
-    loop {
-        let op = prog[pc];
-        // println!("{:?}", prog);
-        match op {
-            1 => {
-                // sum
-                let left_pos = prog[pc + 1];
-                let right_pos = prog[pc + 2];
-                let dest_pos = prog[pc + 3];
-
-                let left = prog[left_pos];
-                let right = prog[right_pos];
-
-                let acc = left + right;
-
-                // println!(
-                //     "[{}]\t {} {} {} {} => {} + {} = {}",
-                //     pc, op, left_pos, right_pos, dest_pos, left, right, acc
-                // );
 
-                prog[dest_pos] = acc;
+    // rather than trying all 10,000 pairs, run once with the noun and verb
+    // left as symbols and solve the formula that comes out
+    let mut m = Machine::new(&prog);
+    m.symbol(1, "noun");
+    m.symbol(2, "verb");
+    m.run().unwrap();
 
-                pc += 4;
-            }
-            2 => {
-                // product
-                let left_pos = prog[pc + 1];
-                let right_pos = prog[pc + 2];
-                let dest_pos = prog[pc + 3];
-
-                let left = prog[left_pos];
-                let right = prog[right_pos];
-
-                let acc = left * right;
-
-                // println!(
-                //     "[{}]\t {} {} {} {} => {} + {} = {}",
-                //     pc, op, left_pos, right_pos, dest_pos, left, right, acc
-                // );
-
-                prog[dest_pos] = acc;
-
-                pc += 4;
-            }
-            99 => break,
-            _ => unreachable!(),
-        }
+    let formula = &m.memory()[0];
+    if let Some(poly) = formula.to_poly() {
+        println!("address 0 = {}", poly);
     }
-
-    prog[0]
-}
-// 406198 too low
+    for solution in solve(formula, target, &[("noun", 0..=99), ("verb", 0..=99)]) {
+        let (noun, verb) = (solution[0], solution[1]);
+        println!("part2: {} (n:{} v:{})", 100 * noun + verb, noun, verb);
+    }
+} // 93042 too high
@@ -57,7 +57,7 @@ fn part1(a: &[Segment], b: &[Segment]) -> Option<isize> {
 
     for x in a {
         for y in b {
-            if let Some(crossing) = x.intersection(&y) {
+            if let Some(crossing) = x.intersection(y) {
                 intersections.insert(crossing);
             }
         }
@@ -19,8 +19,8 @@ fn part1(begin: isize, end: isize) -> usize {
 
 fn filter_range(begin: isize, end: isize) -> Vec<isize> {
     (begin..=end)
-        .filter_map(|x| {
-            let digits = format!("{}", &x).into_bytes();
+        .filter(|x| {
+            let digits = format!("{}", x).into_bytes();
 
             let diffs = digits
                 .iter()
@@ -42,11 +42,7 @@ fn filter_range(begin: isize, end: isize) -> Vec<isize> {
                     _ => (dbl, dec),
                 });
 
-            if double && !decreases {
-                Some(x)
-            } else {
-                None
-            }
+            double && !decreases
         })
         .collect()
 }
@@ -1,3 +1,5 @@
+use intcode::conformance::Step;
+
 #[derive(Debug, PartialEq)]
 enum ParameterMode {
     Position,
@@ -41,7 +43,18 @@ pub enum State {
     Halt,
 }
 
-pub fn interpret(prog: &mut Vec<isize>, pc: usize) -> (usize, State) {
+impl From<State> for Step {
+    fn from(state: State) -> Self {
+        match state {
+            State::Default => Step::Default,
+            State::Input(dest) => Step::Input(dest),
+            State::Output(o) => Step::Output(o),
+            State::Halt => Step::Halt,
+        }
+    }
+}
+
+pub fn interpret(prog: &mut [isize], pc: usize) -> (usize, State) {
     use std::convert::TryInto;
 
     let instruction = prog[pc];
@@ -50,12 +63,12 @@ pub fn interpret(prog: &mut Vec<isize>, pc: usize) -> (usize, State) {
     match op {
         1 => {
             // sum
-            let left = decode_param(1, modes.0, &prog, pc);
-            let right = decode_param(2, modes.1, &prog, pc);
+            let left = decode_param(1, modes.0, prog, pc);
+            let right = decode_param(2, modes.1, prog, pc);
             let dest_pos: usize = prog[pc + 3].try_into().unwrap();
 
             let acc = left + right;
-            dbg!("sum", left, right, dest_pos, acc);
+            // dbg!("sum", left, right, dest_pos, acc);
             prog[dest_pos] = acc;
 
             let new_pc = pc + 4;
@@ -63,12 +76,12 @@ pub fn interpret(prog: &mut Vec<isize>, pc: usize) -> (usize, State) {
         }
         2 => {
             // product
-            let left = decode_param(1, modes.0, &prog, pc);
-            let right = decode_param(2, modes.1, &prog, pc);
+            let left = decode_param(1, modes.0, prog, pc);
+            let right = decode_param(2, modes.1, prog, pc);
             let dest_pos: usize = prog[pc + 3].try_into().unwrap();
 
             let acc = left * right;
-            dbg!("product", left, right, dest_pos, acc);
+            // dbg!("product", left, right, dest_pos, acc);
             prog[dest_pos] = acc;
 
             let new_pc = pc + 4;
@@ -77,15 +90,15 @@ pub fn interpret(prog: &mut Vec<isize>, pc: usize) -> (usize, State) {
         3 => {
             // save input to pos
             let dest_pos: usize = prog[pc + 1].try_into().unwrap();
-            dbg!("input to", dest_pos);
+            // dbg!("input to", dest_pos);
 
             let new_pc = pc + 2;
             (new_pc, State::Input(dest_pos))
         }
         4 => {
             // output
-            let v = decode_param(1, modes.0, &prog, pc);
-            dbg!("output", v);
+            let v = decode_param(1, modes.0, prog, pc);
+            // dbg!("output", v);
 
             let new_pc = pc + 2;
 
@@ -93,20 +106,20 @@ pub fn interpret(prog: &mut Vec<isize>, pc: usize) -> (usize, State) {
         }
         5 => {
             // jmp if true
-            let v = decode_param(1, modes.0, &prog, pc);
+            let v = decode_param(1, modes.0, prog, pc);
             let new_pc = match v {
                 0 => pc + 3,
-                _ => decode_param(2, modes.1, &prog, pc).try_into().unwrap(),
+                _ => decode_param(2, modes.1, prog, pc).try_into().unwrap(),
             };
-            dbg!("jmp if true", v, new_pc);
+            // dbg!("jmp if true", v, new_pc);
 
             (new_pc, State::Default)
         }
         6 => {
             // jmp if false
-            let v = decode_param(1, modes.0, &prog, pc);
+            let v = decode_param(1, modes.0, prog, pc);
             let new_pc = match v {
-                0 => decode_param(2, modes.1, &prog, pc).try_into().unwrap(),
+                0 => decode_param(2, modes.1, prog, pc).try_into().unwrap(),
                 _ => pc + 3,
             };
 
@@ -114,8 +127,8 @@ pub fn interpret(prog: &mut Vec<isize>, pc: usize) -> (usize, State) {
         }
         7 => {
             // less than
-            let l = decode_param(1, modes.0, &prog, pc);
-            let r = decode_param(2, modes.1, &prog, pc);
+            let l = decode_param(1, modes.0, prog, pc);
+            let r = decode_param(2, modes.1, prog, pc);
             let dest_pos: usize = prog[pc + 3].try_into().unwrap();
             prog[dest_pos] = if l < r { 1 } else { 0 };
 
@@ -125,8 +138,8 @@ pub fn interpret(prog: &mut Vec<isize>, pc: usize) -> (usize, State) {
         }
         8 => {
             // equals
-            let l = decode_param(1, modes.0, &prog, pc);
-            let r = decode_param(2, modes.1, &prog, pc);
+            let l = decode_param(1, modes.0, prog, pc);
+            let r = decode_param(2, modes.1, prog, pc);
             let dest_pos: usize = prog[pc + 3].try_into().unwrap();
             prog[dest_pos] = if l == r { 1 } else { 0 };
 
@@ -227,7 +240,7 @@ mod test {
     fn test_jump_if_true() {
         let mut input = vec![1105, 0, 5];
         let (pc, state) = interpret(&mut input, 0);
-        assert_eq!(2, pc);
+        assert_eq!(3, pc);
         assert_eq!(State::Default, state);
 
         let mut input = vec![1105, 1, 5];
@@ -237,7 +250,7 @@ mod test {
 
         let mut input = vec![1005, 3, 7, 0];
         let (pc, state) = interpret(&mut input, 0);
-        assert_eq!(2, pc);
+        assert_eq!(3, pc);
         assert_eq!(State::Default, state);
 
         let mut input = vec![1005, 3, 7, 5];
@@ -247,7 +260,7 @@ mod test {
 
         let mut input = vec![5, 3, 4, 0, 7];
         let (pc, state) = interpret(&mut input, 0);
-        assert_eq!(2, pc);
+        assert_eq!(3, pc);
         assert_eq!(State::Default, state);
 
         let mut input = vec![5, 3, 4, 5, 9];
@@ -265,7 +278,7 @@ mod test {
 
         let mut input = vec![1106, 1, 5];
         let (pc, state) = interpret(&mut input, 0);
-        assert_eq!(2, pc);
+        assert_eq!(3, pc);
         assert_eq!(State::Default, state);
 
         let mut input = vec![1006, 3, 7, 0];
@@ -275,7 +288,7 @@ mod test {
 
         let mut input = vec![1006, 3, 7, 5];
         let (pc, state) = interpret(&mut input, 0);
-        assert_eq!(2, pc);
+        assert_eq!(3, pc);
         assert_eq!(State::Default, state);
 
         let mut input = vec![6, 3, 4, 0, 9];
@@ -285,7 +298,19 @@ mod test {
 
         let mut input = vec![6, 3, 4, 5, 9];
         let (pc, state) = interpret(&mut input, 0);
-        assert_eq!(2, pc);
+        assert_eq!(3, pc);
         assert_eq!(State::Default, state);
     }
+
+    #[test]
+    fn test_conformance() {
+        use intcode::conformance::{check, load_dir, stepper, Subset, DIR};
+
+        let cases = load_dir(DIR).unwrap();
+        let failures: Vec<String> = check(&cases, Subset::Day5, stepper(interpret))
+            .iter()
+            .map(|f| f.to_string())
+            .collect();
+        assert_eq!(Vec::<String>::new(), failures);
+    }
 }
@@ -1,13 +1,8 @@
-mod interpreter;
-use crate::interpreter::{interpret, State};
+use day5::interpreter::{interpret, State};
+use intcode::Program;
 
 fn main() {
-    let input = include_str!("input");
-
-    let prog: Vec<isize> = input
-        .split(",")
-        .filter_map(|x| x.trim().parse::<isize>().ok())
-        .collect();
+    let prog = Program::load_arg_or(concat!(env!("CARGO_MANIFEST_DIR"), "/src/input"));
 
     println!("part1: {}", part1(&prog)); // 223 too low
 
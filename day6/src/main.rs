@@ -19,7 +19,7 @@ fn main() {
 
     let roots = find_roots(&pairs);
     // dbg!(&roots);
-    let primary_root = roots.get(0).cloned().unwrap();
+    let primary_root = roots.first().cloned().unwrap();
 
     let mut hm: HashMap<&str, Vec<String>> = HashMap::new();
     for (p, c) in pairs {
@@ -35,7 +35,7 @@ fn main() {
 }
 
 fn part1(tree: &Orbit) -> usize {
-    count_orbits(&tree, 0)
+    count_orbits(tree, 0)
 }
 
 fn part2(tree: &Orbit) -> usize {
@@ -45,9 +45,9 @@ fn part2(tree: &Orbit) -> usize {
     // (d_x - d_r - 1) + (d_y - d_r - 1)
     // (7 - 3 - 1) + (5 - 3 - 1)
 
-    let you_parents = parents_of("YOU", &tree, &Vec::new()).unwrap();
+    let you_parents = parents_of("YOU", tree, &Vec::new()).unwrap();
     // dbg!(&you_parents);
-    let san_parents = parents_of("SAN", &tree, &Vec::new()).unwrap();
+    let san_parents = parents_of("SAN", tree, &Vec::new()).unwrap();
     // dbg!(&san_parents);
 
     let you_ancestor_count = you_parents.len();
@@ -57,7 +57,7 @@ fn part2(tree: &Orbit) -> usize {
     let san_parents: HashSet<(usize, String)> = san_parents.iter().cloned().enumerate().collect();
     let common_ancestor = you_parents
         .intersection(&san_parents)
-        .max_by(|(depth_x, _), (depth_y, _)| depth_x.cmp(&depth_y))
+        .max_by(|(depth_x, _), (depth_y, _)| depth_x.cmp(depth_y))
         .unwrap();
 
     // dbg!(&common_ancestor);
@@ -79,15 +79,15 @@ fn find_roots<'a>(xs: &[(&'a str, &'a str)]) -> Vec<&'a str> {
     parents.difference(&children).cloned().collect()
 }
 
-fn build_tree(root: &str, mut mapping: &mut HashMap<&str, Vec<String>>) -> Orbit {
+fn build_tree(root: &str, mapping: &mut HashMap<&str, Vec<String>>) -> Orbit {
     let children = mapping.remove(root);
 
     Orbit {
         name: root.to_string(),
         children: children
-            .unwrap_or(Vec::new())
+            .unwrap_or_default()
             .iter()
-            .map(|c| build_tree(c, &mut mapping))
+            .map(|c| build_tree(c, mapping))
             .collect(),
     }
 }
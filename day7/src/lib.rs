@@ -0,0 +1,3 @@
+pub mod interpreter;
+pub mod search;
+pub mod threaded;
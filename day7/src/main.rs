@@ -1,186 +1,87 @@
-mod interpreter;
-use crate::interpreter::*;
-use std::collections::VecDeque;
+use day7::search::{default_threads, search, Best};
+use day7::threaded::ring;
+use intcode::{MachinePool, Program, State};
 
 fn main() {
-    let input = include_str!("input");
+    let prog = Program::load_arg_or(concat!(env!("CARGO_MANIFEST_DIR"), "/src/input"));
 
-    let prog: Vec<isize> = input
-        .split(",")
-        .filter_map(|x| x.trim().parse::<isize>().ok())
-        .collect();
-
-    println!("part1: {}", part1(&prog)); // 12242 too low
-    println!("part2: {}", part2(&prog));
+    let best = part1(&prog);
+    println!("part1: {} (phases: {:?})", best.signal, best.phases); // 12242 too low
+    let best = part2(&prog);
+    println!("part2: {} (phases: {:?})", best.signal, best.phases);
 }
 
-fn part1(prog: &[isize]) -> isize {
-    use itertools::Itertools;
-
-    (0..=4)
-        .permutations(5)
-        // .inspect(|x| {
-        //     dbg!(x);
-        // })
-        .map(|c| thrusters(prog, c[0], c[1], c[2], c[3], c[4]))
-        // .inspect(|a| {
-        //     dbg!(a);
-        // })
-        .max()
-        .unwrap()
-}
-
-fn part2(prog: &[isize]) -> isize {
-    use itertools::Itertools;
-
-    (5..=9)
-        .permutations(5)
-        // .inspect(|x| {
-        //     dbg!(x);
-        // })
-        .map(|c| feedback(prog, c[0], c[1], c[2], c[3], c[4]))
-        // .inspect(|x| {
-        //     dbg!(x);
-        // })
-        .max()
-        .unwrap()
+fn part1(prog: &[isize]) -> Best {
+    let pool = MachinePool::new(prog);
+    search(&[0, 1, 2, 3, 4], 5, default_threads(), |c| {
+        thrusters(&pool, c)
+    })
+    .unwrap()
 }
 
-fn thrusters(prog: &[isize], a: isize, b: isize, c: isize, d: isize, e: isize) -> isize {
-    let signal_a = 0;
-    let signal_b = amplifier(prog, a, signal_a);
-    let signal_c = amplifier(prog, b, signal_b);
-    let signal_d = amplifier(prog, c, signal_c);
-    let signal_e = amplifier(prog, d, signal_d);
-    amplifier(prog, e, signal_e)
+fn part2(prog: &[isize]) -> Best {
+    search(&[5, 6, 7, 8, 9], 5, default_threads(), |c| {
+        ring(prog, c).unwrap()
+    })
+    .unwrap()
 }
 
-fn amplifier(prog: &[isize], phase: isize, signal: isize) -> isize {
-    run_to_completion(prog, &vec![phase, signal]).pop().unwrap()
+/// run the amplifiers in series, each feeding its signal to the next
+fn thrusters(pool: &MachinePool, phases: &[isize]) -> isize {
+    phases
+        .iter()
+        .fold(0, |signal, &phase| amplifier(pool, phase, signal))
 }
 
-fn run_to_completion(prog: &[isize], inputs: &[isize]) -> Vec<isize> {
-    let mut prog = prog.to_vec();
-    let mut inputs = inputs.iter().cloned();
-    let mut outputs = Vec::new();
-
-    let mut pc = 0;
+/// the last thing the amplifier outputs before halting
+fn amplifier(pool: &MachinePool, phase: isize, signal: isize) -> isize {
+    let mut m = pool.get();
+    m.push_input(phase);
+    m.push_input(signal);
 
+    let mut last = None;
     loop {
-        let (new_pc, state) = interpret(&mut prog, pc);
-        pc = new_pc;
-
-        match state {
-            State::Output(out) => outputs.push(out),
-            State::Input(dest) => {
-                let v = inputs.next().unwrap();
-                prog[dest] = v;
-            }
-            State::Default => {}
-            State::Halt => {
-                // dbg!(&outputs);
-                return outputs;
-            }
+        match m.step().unwrap() {
+            State::Output(o) => last = Some(o),
+            State::Halt => return last.unwrap(),
+            State::NeedInput => panic!("amplifier wants more than a phase and a signal"),
+            _ => {}
         }
     }
 }
 
-struct Machine {
-    pc: usize,
-    prog: Vec<isize>,
-    input: VecDeque<isize>,
-}
-
-impl Machine {
-    fn run(&mut self) -> State {
-        loop {
-            let (new_pc, state) = interpret(&mut self.prog, self.pc);
-            self.pc = new_pc;
-
-            match state {
-                State::Output(_) => return state,
-                State::Input(dest) => {
-                    let v = self.input.pop_front().unwrap();
-                    self.prog[dest] = v;
-                }
-                State::Default => {}
-                State::Halt => return state,
-            }
-        }
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_thrusters() {
+        let prog: &[isize] = &[
+            3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
+        ];
+        let pool = MachinePool::new(prog);
+        let best = search(&[0, 1, 2, 3, 4], 5, 4, |c| thrusters(&pool, c)).unwrap();
+        assert_eq!(vec![4, 3, 2, 1, 0], best.phases);
+        assert_eq!(43210, best.signal);
+
+        let prog: &[isize] = &[
+            3, 23, 3, 24, 1002, 24, 10, 24, 1002, 23, -1, 23, 101, 5, 23, 23, 1, 24, 23, 23, 4, 23,
+            99, 0, 0,
+        ];
+        let pool = MachinePool::new(prog);
+        let best = search(&[0, 1, 2, 3, 4], 5, 4, |c| thrusters(&pool, c)).unwrap();
+        assert_eq!(vec![0, 1, 2, 3, 4], best.phases);
+        assert_eq!(54321, best.signal);
     }
-}
-
-fn feedback(prog: &[isize], a: isize, b: isize, c: isize, d: isize, e: isize) -> isize {
-    let mut final_o = 0;
-
-    let mut thruster_a = Machine {
-        pc: 0,
-        prog: prog.to_vec(),
-        input: vec![a, 0].into(),
-    };
-    let mut thruster_b = Machine {
-        pc: 0,
-        prog: prog.to_vec(),
-        input: vec![b].into(),
-    };
-    let mut thruster_c = Machine {
-        pc: 0,
-        prog: prog.to_vec(),
-        input: vec![c].into(),
-    };
-    let mut thruster_d = Machine {
-        pc: 0,
-        prog: prog.to_vec(),
-        input: vec![d].into(),
-    };
-    let mut thruster_e = Machine {
-        pc: 0,
-        prog: prog.to_vec(),
-        input: vec![e].into(),
-    };
-
-    loop {
-        let s = thruster_a.run();
-        let b_in = match s {
-            State::Halt => return final_o,
-            State::Output(o) => o,
-            _ => unreachable!(),
-        };
-        thruster_b.input.push_back(b_in);
-
-        let s = thruster_b.run();
-        let c_in = match s {
-            State::Halt => return final_o,
-            State::Output(o) => o,
-            _ => unreachable!(),
-        };
-        thruster_c.input.push_back(c_in);
-
-        let s = thruster_c.run();
-        let d_in = match s {
-            State::Halt => return final_o,
-            State::Output(o) => o,
-            _ => unreachable!(),
-        };
-        thruster_d.input.push_back(d_in);
-
-        let s = thruster_d.run();
-        let e_in = match s {
-            State::Halt => return final_o,
-            State::Output(o) => o,
-            _ => unreachable!(),
-        };
-        thruster_e.input.push_back(e_in);
 
-        let s = thruster_e.run();
-        let a_in = match s {
-            State::Halt => return final_o,
-            State::Output(o) => {
-                final_o = o;
-                o
-            }
-            _ => unreachable!(),
-        };
-        thruster_a.input.push_back(a_in);
+    #[test]
+    fn test_ring() {
+        let prog: &[isize] = &[
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+        let best = search(&[5, 6, 7, 8, 9], 5, 4, |c| ring(prog, c).unwrap()).unwrap();
+        assert_eq!(vec![9, 8, 7, 6, 5], best.phases);
+        assert_eq!(139629729, best.signal);
     }
 }
@@ -0,0 +1,112 @@
+use itertools::Itertools;
+use std::sync::Mutex;
+use std::thread;
+
+/// how many permutations a worker pulls off the shared queue at a time
+const BATCH: usize = 256;
+
+/// phase setting with the strongest signal found by `search`
+#[derive(Debug, PartialEq, Clone)]
+pub struct Best {
+    pub phases: Vec<isize>,
+    pub signal: isize,
+}
+
+/// number of workers to use when the caller doesn't care
+pub fn default_threads() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// Evaluate every ordering of `stages` distinct values drawn from `phases`,
+/// spread across `threads` workers, and return the setting with the highest
+/// signal. Ties go to whichever setting comes first in permutation order, so
+/// the answer doesn't depend on scheduling.
+///
+/// Permutations are generated lazily and handed out in batches, so large
+/// chains never materialize the whole search space.
+pub fn search<F>(phases: &[isize], stages: usize, threads: usize, eval: F) -> Option<Best>
+where
+    F: Fn(&[isize]) -> isize + Sync,
+{
+    // every worker polls the queue once more after it runs dry, and
+    // `Permutations` isn't fused
+    let queue = Mutex::new(
+        phases
+            .iter()
+            .cloned()
+            .permutations(stages)
+            .fuse()
+            .enumerate(),
+    );
+
+    let found: Vec<Option<(usize, Best)>> = thread::scope(|s| {
+        let workers: Vec<_> = (0..threads.max(1))
+            .map(|_| s.spawn(|| worker(&queue, &eval)))
+            .collect();
+
+        workers.into_iter().map(|w| w.join().unwrap()).collect()
+    });
+
+    found
+        .into_iter()
+        .flatten()
+        .max_by(|(i, x), (j, y)| x.signal.cmp(&y.signal).then(j.cmp(i)))
+        .map(|(_, best)| best)
+}
+
+/// drain batches from the queue until it's empty, keeping the local best
+fn worker<I, F>(queue: &Mutex<I>, eval: &F) -> Option<(usize, Best)>
+where
+    I: Iterator<Item = (usize, Vec<isize>)>,
+    F: Fn(&[isize]) -> isize,
+{
+    let mut best: Option<(usize, Best)> = None;
+
+    loop {
+        let batch: Vec<_> = queue.lock().unwrap().by_ref().take(BATCH).collect();
+        if batch.is_empty() {
+            return best;
+        }
+
+        for (i, phases) in batch {
+            let signal = eval(&phases);
+            // indices only grow within a worker, so strict > keeps the earliest tie
+            if best.as_ref().is_none_or(|(_, b)| signal > b.signal) {
+                best = Some((i, Best { phases, signal }));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stages_and_ranges() {
+        let digits = |c: &[isize]| c.iter().fold(0, |acc, x| acc * 10 + x);
+
+        let best = search(&[0, 1, 2, 3, 4, 5], 3, 3, digits).unwrap();
+        assert_eq!(vec![5, 4, 3], best.phases);
+        assert_eq!(543, best.signal);
+
+        assert_eq!(None, search(&[1, 2], 3, 2, digits));
+    }
+
+    #[test]
+    fn test_ties_are_deterministic() {
+        let sum = |c: &[isize]| c.iter().sum();
+        for threads in 1..=8 {
+            let best = search(&[0, 1, 2, 3, 4, 5, 6], 4, threads, sum).unwrap();
+            assert_eq!(vec![3, 4, 5, 6], best.phases);
+        }
+
+        let flat = |_: &[isize]| 0;
+        for threads in 1..=8 {
+            let best = search(&[3, 1, 2], 3, threads, flat).unwrap();
+            assert_eq!(vec![3, 1, 2], best.phases);
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use intcode::machine::Error as Fault;
+use intcode::{Machine, State};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, SendError, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+/// how long a blocked machine waits before checking whether the network is stuck
+const POLL: Duration = Duration::from_millis(5);
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// every machine still running is waiting on input nobody is going to send
+    Deadlock,
+    /// wanted input after everything feeding it had halted
+    Disconnected,
+    /// the machine itself hit an error
+    Faulted(Fault),
+}
+
+/// Where a machine's output goes. Only values bound for another machine
+/// count as in flight: nothing in the network will ever read the rest.
+enum Output {
+    Discard,
+    Machine(Sender<isize>),
+    Outside(Sender<isize>),
+}
+
+/// a machine and its links to the rest of the network
+struct Node {
+    machine: Machine,
+    input: Receiver<isize>,
+    output: Output,
+}
+
+/// A way to feed a machine from outside the network. What's sent through it
+/// is counted like any value sent between machines, so deadlock detection
+/// knows it's on its way.
+#[derive(Clone)]
+pub struct Link {
+    tx: Sender<isize>,
+    monitor: Arc<Monitor>,
+}
+
+impl Link {
+    /// fails, like `Sender::send`, once the machine has stopped
+    pub fn send(&self, v: isize) -> Result<(), SendError<isize>> {
+        self.monitor.send(&self.tx, v)
+    }
+}
+
+#[derive(Default)]
+struct Counts {
+    live: usize,
+    blocked: usize,
+    in_flight: usize,
+    deadlock: bool,
+}
+
+/// Shared bookkeeping that lets a blocked machine tell a slow neighbour
+/// apart from one that will never send. The network is stuck once every
+/// live machine is blocked and no value sent to any of them is still
+/// sitting in a channel.
+#[derive(Default)]
+struct Monitor {
+    counts: Mutex<Counts>,
+}
+
+impl Monitor {
+    fn counts(&self) -> MutexGuard<'_, Counts> {
+        self.counts.lock().unwrap()
+    }
+
+    /// a machine halted or gave up, leaving whatever it never read
+    fn exit(&self, input: Receiver<isize>) {
+        let mut counts = self.counts();
+        counts.in_flight -= input.try_iter().count();
+        // from here on sending to it fails, and isn't counted
+        drop(input);
+        counts.live -= 1;
+    }
+
+    fn send(&self, tx: &Sender<isize>, v: isize) -> Result<(), SendError<isize>> {
+        // count it before it's visible to the receiver so there's no window
+        // where the network looks idle with a value in transit, and hold the
+        // lock so a machine leaving can't miss it
+        let mut counts = self.counts();
+        counts.in_flight += 1;
+        let sent = tx.send(v);
+        if sent.is_err() {
+            // whoever reads this has already halted
+            counts.in_flight -= 1;
+        }
+        sent
+    }
+
+    fn recv(&self, rx: &Receiver<isize>) -> Result<isize, Error> {
+        match rx.try_recv() {
+            Ok(v) => {
+                self.counts().in_flight -= 1;
+                return Ok(v);
+            }
+            Err(TryRecvError::Disconnected) => return Err(self.disconnected()),
+            Err(TryRecvError::Empty) => {}
+        }
+
+        self.counts().blocked += 1;
+        loop {
+            match rx.recv_timeout(POLL) {
+                Ok(v) => {
+                    let mut counts = self.counts();
+                    counts.blocked -= 1;
+                    counts.in_flight -= 1;
+                    return Ok(v);
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.counts().blocked -= 1;
+                    return Err(self.disconnected());
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    let mut counts = self.counts();
+                    if counts.deadlock || (counts.blocked == counts.live && counts.in_flight == 0) {
+                        counts.deadlock = true;
+                        counts.blocked -= 1;
+                        return Err(Error::Deadlock);
+                    }
+                }
+            }
+        }
+    }
+
+    /// once a deadlock is found the machines giving up drop their senders,
+    /// which shouldn't be mistaken for a normal halt downstream
+    fn disconnected(&self) -> Error {
+        if self.counts().deadlock {
+            Error::Deadlock
+        } else {
+            Error::Disconnected
+        }
+    }
+}
+
+/// Machines wired together by channels, each run on its own thread. Every
+/// value a machine reads comes through the network, from another machine
+/// or a `Link`, so deadlock detection sees all of them.
+pub struct Network {
+    monitor: Arc<Monitor>,
+    nodes: Vec<Node>,
+    /// the sending end of each machine's input, dropped once the network
+    /// runs so a machine nobody feeds sees the disconnect
+    inputs: Vec<Sender<isize>>,
+}
+
+impl Network {
+    /// the machines, none of them connected yet
+    pub fn new(machines: Vec<Machine>) -> Network {
+        let (inputs, nodes) = machines
+            .into_iter()
+            .map(|machine| {
+                let (tx, rx) = channel();
+                let node = Node {
+                    machine,
+                    input: rx,
+                    output: Output::Discard,
+                };
+                (tx, node)
+            })
+            .unzip();
+        Network {
+            monitor: Arc::default(),
+            nodes,
+            inputs,
+        }
+    }
+
+    /// feed what machine `from` outputs to machine `to`
+    pub fn connect(&mut self, from: usize, to: usize) {
+        self.nodes[from].output = Output::Machine(self.inputs[to].clone());
+    }
+
+    /// a way to feed machine `to` from outside
+    pub fn input(&self, to: usize) -> Link {
+        Link {
+            tx: self.inputs[to].clone(),
+            monitor: Arc::clone(&self.monitor),
+        }
+    }
+
+    /// what machine `from` outputs, to read outside the network
+    pub fn output(&mut self, from: usize) -> Receiver<isize> {
+        let (tx, rx) = channel();
+        self.nodes[from].output = Output::Outside(tx);
+        rx
+    }
+
+    /// Run every machine on its own thread until they've all halted or
+    /// given up, returning what each one output, in order.
+    pub fn run(self) -> Vec<Result<Vec<isize>, Error>> {
+        let Network {
+            monitor,
+            nodes,
+            inputs,
+        } = self;
+        drop(inputs);
+        // everyone has to be counted before anyone can decide the network is stuck
+        monitor.counts().live = nodes.len();
+
+        thread::scope(|s| {
+            let workers: Vec<_> = nodes
+                .into_iter()
+                .map(|node| {
+                    let monitor = &monitor;
+                    s.spawn(move || drive(node, monitor))
+                })
+                .collect();
+
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        })
+    }
+}
+
+fn drive(node: Node, monitor: &Monitor) -> Result<Vec<isize>, Error> {
+    let Node {
+        mut machine,
+        input,
+        output,
+    } = node;
+    let mut outputs = Vec::new();
+
+    let result = loop {
+        match machine.step() {
+            Ok(State::Output(o)) => {
+                match &output {
+                    Output::Discard => {}
+                    Output::Machine(tx) => {
+                        // nobody's left to read it, which is their business
+                        let _ = monitor.send(tx, o);
+                    }
+                    Output::Outside(tx) => {
+                        let _ = tx.send(o);
+                    }
+                }
+                outputs.push(o);
+            }
+            Ok(State::NeedInput) => match monitor.recv(&input) {
+                Ok(v) => machine.push_input(v),
+                Err(e) => break Err(e),
+            },
+            Ok(State::Halt) => break Ok(outputs),
+            Ok(_) => {}
+            Err(e) => break Err(Error::Faulted(e)),
+        }
+    };
+
+    // hang up before leaving so anyone downstream sees the disconnect
+    // rather than a network that looks stuck
+    drop(output);
+    monitor.exit(input);
+    result
+}
+
+/// The feedback loop as a ring of threads, one per amplifier, the last one
+/// feeding back into the first. Returns the last signal out of the final
+/// amplifier.
+pub fn ring(prog: &[isize], phases: &[isize]) -> Result<isize, Error> {
+    let machines = phases
+        .iter()
+        .enumerate()
+        // the first amplifier starts the signal off at 0
+        .map(|(i, &phase)| match i {
+            0 => Machine::new(prog, &[phase, 0]),
+            _ => Machine::new(prog, &[phase]),
+        })
+        .collect();
+    let mut network = Network::new(machines);
+    for i in 0..phases.len() {
+        network.connect(i, (i + 1) % phases.len());
+    }
+
+    let mut results = network.run().into_iter().collect::<Result<Vec<_>, _>>()?;
+
+    Ok(results.pop().and_then(|o| o.last().cloned()).unwrap_or(0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ring() {
+        let prog: &[isize] = &[
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+        assert_eq!(Ok(139629729), ring(prog, &[9, 8, 7, 6, 5]));
+
+        let prog: &[isize] = &[
+            3, 52, 1001, 52, -5, 52, 3, 53, 1, 52, 56, 54, 1007, 54, 5, 55, 1005, 55, 26, 1001, 54,
+            -5, 54, 1105, 1, 12, 1, 53, 54, 53, 1008, 54, 0, 55, 1001, 55, 1, 55, 2, 53, 55, 53, 4,
+            53, 1001, 56, -1, 56, 1005, 56, 6, 99, 0, 0, 0, 0, 10,
+        ];
+        assert_eq!(Ok(18216), ring(prog, &[9, 7, 8, 5, 6]));
+        assert_eq!(Ok(0), ring(prog, &[]));
+    }
+
+    #[test]
+    fn test_series_shuts_down_cleanly() {
+        // double whatever comes in, twice over
+        let prog: &[isize] = &[3, 0, 1002, 0, 2, 0, 4, 0, 99];
+        let mut network = Network::new(vec![Machine::new(prog, &[5]), Machine::new(prog, &[])]);
+        network.connect(0, 1);
+        let out = network.output(1);
+
+        assert_eq!(vec![Ok(vec![10]), Ok(vec![20])], network.run());
+        assert_eq!(vec![20], out.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_deadlock() {
+        // both want input from the other before sending anything
+        let prog: &[isize] = &[3, 0, 4, 0, 99];
+        let mut network = Network::new(vec![Machine::new(prog, &[]), Machine::new(prog, &[])]);
+        network.connect(0, 1);
+        network.connect(1, 0);
+
+        assert_eq!(
+            vec![Err(Error::Deadlock), Err(Error::Deadlock)],
+            network.run()
+        );
+    }
+
+    #[test]
+    fn test_deadlock_held_sender() {
+        // the link is still alive, so only deadlock detection can end this
+        let network = Network::new(vec![Machine::new(&[3, 0, 99], &[])]);
+        let _link = network.input(0);
+        assert_eq!(vec![Err(Error::Deadlock)], network.run());
+    }
+
+    #[test]
+    fn test_deadlock_after_halt() {
+        // the first halts without reading what it was sent, or what the
+        // second sends it before blocking on the third, which blocks on it
+        let prog: &[isize] = &[104, 1, 3, 0, 4, 0, 99];
+        let mut network = Network::new(vec![
+            Machine::new(&[99], &[]),
+            Machine::new(prog, &[]),
+            Machine::new(&[3, 0, 4, 0, 99], &[]),
+        ]);
+        network.input(0).send(5).unwrap();
+        network.connect(1, 0);
+        network.connect(2, 1);
+        let _link = network.input(2);
+
+        assert_eq!(
+            vec![Ok(vec![]), Err(Error::Deadlock), Err(Error::Deadlock)],
+            network.run()
+        );
+    }
+
+    #[test]
+    fn test_outside_values() {
+        // echo one value out of the network, then wait for another
+        let mut network = Network::new(vec![Machine::new(&[3, 0, 4, 0, 3, 0, 99], &[])]);
+        let link = network.input(0);
+        let out = network.output(0);
+        link.send(7).unwrap();
+
+        // what went in was read and what came out won't be, so neither
+        // keeps the network looking busy
+        assert_eq!(vec![Err(Error::Deadlock)], network.run());
+        assert_eq!(vec![7], out.iter().collect::<Vec<_>>());
+        assert!(link.send(8).is_err());
+    }
+
+    #[test]
+    fn test_fault() {
+        let network = Network::new(vec![Machine::new(&[98], &[])]);
+        assert_eq!(
+            vec![Err(Error::Faulted(Fault::InvalidOpcode(0, 98)))],
+            network.run()
+        );
+    }
+
+    #[test]
+    fn test_upstream_halted() {
+        let mut network = Network::new(vec![
+            Machine::new(&[99], &[]),
+            Machine::new(&[3, 0, 99], &[]),
+        ]);
+        network.connect(0, 1);
+
+        assert_eq!(vec![Ok(vec![]), Err(Error::Disconnected)], network.run());
+    }
+}
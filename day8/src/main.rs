@@ -46,7 +46,7 @@ fn part2(input: &[u8]) {
     let mut o = std::io::stdout();
     for (i, x) in img.iter().enumerate() {
         if i % 25 == 0 {
-            write!(o, "\n").unwrap();
+            writeln!(o).unwrap();
         }
 
         write!(
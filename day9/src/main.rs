@@ -1,11 +1,8 @@
-mod interpreter;
-use crate::interpreter::run_to_completion;
+use intcode::machine::run_to_completion;
+use intcode::Program;
 
 fn main() {
-    let prog: Vec<isize> = include_str!("input")
-        .split(",")
-        .filter_map(|x| x.parse::<isize>().ok())
-        .collect();
+    let prog = Program::load_arg_or(concat!(env!("CARGO_MANIFEST_DIR"), "/src/input"));
 
     let part1 = run_to_completion(&prog, &[1]);
     println!("part1: {:?}", part1);
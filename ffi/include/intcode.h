@@ -0,0 +1,162 @@
+/* The C interface to the Intcode machine, generated by cbindgen from
+ * ffi/src/lib.rs. Regenerate it with `INTCODE_BLESS=1 cargo test -p intcode-ffi`. */
+
+#ifndef INTCODE_H
+#define INTCODE_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * How many cells past the end of the program memory may grow, whether the
+ * program writes there or `intcode_write` does. Any further is an error
+ * rather than an allocation big enough to take the host down.
+ */
+#define INTCODE_MAX_GROWTH (1 << 20)
+
+/**
+ * Why `intcode_run` or `intcode_write` returned.
+ */
+typedef enum IntcodeStatus {
+  /**
+   * It produced a value, read with `intcode_output`.
+   */
+  INTCODE_STATUS_OUTPUT,
+  /**
+   * It can't go on until some input is pushed.
+   */
+  INTCODE_STATUS_NEED_INPUT,
+  INTCODE_STATUS_HALTED,
+  /**
+   * It stopped on a runtime error, described by `intcode_error`.
+   */
+  INTCODE_STATUS_ERROR,
+  /**
+   * It ran the number of steps it was given.
+   */
+  INTCODE_STATUS_EXHAUSTED,
+  /**
+   * `intcode_write` or `intcode_push_input` did as it was asked.
+   */
+  INTCODE_STATUS_OK,
+} IntcodeStatus;
+
+/**
+ * A machine and what it last produced; opaque to C.
+ */
+typedef struct IntcodeMachine IntcodeMachine;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * A machine loaded with the `len` values at `prog`, which the caller
+ * still owns, or null if it couldn't be made, as when a value is too big
+ * for the platform's word. Free it with `intcode_free`.
+ *
+ * # Safety
+ *
+ * `prog` must point to `len` values, or may be null if `len` is 0.
+ */
+struct IntcodeMachine *intcode_new(const int64_t *prog, size_t len);
+
+/**
+ * Free a machine. Null is ignored.
+ *
+ * # Safety
+ *
+ * `m` must have come from `intcode_new` and not been freed already.
+ */
+void intcode_free(struct IntcodeMachine *m);
+
+/**
+ * Queue a value for the next input instruction. Returns
+ * `INTCODE_STATUS_OK`, or `INTCODE_STATUS_ERROR` if `value` is too big for
+ * the platform's word.
+ *
+ * # Safety
+ *
+ * `m` must be a live machine from `intcode_new`.
+ */
+enum IntcodeStatus intcode_push_input(struct IntcodeMachine *m, int64_t value);
+
+/**
+ * Run until the machine outputs, needs input, halts or fails, or until
+ * it's run `max_steps` instructions if that isn't 0. Running it again
+ * carries on where it stopped.
+ *
+ * # Safety
+ *
+ * `m` must be a live machine from `intcode_new`.
+ */
+enum IntcodeStatus intcode_run(struct IntcodeMachine *m, uint64_t max_steps);
+
+/**
+ * The value from the last run that returned `INTCODE_STATUS_OUTPUT`.
+ *
+ * # Safety
+ *
+ * `m` must be a live machine from `intcode_new`.
+ */
+int64_t intcode_output(const struct IntcodeMachine *m);
+
+/**
+ * What went wrong in the last run or write, if it returned
+ * `INTCODE_STATUS_ERROR`, or null. The string belongs to the machine and
+ * lasts until it's next run, written to or freed.
+ *
+ * # Safety
+ *
+ * `m` must be a live machine from `intcode_new`.
+ */
+const char *intcode_error(const struct IntcodeMachine *m);
+
+/**
+ * The value at `addr`; memory past the end reads as zero.
+ *
+ * # Safety
+ *
+ * `m` must be a live machine from `intcode_new`.
+ */
+int64_t intcode_read(const struct IntcodeMachine *m, size_t addr);
+
+/**
+ * Write `value` at `addr`, growing memory to fit as long as that's no more
+ * than `INTCODE_MAX_GROWTH` cells past the end of the program. Returns
+ * `INTCODE_STATUS_OK`, or `INTCODE_STATUS_ERROR` if `addr` is too far past
+ * the end or `value` is too big for the platform's word.
+ *
+ * # Safety
+ *
+ * `m` must be a live machine from `intcode_new`.
+ */
+enum IntcodeStatus intcode_write(struct IntcodeMachine *m, size_t addr, int64_t value);
+
+/**
+ * How many cells of memory there are, including any grown past the end
+ * of the program.
+ *
+ * # Safety
+ *
+ * `m` must be a live machine from `intcode_new`.
+ */
+size_t intcode_memory_len(const struct IntcodeMachine *m);
+
+/**
+ * Where the next instruction is.
+ *
+ * # Safety
+ *
+ * `m` must be a live machine from `intcode_new`.
+ */
+size_t intcode_pc(const struct IntcodeMachine *m);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* INTCODE_H */
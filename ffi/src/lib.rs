@@ -0,0 +1,387 @@
+//! A C interface to the Intcode `Machine`, for embedding it in harnesses
+//! that aren't written in Rust. Build the crate for `libintcode_ffi.a` or
+//! `libintcode_ffi.so` and include `include/intcode.h`:
+//!
+//! ```c
+//! IntcodeMachine *m = intcode_new(prog, len);
+//! intcode_push_input(m, 5);
+//! while (intcode_run(m, 0) == INTCODE_STATUS_OUTPUT)
+//!     printf("%lld\n", (long long) intcode_output(m));
+//! intcode_free(m);
+//! ```
+//!
+//! The header is generated by cbindgen from this file. `cargo test` fails
+//! when it's out of date; `INTCODE_BLESS=1 cargo test -p intcode-ffi`
+//! writes it afresh.
+
+use intcode::machine::Error as Fault;
+use intcode::{Machine, State};
+use std::any::Any;
+use std::convert::TryFrom;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+/// How many cells past the end of the program memory may grow, whether the
+/// program writes there or `intcode_write` does. Any further is an error
+/// rather than an allocation big enough to take the host down.
+pub const INTCODE_MAX_GROWTH: usize = 1 << 20;
+
+/// A machine and what it last produced; opaque to C.
+pub struct IntcodeMachine {
+    machine: Machine,
+    output: i64,
+    /// why the last run failed, if it did
+    error: Option<CString>,
+}
+
+/// Why `intcode_run` or `intcode_write` returned.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntcodeStatus {
+    /// It produced a value, read with `intcode_output`.
+    Output,
+    /// It can't go on until some input is pushed.
+    NeedInput,
+    Halted,
+    /// It stopped on a runtime error, described by `intcode_error`.
+    Error,
+    /// It ran the number of steps it was given.
+    Exhausted,
+    /// `intcode_write` or `intcode_push_input` did as it was asked.
+    Ok,
+}
+
+impl IntcodeMachine {
+    /// Fail with `error`, described by `intcode_error`.
+    fn fail(&mut self, error: String) -> IntcodeStatus {
+        // error messages are plain text, never with a NUL
+        self.error = CString::new(error).ok();
+        IntcodeStatus::Error
+    }
+
+    /// `f`'s status, or an error if it panics rather than unwinding into C.
+    fn guarded<F>(&mut self, f: F) -> IntcodeStatus
+    where
+        F: FnOnce(&mut IntcodeMachine) -> IntcodeStatus,
+    {
+        self.error = None;
+        match panic::catch_unwind(AssertUnwindSafe(|| f(self))) {
+            Ok(status) => status,
+            Err(e) => self.fail(format!("internal error: {}", panic_message(e))),
+        }
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_default()
+}
+
+/// A machine loaded with the `len` values at `prog`, which the caller
+/// still owns, or null if it couldn't be made, as when a value is too big
+/// for the platform's word. Free it with `intcode_free`.
+///
+/// # Safety
+///
+/// `prog` must point to `len` values, or may be null if `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_new(prog: *const i64, len: usize) -> *mut IntcodeMachine {
+    if len > 0 && prog.is_null() {
+        return ptr::null_mut();
+    }
+    let new = || {
+        let prog: Vec<isize> = match len {
+            0 => Vec::new(),
+            _ => match slice::from_raw_parts(prog, len)
+                .iter()
+                .map(|&v| isize::try_from(v))
+                .collect()
+            {
+                Ok(prog) => prog,
+                Err(_) => return ptr::null_mut(),
+            },
+        };
+        let mut machine = Machine::new(&prog, &[]);
+        machine.memory_limit(len.saturating_add(INTCODE_MAX_GROWTH));
+        Box::into_raw(Box::new(IntcodeMachine {
+            machine,
+            output: 0,
+            error: None,
+        }))
+    };
+    panic::catch_unwind(new).unwrap_or(ptr::null_mut())
+}
+
+/// Free a machine. Null is ignored.
+///
+/// # Safety
+///
+/// `m` must have come from `intcode_new` and not been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_free(m: *mut IntcodeMachine) {
+    if !m.is_null() {
+        drop(Box::from_raw(m));
+    }
+}
+
+/// Queue a value for the next input instruction. Returns
+/// `INTCODE_STATUS_OK`, or `INTCODE_STATUS_ERROR` if `value` is too big for
+/// the platform's word.
+///
+/// # Safety
+///
+/// `m` must be a live machine from `intcode_new`.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_push_input(m: *mut IntcodeMachine, value: i64) -> IntcodeStatus {
+    (*m).guarded(|m| match word(value) {
+        Ok(v) => {
+            m.machine.push_input(v);
+            IntcodeStatus::Ok
+        }
+        Err(e) => m.fail(e),
+    })
+}
+
+/// `value` as a machine word, which is narrower on 32-bit targets
+fn word(value: i64) -> Result<isize, String> {
+    isize::try_from(value).map_err(|_| format!("{} is too big for a word", value))
+}
+
+/// Run until the machine outputs, needs input, halts or fails, or until
+/// it's run `max_steps` instructions if that isn't 0. Running it again
+/// carries on where it stopped.
+///
+/// # Safety
+///
+/// `m` must be a live machine from `intcode_new`.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_run(m: *mut IntcodeMachine, max_steps: u64) -> IntcodeStatus {
+    (*m).guarded(|m| {
+        let mut steps = 0;
+        while max_steps == 0 || steps < max_steps {
+            match m.machine.step() {
+                Ok(State::Output(o)) => {
+                    m.output = o as i64;
+                    return IntcodeStatus::Output;
+                }
+                Ok(State::NeedInput) => return IntcodeStatus::NeedInput,
+                Ok(State::Halt) => return IntcodeStatus::Halted,
+                Ok(_) => {}
+                Err(e) => return m.fail(e.to_string()),
+            }
+            steps += 1;
+        }
+        IntcodeStatus::Exhausted
+    })
+}
+
+/// The value from the last run that returned `INTCODE_STATUS_OUTPUT`.
+///
+/// # Safety
+///
+/// `m` must be a live machine from `intcode_new`.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_output(m: *const IntcodeMachine) -> i64 {
+    (*m).output
+}
+
+/// What went wrong in the last run or write, if it returned
+/// `INTCODE_STATUS_ERROR`, or null. The string belongs to the machine and
+/// lasts until it's next run, written to or freed.
+///
+/// # Safety
+///
+/// `m` must be a live machine from `intcode_new`.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_error(m: *const IntcodeMachine) -> *const c_char {
+    (*m).error.as_ref().map_or(ptr::null(), |e| e.as_ptr())
+}
+
+/// The value at `addr`; memory past the end reads as zero.
+///
+/// # Safety
+///
+/// `m` must be a live machine from `intcode_new`.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_read(m: *const IntcodeMachine, addr: usize) -> i64 {
+    (*m).machine.get(addr) as i64
+}
+
+/// Write `value` at `addr`, growing memory to fit as long as that's no more
+/// than `INTCODE_MAX_GROWTH` cells past the end of the program. Returns
+/// `INTCODE_STATUS_OK`, or `INTCODE_STATUS_ERROR` if `addr` is too far past
+/// the end or `value` is too big for the platform's word.
+///
+/// # Safety
+///
+/// `m` must be a live machine from `intcode_new`.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_write(
+    m: *mut IntcodeMachine,
+    addr: usize,
+    value: i64,
+) -> IntcodeStatus {
+    (*m).guarded(|m| {
+        let value = match word(value) {
+            Ok(value) => value,
+            Err(e) => return m.fail(e),
+        };
+        let stored = isize::try_from(addr)
+            .map_err(|_| Fault::OutOfMemory(m.machine.pc()))
+            .and_then(|addr| m.machine.store(addr, value));
+        match stored {
+            Ok(()) => IntcodeStatus::Ok,
+            Err(Fault::OutOfMemory(_)) => m.fail(format!(
+                "address {} is too far past the end of memory",
+                addr
+            )),
+            Err(e) => m.fail(e.to_string()),
+        }
+    })
+}
+
+/// How many cells of memory there are, including any grown past the end
+/// of the program.
+///
+/// # Safety
+///
+/// `m` must be a live machine from `intcode_new`.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_memory_len(m: *const IntcodeMachine) -> usize {
+    (*m).machine.memory().len()
+}
+
+/// Where the next instruction is.
+///
+/// # Safety
+///
+/// `m` must be a live machine from `intcode_new`.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_pc(m: *const IntcodeMachine) -> usize {
+    (*m).machine.pc()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ffi::CStr;
+    use std::fs;
+
+    fn new(prog: &[i64]) -> *mut IntcodeMachine {
+        unsafe { intcode_new(prog.as_ptr(), prog.len()) }
+    }
+
+    #[test]
+    fn test_run() {
+        // echo inputs until a 0
+        let m = new(&[3, 11, 1006, 11, 10, 4, 11, 1105, 1, 0, 99, 0]);
+        unsafe {
+            assert_eq!(IntcodeStatus::NeedInput, intcode_run(m, 0));
+            assert_eq!(IntcodeStatus::Ok, intcode_push_input(m, 5));
+            assert_eq!(IntcodeStatus::Ok, intcode_push_input(m, -7));
+            assert_eq!(IntcodeStatus::Output, intcode_run(m, 0));
+            assert_eq!(5, intcode_output(m));
+            assert_eq!(IntcodeStatus::Output, intcode_run(m, 0));
+            assert_eq!(-7, intcode_output(m));
+            assert_eq!(IntcodeStatus::NeedInput, intcode_run(m, 0));
+
+            assert_eq!(IntcodeStatus::Ok, intcode_push_input(m, 0));
+            assert_eq!(IntcodeStatus::Halted, intcode_run(m, 0));
+            assert_eq!(IntcodeStatus::Halted, intcode_run(m, 0));
+            assert_eq!(10, intcode_pc(m));
+            assert!(intcode_error(m).is_null());
+            intcode_free(m);
+        }
+    }
+
+    #[test]
+    fn test_memory() {
+        let m = new(&[1101, 2, 3, 5, 99, 0]);
+        unsafe {
+            assert_eq!(IntcodeStatus::Halted, intcode_run(m, 0));
+            assert_eq!(5, intcode_read(m, 5));
+            assert_eq!(0, intcode_read(m, 5000));
+            assert_eq!(6, intcode_memory_len(m));
+
+            assert_eq!(IntcodeStatus::Ok, intcode_write(m, 100, i64::MIN));
+            assert_eq!(i64::MIN, intcode_read(m, 100));
+            assert_eq!(101, intcode_memory_len(m));
+
+            assert_eq!(IntcodeStatus::Error, intcode_write(m, usize::MAX, 1));
+            let error = CStr::from_ptr(intcode_error(m)).to_str().unwrap();
+            assert_eq!(
+                format!("address {} is too far past the end of memory", usize::MAX),
+                error
+            );
+            assert_eq!(101, intcode_memory_len(m));
+            intcode_free(m);
+        }
+    }
+
+    #[test]
+    fn test_errors_and_limits() {
+        unsafe {
+            let m = new(&[42]);
+            assert_eq!(IntcodeStatus::Error, intcode_run(m, 0));
+            let error = CStr::from_ptr(intcode_error(m)).to_str().unwrap();
+            assert_eq!("invalid opcode 42 at pc 0", error);
+            intcode_free(m);
+
+            // a loop forever
+            let m = new(&[1105, 1, 0]);
+            assert_eq!(IntcodeStatus::Exhausted, intcode_run(m, 10));
+            intcode_free(m);
+
+            // the program is held to the same limit as intcode_write
+            let m = new(&[1101, 1, 1, 1 << 61, 99]);
+            assert_eq!(IntcodeStatus::Error, intcode_run(m, 0));
+            let error = CStr::from_ptr(intcode_error(m)).to_str().unwrap();
+            assert_eq!("memory limit exceeded at pc 0", error);
+            intcode_free(m);
+
+            // a panic comes back as an error rather than unwinding into C
+            let m = new(&[99]);
+            let status = (*m).guarded(|_| panic!("oops"));
+            assert_eq!(IntcodeStatus::Error, status);
+            let error = CStr::from_ptr(intcode_error(m)).to_str().unwrap();
+            assert_eq!("internal error: oops", error);
+            intcode_free(m);
+
+            assert!(intcode_new(ptr::null(), 3).is_null());
+            let m = intcode_new(ptr::null(), 0);
+            assert_eq!(0, intcode_memory_len(m));
+            intcode_free(m);
+            intcode_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_header_is_current() {
+        let dir = env!("CARGO_MANIFEST_DIR");
+        let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", dir)).unwrap();
+        let mut header = Vec::new();
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(format!("{}/src/lib.rs", dir))
+            .generate()
+            .unwrap()
+            .write(&mut header);
+
+        let path = format!("{}/include/intcode.h", dir);
+        if std::env::var_os("INTCODE_BLESS").is_some() {
+            fs::write(&path, &header).unwrap();
+        }
+        let current = fs::read(&path).unwrap_or_default();
+        assert!(
+            header == current,
+            "include/intcode.h is out of date; \
+             regenerate it with `INTCODE_BLESS=1 cargo test -p intcode-ffi`"
+        );
+    }
+}
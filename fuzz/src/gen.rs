@@ -0,0 +1,209 @@
+pub use intcode::conformance::Subset;
+
+/// xorshift64*, plenty for picking opcodes without pulling in a dependency
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // an all-zero state never leaves zero
+        Rng(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// uniform-ish in 0..n
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// uniform-ish in lo..=hi
+    pub fn range(&mut self, lo: isize, hi: isize) -> isize {
+        lo + self.below((hi - lo + 1) as usize) as isize
+    }
+
+    pub fn pick<T: Copy>(&mut self, xs: &[T]) -> T {
+        xs[self.below(xs.len())]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    pub prog: Vec<isize>,
+    pub inputs: Vec<isize>,
+}
+
+const MAX_INSTRUCTIONS: usize = 12;
+const DATA_CELLS: usize = 8;
+
+/// Generate a program that's valid on every implementation in `subset`.
+///
+/// Code comes first, then a halt, then a small data region. Reads may come
+/// from anywhere in the image but writes only ever land in the data region,
+/// and jumps only go forward to an instruction boundary, so every program
+/// halts, never touches memory outside the image and every implementation
+/// has to agree on it.
+///
+/// The relative base only moves by small steps, and since any of them may
+/// be jumped over the generator keeps the range it could be in. Relative
+/// parameters are only used where every base in that range keeps them in
+/// bounds.
+pub fn generate(rng: &mut Rng, subset: Subset) -> Case {
+    let ops: &[isize] = match subset {
+        Subset::Arithmetic => &[1, 2],
+        Subset::Day5 => &[1, 2, 3, 4, 5, 6, 7, 8],
+        Subset::Day9 => &[1, 2, 3, 4, 5, 6, 7, 8, 9],
+    };
+
+    let count = 1 + rng.below(MAX_INSTRUCTIONS);
+    let ops: Vec<isize> = (0..count).map(|_| rng.pick(ops)).collect();
+
+    let mut starts = Vec::with_capacity(count + 1);
+    let mut code_len = 0;
+    for &op in &ops {
+        starts.push(code_len);
+        code_len += width(op);
+    }
+    // the halt
+    starts.push(code_len);
+    let data = code_len + 1;
+    let len = data + DATA_CELLS;
+
+    let (lo, hi) = match subset {
+        Subset::Arithmetic => (0, 9),
+        _ => (-9, 9),
+    };
+
+    let mut prog = Vec::with_capacity(len);
+    let mut inputs = Vec::new();
+    // the lowest and highest the relative base could be
+    let mut base = (0, 0);
+    for (i, &op) in ops.iter().enumerate() {
+        // a relative parameter for an address in lo..hi from every base, if
+        // there is one
+        let relative = |rng: &mut Rng, lo: usize, hi: usize| -> Option<isize> {
+            let (from, to) = (lo as isize - base.0, hi as isize - 1 - base.1);
+            if subset != Subset::Day9 || from > to || rng.below(3) != 0 {
+                return None;
+            }
+            Some(rng.range(from, to))
+        };
+        let read = |rng: &mut Rng| -> (isize, isize) {
+            if let Some(raw) = relative(rng, 0, len) {
+                (2, raw)
+            } else if subset != Subset::Arithmetic && rng.below(2) == 0 {
+                (1, rng.range(-99, 99))
+            } else {
+                (0, rng.below(len) as isize)
+            }
+        };
+        let write = |rng: &mut Rng| -> (isize, isize) {
+            match relative(rng, data, len) {
+                Some(raw) => (2, raw),
+                None => (0, (data + rng.below(DATA_CELLS)) as isize),
+            }
+        };
+
+        match op {
+            1 | 2 | 7 | 8 => {
+                let (m1, p1) = read(rng);
+                let (m2, p2) = read(rng);
+                let (m3, p3) = write(rng);
+                prog.extend(&[op + 100 * m1 + 1000 * m2 + 10000 * m3, p1, p2, p3]);
+            }
+            3 => {
+                let (m1, p1) = write(rng);
+                prog.extend(&[op + 100 * m1, p1]);
+                inputs.push(rng.range(lo, hi));
+            }
+            4 => {
+                let (m1, p1) = read(rng);
+                prog.extend(&[op + 100 * m1, p1]);
+            }
+            5 | 6 => {
+                let (m1, p1) = read(rng);
+                let target = starts[i + 1 + rng.below(starts.len() - i - 1)];
+                prog.extend(&[op + 100 * m1 + 1000, p1, target as isize]);
+            }
+            9 => {
+                let step = rng.range(-3, 3);
+                prog.extend(&[109, step]);
+                base = (base.0.min(base.0 + step), base.1.max(base.1 + step));
+            }
+            _ => unreachable!(),
+        }
+    }
+    prog.push(99);
+    prog.extend((0..DATA_CELLS).map(|_| rng.range(lo, hi)));
+
+    Case { prog, inputs }
+}
+
+/// how many cells an instruction takes up
+fn width(op: isize) -> usize {
+    match op {
+        1 | 2 | 7 | 8 => 4,
+        5 | 6 => 3,
+        3 | 4 | 9 => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_arithmetic_is_day2_shaped() {
+        let mut rng = Rng::new(7);
+        for _ in 0..500 {
+            let case = generate(&mut rng, Subset::Arithmetic);
+            assert!(case.inputs.is_empty());
+            assert!(case.prog.iter().all(|&x| x >= 0));
+
+            let mut pc = 0;
+            while case.prog[pc] != 99 {
+                assert!(case.prog[pc] == 1 || case.prog[pc] == 2);
+                pc += 4;
+            }
+        }
+    }
+
+    #[test]
+    fn test_one_input_per_read() {
+        let mut rng = Rng::new(11);
+        for _ in 0..500 {
+            let case = generate(&mut rng, Subset::Day5);
+            let mut pc = 0;
+            let mut reads = 0;
+            while case.prog[pc] != 99 {
+                if case.prog[pc] == 3 {
+                    reads += 1;
+                }
+                pc += width(case.prog[pc] % 100);
+            }
+            assert_eq!(reads, case.inputs.len());
+        }
+    }
+
+    #[test]
+    fn test_day9_stays_in_bounds() {
+        use intcode::{Machine, State};
+
+        let mut rng = Rng::new(9);
+        let mut relative = 0;
+        for _ in 0..500 {
+            let case = generate(&mut rng, Subset::Day9);
+            relative += case.prog.iter().filter(|&&x| x / 100 % 10 == 2).count();
+
+            let mut m = Machine::new(&case.prog, &case.inputs);
+            while m.step().unwrap() != State::Halt {}
+            assert_eq!(case.prog.len(), m.memory().len(), "{:?}", case);
+        }
+        assert!(relative > 0);
+    }
+}
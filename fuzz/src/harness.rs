@@ -0,0 +1,272 @@
+use crate::gen::{Case, Subset};
+use intcode::conformance::{panic_message, step_through, Step, Stop};
+use std::convert::TryFrom;
+use std::panic::{self, AssertUnwindSafe};
+
+/// more steps than any generated program can take, since jumps only go forward
+const BUDGET: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Halted {
+        /// where it halted, for the implementations that say
+        pc: Option<usize>,
+        outputs: Vec<isize>,
+        memory: Vec<isize>,
+    },
+    /// asked for more input than the case provides
+    Starved,
+    /// still running when its budget of steps ran out
+    Runaway,
+    /// stopped with an error rather than a panic
+    Faulted(String),
+    Panicked(String),
+}
+
+impl Outcome {
+    fn agrees(&self, other: &Outcome) -> bool {
+        match (self, other) {
+            (
+                Outcome::Halted {
+                    pc: pc_a,
+                    outputs: out_a,
+                    memory: mem_a,
+                },
+                Outcome::Halted {
+                    pc: pc_b,
+                    outputs: out_b,
+                    memory: mem_b,
+                },
+            ) => {
+                out_a == out_b
+                    && mem_a == mem_b
+                    && (pc_a.is_none() || pc_b.is_none() || pc_a == pc_b)
+            }
+            (a, b) => a == b,
+        }
+    }
+
+    fn overflowed(&self) -> bool {
+        match self {
+            Outcome::Panicked(msg) | Outcome::Faulted(msg) => msg.contains("overflow"),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    Agree,
+    /// something overflowed; day2 works in usize and the others in isize so
+    /// they're allowed to disagree about where
+    Skipped,
+    Diverged(Vec<(&'static str, Outcome)>),
+}
+
+/// runs a case for at most the given number of steps
+pub type Implementation = fn(&Case, usize) -> Outcome;
+
+/// every implementation that understands programs from `subset`
+pub fn implementations(subset: Subset) -> Vec<(&'static str, Implementation)> {
+    let mut impls: Vec<(&'static str, Implementation)> = Vec::new();
+    if subset == Subset::Arithmetic {
+        impls.push(("day2", run_day2));
+    }
+    if subset <= Subset::Day5 {
+        impls.push(("day5", run_day5));
+        impls.push(("day7", run_day7));
+    }
+    impls.push(("intcode", run_intcode));
+    impls
+}
+
+/// run the case on every implementation that understands it and compare
+pub fn check(case: &Case, subset: Subset) -> Verdict {
+    let results: Vec<(&'static str, Outcome)> = implementations(subset)
+        .into_iter()
+        .map(|(name, run)| (name, guarded(run, case, BUDGET)))
+        .collect();
+
+    if results.iter().any(|(_, o)| o.overflowed()) {
+        return Verdict::Skipped;
+    }
+
+    let (_, first) = &results[0];
+    if results.iter().all(|(_, o)| o.agrees(first)) {
+        Verdict::Agree
+    } else {
+        Verdict::Diverged(results)
+    }
+}
+
+fn guarded(run: Implementation, case: &Case, budget: usize) -> Outcome {
+    panic::catch_unwind(AssertUnwindSafe(|| run(case, budget)))
+        .unwrap_or_else(|e| Outcome::Panicked(panic_message(e)))
+}
+
+/// day2 has no budget, as its programs can't jump
+fn run_day2(case: &Case, _budget: usize) -> Outcome {
+    let mut prog: Vec<usize> = case
+        .prog
+        .iter()
+        .map(|&x| usize::try_from(x).unwrap())
+        .collect();
+    day2::execute(&mut prog);
+
+    Outcome::Halted {
+        pc: None,
+        outputs: Vec::new(),
+        memory: prog.iter().map(|&x| isize::try_from(x).unwrap()).collect(),
+    }
+}
+
+fn run_day5(case: &Case, budget: usize) -> Outcome {
+    stepped(day5::interpreter::interpret, case, budget)
+}
+
+fn run_day7(case: &Case, budget: usize) -> Outcome {
+    stepped(day7::interpreter::interpret, case, budget)
+}
+
+/// run one of the interpreters that return (pc, state) one step at a time
+fn stepped<S: Into<Step>>(
+    interpret: fn(&mut [isize], usize) -> (usize, S),
+    case: &Case,
+    budget: usize,
+) -> Outcome {
+    match step_through(interpret, &case.prog, &case.inputs, budget) {
+        Ok((pc, run)) => Outcome::Halted {
+            pc: Some(pc),
+            outputs: run.outputs,
+            memory: run.memory,
+        },
+        Err(Stop::Starved) => Outcome::Starved,
+        Err(Stop::Runaway) => Outcome::Runaway,
+        Err(Stop::Panicked(msg)) => Outcome::Panicked(msg),
+    }
+}
+
+fn run_intcode(case: &Case, budget: usize) -> Outcome {
+    use intcode::{Machine, State};
+
+    let mut m = Machine::new(&case.prog, &case.inputs);
+    let mut outputs = Vec::new();
+
+    for _ in 0..budget {
+        match m.step() {
+            Ok(State::Output(o)) => outputs.push(o),
+            Ok(State::NeedInput) => return Outcome::Starved,
+            Ok(State::Halt) => {
+                return Outcome::Halted {
+                    pc: Some(m.pc()),
+                    outputs,
+                    memory: m.memory().to_vec(),
+                }
+            }
+            Ok(_) => {}
+            Err(e) => return Outcome::Faulted(e.to_string()),
+        }
+    }
+
+    Outcome::Runaway
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gen::{generate, Rng};
+
+    #[test]
+    fn test_no_divergence() {
+        let mut rng = Rng::new(2019);
+        for subset in &[Subset::Arithmetic, Subset::Day5] {
+            for _ in 0..2000 {
+                let case = generate(&mut rng, *subset);
+                if let Verdict::Diverged(results) = check(&case, *subset) {
+                    panic!("{:?} {:?}", case, results);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_optimizer_preserves_behaviour() {
+        use intcode::optimize::{optimize, verify};
+
+        let mut rng = Rng::new(38);
+        let (mut optimized, mut improved) = (0, 0);
+        for _ in 0..2000 {
+            let case = generate(&mut rng, Subset::Day5);
+            if let Ok((prog, report)) = optimize(&case.prog) {
+                optimized += 1;
+                if report.saved > 0 {
+                    improved += 1;
+                }
+                if let Err(mismatch) = verify(&case.prog, &prog, &case.inputs, BUDGET) {
+                    panic!("{:?} {:?} {}", case, prog, mismatch);
+                }
+            }
+        }
+        // generated programs read their own code but never write to it, and
+        // only some have anything to jump over
+        assert!(optimized > 1000 && improved > 0);
+    }
+
+    #[test]
+    fn test_decompiler_copes_with_anything() {
+        use intcode::decompile::decompile;
+
+        let mut rng = Rng::new(39);
+        for _ in 0..2000 {
+            let mut case = generate(&mut rng, Subset::Day9);
+            let text = decompile(&case.prog).to_string();
+            assert!(text.starts_with("fn main("), "{:?}", case);
+
+            // and once scrambled, so jumps land anywhere
+            let len = case.prog.len() as isize;
+            for _ in 0..4 {
+                let at = rng.below(case.prog.len());
+                case.prog[at] = rng.range(-2, len + 2);
+            }
+            let text = decompile(&case.prog).to_string();
+            assert!(text.starts_with("fn main("), "{:?}", case);
+        }
+    }
+
+    #[test]
+    fn test_input_destination() {
+        // the store has to go to parameter 1's address, not wherever the
+        // cell after the next instruction's first parameter points
+        let case = Case {
+            prog: vec![3, 9, 101, 1, 9, 10, 4, 10, 99, 0, 0],
+            inputs: vec![41],
+        };
+        assert_eq!(Verdict::Agree, check(&case, Subset::Day5));
+        assert_eq!(
+            Outcome::Halted {
+                pc: Some(8),
+                outputs: vec![42],
+                memory: vec![3, 9, 101, 1, 9, 10, 4, 10, 99, 41, 42],
+            },
+            run_intcode(&case, BUDGET)
+        );
+    }
+
+    #[test]
+    fn test_reports_divergence() {
+        // days 5 and 7 panic writing past the end of the image, intcode grows
+        // memory to fit
+        let case = Case {
+            prog: vec![1101, 1, 1, 7, 99],
+            inputs: vec![],
+        };
+        match check(&case, Subset::Day5) {
+            Verdict::Diverged(results) => {
+                assert!(matches!(results[0], ("day5", Outcome::Panicked(_))));
+                assert!(matches!(results[1], ("day7", Outcome::Panicked(_))));
+                assert!(matches!(results[2], ("intcode", Outcome::Halted { .. })));
+            }
+            v => panic!("{:?}", v),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use fuzz::gen::{generate, Rng, Subset};
+use fuzz::harness::{check, Verdict};
+
+/// Differential fuzzing across the Intcode interpreters.
+///
+/// usage: fuzz [iterations] [seed]
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let iterations: usize = args.next().map_or(10_000, |x| x.parse().unwrap());
+    let seed: u64 = args.next().map_or(2019, |x| x.parse().unwrap());
+
+    // the interpreters report trouble by panicking, which is caught and
+    // compared, so don't spray every one of them over stderr
+    std::panic::set_hook(Box::new(|_| {}));
+
+    let mut rng = Rng::new(seed);
+    let mut skipped = 0;
+    let mut diverged = 0;
+
+    for i in 0..iterations {
+        let subset = if i % 2 == 0 {
+            Subset::Arithmetic
+        } else {
+            Subset::Day5
+        };
+        let case = generate(&mut rng, subset);
+
+        match check(&case, subset) {
+            Verdict::Agree => {}
+            Verdict::Skipped => skipped += 1,
+            Verdict::Diverged(results) => {
+                diverged += 1;
+                println!("divergence on case {} ({:?})", i, subset);
+                println!("  prog:   {:?}", case.prog);
+                println!("  inputs: {:?}", case.inputs);
+                for (name, outcome) in results {
+                    println!("  {}: {:?}", name, outcome);
+                }
+            }
+        }
+    }
+
+    println!(
+        "{} cases, {} diverged, {} skipped for overflow",
+        iterations, diverged, skipped
+    );
+    if diverged > 0 {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,527 @@
+//! A table of cases any Intcode interpreter should pass, kept in text files
+//! so they can be read by interpreters outside this crate too. The ones
+//! that ship with it are in `conformance/`, one case after another:
+//!
+//! ```text
+//! subset day5                # what the cases need, until the next subset line
+//!
+//! case add immediates        # starts a case, named by the rest of the line
+//! program 1101,2,3,5,99,0
+//! input 7                    # optional, inputs in order
+//! output 5                   # optional, outputs expected; none if it's left out
+//! memory 1101,2,3,5,99,5     # optional, memory once it halts, zero past the end
+//! error                      # or that it has to stop with an error instead
+//! ```
+//!
+//! A `subset` line inside a case applies to that case alone.
+
+use crate::machine::{Machine, State};
+use crate::program::Program;
+use std::any::Any;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+/// where the cases that come with this crate live
+pub const DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/conformance");
+
+/// How many steps a runner should allow before giving up on a case; none
+/// of them needs anywhere near this many.
+pub const STEP_LIMIT: usize = 100_000;
+
+/// Which instructions a case uses, from fewest to most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Subset {
+    /// position mode add, multiply and halt
+    Arithmetic,
+    /// immediate mode, input, output, jumps and comparisons too
+    Day5,
+    /// the relative base and memory past the end of the program too
+    Day9,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    pub name: String,
+    /// the file it came from, empty if it was parsed from a string
+    pub file: String,
+    /// 1-based line of its `case`
+    pub line: usize,
+    pub subset: Subset,
+    pub program: Vec<isize>,
+    pub inputs: Vec<isize>,
+    pub outputs: Vec<isize>,
+    pub memory: Option<Vec<isize>>,
+    pub error: bool,
+}
+
+/// what an interpreter made of a case that halted
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Run {
+    pub outputs: Vec<isize>,
+    pub memory: Vec<isize>,
+}
+
+impl Case {
+    /// What's wrong with how an interpreter ran the case, if anything.
+    /// Interpreters say why they stopped without halting in the error.
+    pub fn check(&self, result: Result<Run, String>) -> Result<(), String> {
+        let run = match (result, self.error) {
+            (Ok(run), true) => {
+                return Err(format!(
+                    "expected an error, halted with output {:?}",
+                    run.outputs
+                ))
+            }
+            (Ok(run), false) => run,
+            (Err(_), true) => return Ok(()),
+            (Err(e), false) => return Err(e),
+        };
+
+        if run.outputs != self.outputs {
+            return Err(format!(
+                "expected output {:?}, got {:?}",
+                self.outputs, run.outputs
+            ));
+        }
+        if let Some(memory) = &self.memory {
+            let len = memory.len().max(run.memory.len());
+            for addr in 0..len {
+                let expected = memory.get(addr).copied().unwrap_or(0);
+                let found = run.memory.get(addr).copied().unwrap_or(0);
+                if expected != found {
+                    return Err(format!(
+                        "expected {} at {}, found {}",
+                        expected, addr, found
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// a line that doesn't belong in a case file; `line` is 1-based
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    /// the file and what's wrong with it
+    Parse(String, ParseError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "{}", e),
+            LoadError::Parse(file, e) => write!(f, "{}: {}", file, e),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+fn subset(name: &str) -> Option<Subset> {
+    match name {
+        "arithmetic" => Some(Subset::Arithmetic),
+        "day5" => Some(Subset::Day5),
+        "day9" => Some(Subset::Day9),
+        _ => None,
+    }
+}
+
+/// the cases in a file's worth of text
+pub fn parse(text: &str) -> Result<Vec<Case>, ParseError> {
+    let mut cases: Vec<Case> = Vec::new();
+    // whether the last case has had its program yet
+    let mut programmed = true;
+    let mut default = Subset::Day9;
+
+    for (i, line) in text.lines().enumerate() {
+        let fail = |message: String| ParseError {
+            line: i + 1,
+            message,
+        };
+
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (keyword, rest) = match line.find(char::is_whitespace) {
+            Some(at) => (&line[..at], line[at..].trim()),
+            None => (line, ""),
+        };
+
+        if keyword == "case" {
+            if !programmed {
+                let last = cases.last().unwrap();
+                return Err(ParseError {
+                    line: last.line,
+                    message: format!("case `{}` has no program", last.name),
+                });
+            }
+            if rest.is_empty() {
+                return Err(fail("a case needs a name".to_string()));
+            }
+            cases.push(Case {
+                name: rest.to_string(),
+                file: String::new(),
+                line: i + 1,
+                subset: default,
+                program: Vec::new(),
+                inputs: Vec::new(),
+                outputs: Vec::new(),
+                memory: None,
+                error: false,
+            });
+            programmed = false;
+            continue;
+        }
+
+        let words = || {
+            rest.parse::<Program>()
+                .map(Program::into_inner)
+                .map_err(|e| fail(format!("{} {}: {}", keyword, rest, e)))
+        };
+        let case = match (keyword, cases.last_mut()) {
+            ("subset", None) => {
+                default = subset(rest).ok_or_else(|| fail(format!("unknown subset `{}`", rest)))?;
+                continue;
+            }
+            (_, Some(case)) => case,
+            (_, None) => return Err(fail(format!("`{}` before any case", keyword))),
+        };
+
+        match keyword {
+            "subset" => {
+                case.subset =
+                    subset(rest).ok_or_else(|| fail(format!("unknown subset `{}`", rest)))?
+            }
+            "program" => {
+                case.program = words()?;
+                programmed = true;
+            }
+            "input" => case.inputs = words()?,
+            "output" => case.outputs = words()?,
+            "memory" => case.memory = Some(words()?),
+            "error" if rest.is_empty() => case.error = true,
+            _ => return Err(fail(format!("`{}` is not part of a case", line))),
+        }
+    }
+
+    if !programmed {
+        let last = cases.last().unwrap();
+        return Err(ParseError {
+            line: last.line,
+            message: format!("case `{}` has no program", last.name),
+        });
+    }
+    Ok(cases)
+}
+
+/// the cases in one file
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Case>, LoadError> {
+    let path = path.as_ref();
+    let file = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut cases =
+        parse(&fs::read_to_string(path)?).map_err(|e| LoadError::Parse(file.to_string(), e))?;
+    for case in &mut cases {
+        case.file = file.to_string();
+    }
+    Ok(cases)
+}
+
+/// the cases in every `.txt` file in `dir`, a file at a time in name order
+pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<Case>, LoadError> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "txt") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut cases = Vec::new();
+    for path in paths {
+        cases.extend(load(path)?);
+    }
+    Ok(cases)
+}
+
+/// a case an interpreter got wrong, and how
+#[derive(Debug, PartialEq)]
+pub struct Failure<'a> {
+    pub case: &'a Case,
+    pub problem: String,
+}
+
+impl fmt::Display for Failure<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.case.file, self.case.line, self.case.name, self.problem
+        )
+    }
+}
+
+/// Run every case within `subset` through `run`, which is given a program
+/// and its inputs, and collect the ones it gets wrong.
+pub fn check<'a, F>(cases: &'a [Case], subset: Subset, mut run: F) -> Vec<Failure<'a>>
+where
+    F: FnMut(&[isize], &[isize]) -> Result<Run, String>,
+{
+    cases
+        .iter()
+        .filter(|case| case.subset <= subset)
+        .filter_map(|case| {
+            let problem = case.check(run(&case.program, &case.inputs)).err()?;
+            Some(Failure { case, problem })
+        })
+        .collect()
+}
+
+/// a runner for `check` using this crate's `Machine`
+pub fn machine(prog: &[isize], inputs: &[isize]) -> Result<Run, String> {
+    let mut m = Machine::new(prog, inputs);
+    let mut outputs = Vec::new();
+
+    for _ in 0..STEP_LIMIT {
+        match m.step() {
+            Ok(State::Output(o)) => outputs.push(o),
+            Ok(State::Halt) => {
+                return Ok(Run {
+                    outputs,
+                    memory: m.memory().to_vec(),
+                })
+            }
+            Ok(State::NeedInput) => return Err("ran out of input".to_string()),
+            Ok(_) => {}
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Err(format!("still running after {} steps", STEP_LIMIT))
+}
+
+/// What one step of an interpreter in the style of days 5 and 7 did. They
+/// are handed memory and a pc, run one instruction and leave it to the
+/// caller to supply any input, and panic at anything they can't do.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    Default,
+    /// the address the next input goes to
+    Input(usize),
+    Output(isize),
+    Halt,
+}
+
+/// Why a stepping interpreter didn't halt.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stop {
+    /// it asked for more input than there was
+    Starved,
+    /// it ran out of steps
+    Runaway,
+    Panicked(String),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Starved => write!(f, "ran out of input"),
+            Stop::Runaway => write!(f, "ran too long"),
+            Stop::Panicked(msg) => write!(f, "panicked: {}", msg),
+        }
+    }
+}
+
+/// what a panic said, if it said it with a string
+pub fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_default()
+}
+
+/// Run a stepping interpreter from pc 0 for at most `limit` steps, feeding
+/// it `inputs`, and return the pc it halted at and what it left.
+pub fn step_through<S: Into<Step>>(
+    interpret: fn(&mut [isize], usize) -> (usize, S),
+    prog: &[isize],
+    inputs: &[isize],
+    limit: usize,
+) -> Result<(usize, Run), Stop> {
+    let mut memory = prog.to_vec();
+    let mut inputs = inputs.iter();
+    let mut outputs = Vec::new();
+    let mut pc = 0;
+
+    for _ in 0..limit {
+        let step = panic::catch_unwind(AssertUnwindSafe(|| interpret(&mut memory, pc)));
+        let (new_pc, step) = step.map_err(|e| Stop::Panicked(panic_message(e)))?;
+        pc = new_pc;
+
+        match step.into() {
+            Step::Output(o) => outputs.push(o),
+            // they can't grow memory to fit
+            Step::Input(dest) => match (inputs.next(), memory.get_mut(dest)) {
+                (Some(&v), Some(cell)) => *cell = v,
+                (None, _) => return Err(Stop::Starved),
+                (_, None) => return Err(Stop::Panicked("input out of bounds".to_string())),
+            },
+            Step::Default => {}
+            Step::Halt => return Ok((pc, Run { outputs, memory })),
+        }
+    }
+    Err(Stop::Runaway)
+}
+
+/// a runner for `check` using a stepping interpreter
+pub fn stepper<S: Into<Step>>(
+    interpret: fn(&mut [isize], usize) -> (usize, S),
+) -> impl Fn(&[isize], &[isize]) -> Result<Run, String> {
+    move |prog, inputs| {
+        step_through(interpret, prog, inputs, STEP_LIMIT)
+            .map(|(_, run)| run)
+            .map_err(|stop| stop.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TEXT: &str = "
+# header
+subset day5
+
+case add immediates
+program 1101,2,3,5,99,0
+output 5     # not really
+memory 1101,2,3,5,99,5
+
+case relative
+subset day9
+program 204,0,99
+input 1, 2
+
+case bad
+program 42
+error
+";
+
+    #[test]
+    fn test_parse() {
+        let cases = parse(TEXT).unwrap();
+        assert_eq!(3, cases.len());
+        assert_eq!(
+            Case {
+                name: "add immediates".to_string(),
+                file: String::new(),
+                line: 5,
+                subset: Subset::Day5,
+                program: vec![1101, 2, 3, 5, 99, 0],
+                inputs: vec![],
+                outputs: vec![5],
+                memory: Some(vec![1101, 2, 3, 5, 99, 5]),
+                error: false,
+            },
+            cases[0]
+        );
+        assert_eq!(
+            (Subset::Day9, vec![1, 2]),
+            (cases[1].subset, cases[1].inputs.clone())
+        );
+        assert_eq!((Subset::Day5, true), (cases[2].subset, cases[2].error));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let line = |text: &str| parse(text).unwrap_err().line;
+        assert_eq!(1, line("program 1,2"));
+        assert_eq!(2, line("case a\nprogram 1,,2"));
+        assert_eq!(1, line("case a\ninput 1\ncase b\nprogram 99"));
+        assert_eq!(1, line("case a"));
+        assert_eq!(2, line("case a\nsubset day6\nprogram 99"));
+        assert_eq!(3, line("case a\nprogram 99\nerror now"));
+        assert_eq!(1, line("case"));
+        assert_eq!(
+            "line 2: `outputs 1` is not part of a case",
+            parse("case a\noutputs 1").unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let cases = parse(TEXT).unwrap();
+        let run = |outputs: Vec<isize>, memory: Vec<isize>| Ok(Run { outputs, memory });
+
+        let add = &cases[0];
+        assert_eq!(
+            Ok(()),
+            add.check(run(vec![5], vec![1101, 2, 3, 5, 99, 5, 0, 0]))
+        );
+        assert_eq!(
+            Err("expected output [5], got []".to_string()),
+            add.check(run(vec![], vec![]))
+        );
+        assert_eq!(
+            Err("expected 0 at 6, found 1".to_string()),
+            add.check(run(vec![5], vec![1101, 2, 3, 5, 99, 5, 1]))
+        );
+        assert_eq!(
+            Err("stuck".to_string()),
+            add.check(Err("stuck".to_string()))
+        );
+
+        let bad = &cases[2];
+        assert_eq!(Ok(()), bad.check(Err("invalid opcode".to_string())));
+        assert!(bad.check(run(vec![], vec![42])).is_err());
+    }
+
+    #[test]
+    fn test_check_subsets() {
+        let cases = parse(TEXT).unwrap();
+        let failures = check(&cases, Subset::Day5, |_, _| Err("nope".to_string()));
+        assert_eq!(1, failures.len());
+        assert_eq!(":5: add immediates: nope", failures[0].to_string());
+        assert_eq!(
+            2,
+            check(&cases, Subset::Day9, |_, _| Err("nope".to_string())).len()
+        );
+    }
+
+    #[test]
+    fn test_machine_conforms() {
+        let cases = load_dir(DIR).unwrap();
+        assert!(cases.len() > 150);
+        assert!(cases.iter().all(|case| !case.file.is_empty()));
+
+        let failures = check(&cases, Subset::Day9, machine);
+        let report: Vec<String> = failures.iter().map(|f| f.to_string()).collect();
+        assert_eq!(Vec::<String>::new(), report);
+    }
+}
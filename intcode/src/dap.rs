@@ -0,0 +1,1000 @@
+//! A Debug Adapter Protocol server over stdin and stdout, so editors that
+//! speak DAP can debug Intcode programs with `intcode dap` as the adapter.
+//!
+//! `launch` takes the `program` to load, its `inputs`, `stopOnEntry` and
+//! `negativeBank`. The source shown is the program disassembled one
+//! instruction to a line, as it was loaded; breakpoints go on its lines, or
+//! on addresses as instruction breakpoints. The registers and memory,
+//! sixteen cells to a window, are variables, outputs are output events,
+//! and typing `input 1,2` into the debug console queues input.
+
+use crate::decode::decode;
+use crate::json::Json;
+use crate::machine::{Machine, NegativeAddresses, State};
+use crate::program::Program;
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+/// the only thread there is
+const THREAD: i64 = 1;
+/// the disassembly's `sourceReference`
+const LISTING: i64 = 1;
+
+// variables references
+const REGISTERS: i64 = 1;
+const MEMORY: i64 = 2;
+/// memory window n is `WINDOWS + n`
+const WINDOWS: i64 = 3;
+const WINDOW: usize = 16;
+
+/// steps between looking for requests while the program runs
+const CHUNK: usize = 10_000;
+
+/// the longest message the client may send us
+const MESSAGE_SIZE: usize = 1 << 20;
+
+/// The program disassembled from the start, an instruction or a cell of
+/// data to a line.
+struct Listing {
+    text: String,
+    /// the address each line starts at
+    starts: Vec<usize>,
+    /// the length of the program
+    end: usize,
+}
+
+impl Listing {
+    fn new(prog: &[isize]) -> Listing {
+        let (mut text, mut starts) = (String::new(), Vec::new());
+        let mut pc = 0;
+        while pc < prog.len() {
+            starts.push(pc);
+            match decode(prog, pc) {
+                Some(ins) if pc + ins.width() <= prog.len() => {
+                    text += &format!("{:>5}: {}\n", pc, ins);
+                    pc += ins.width();
+                }
+                _ => {
+                    text += &format!("{:>5}: data {}\n", pc, prog[pc]);
+                    pc += 1;
+                }
+            }
+        }
+        Listing {
+            text,
+            starts,
+            end: prog.len(),
+        }
+    }
+
+    /// the 1-based line `addr` is on, if it's in the program
+    fn line(&self, addr: usize) -> Option<i64> {
+        if addr >= self.end {
+            return None;
+        }
+        Some(self.starts.partition_point(|&start| start <= addr) as i64)
+    }
+
+    fn address(&self, line: i64) -> Option<usize> {
+        let index = usize::try_from(line).ok()?.checked_sub(1)?;
+        self.starts.get(index).copied()
+    }
+}
+
+struct Session {
+    machine: Machine,
+    listing: Listing,
+    /// what the listing is called
+    name: String,
+    exited: bool,
+}
+
+pub struct Adapter {
+    /// of the last message sent
+    seq: i64,
+    session: Option<Session>,
+    line_breakpoints: BTreeSet<usize>,
+    instruction_breakpoints: BTreeSet<usize>,
+    stop_on_entry: bool,
+    running: bool,
+    /// whether the next step leaves a breakpoint rather than stopping at it
+    resuming: bool,
+    finished: bool,
+}
+
+type Events = Vec<(&'static str, Json)>;
+
+fn stopped(reason: &str, description: Option<String>) -> (&'static str, Json) {
+    let mut body = vec![
+        ("reason", reason.into()),
+        ("threadId", THREAD.into()),
+        ("allThreadsStopped", true.into()),
+    ];
+    if let Some(description) = description {
+        body.push(("description", description.clone().into()));
+        body.push(("text", description.into()));
+    }
+    ("stopped", Json::object(body))
+}
+
+fn output(category: &str, text: String) -> (&'static str, Json) {
+    let body = vec![("category", category.into()), ("output", text.into())];
+    ("output", Json::object(body))
+}
+
+/// an address given as `12` or `0xc`
+fn address(text: &str) -> Option<i64> {
+    match text.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+impl Default for Adapter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Adapter {
+    pub fn new() -> Self {
+        Adapter {
+            seq: 0,
+            session: None,
+            line_breakpoints: BTreeSet::new(),
+            instruction_breakpoints: BTreeSet::new(),
+            stop_on_entry: false,
+            running: false,
+            resuming: false,
+            finished: false,
+        }
+    }
+
+    /// whether the program is running, to be carried on with `run`
+    pub fn running(&self) -> bool {
+        self.running
+    }
+
+    /// whether the client has disconnected
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    fn message(&mut self, kind: &str, mut fields: Vec<(&str, Json)>) -> Json {
+        self.seq += 1;
+        fields.insert(0, ("seq", self.seq.into()));
+        fields.insert(1, ("type", kind.into()));
+        Json::object(fields)
+    }
+
+    fn events(&mut self, events: Events) -> Vec<Json> {
+        events
+            .into_iter()
+            .map(|(event, body)| {
+                let mut fields = vec![("event", event.into())];
+                if body != Json::Null {
+                    fields.push(("body", body));
+                }
+                self.message("event", fields)
+            })
+            .collect()
+    }
+
+    /// The response to `request` and any events that follow it.
+    pub fn handle(&mut self, request: &Json) -> Vec<Json> {
+        let null = Json::Null;
+        let command = request.get("command").and_then(Json::as_str).unwrap_or("");
+        let args = request.get("arguments").unwrap_or(&null);
+        let mut events = Vec::new();
+
+        let result = match command {
+            "initialize" => Ok(Json::object(vec![
+                ("supportsConfigurationDoneRequest", true.into()),
+                ("supportsInstructionBreakpoints", true.into()),
+                ("supportsTerminateRequest", true.into()),
+            ])),
+            "launch" => self.launch(args).map(|_| {
+                // only now is there a listing to set breakpoints in
+                events.push(("initialized", Json::Null));
+                Json::Null
+            }),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(Json::Null),
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    events.push(stopped("entry", None));
+                } else {
+                    self.resume();
+                }
+                Ok(Json::Null)
+            }
+            "threads" => Ok(Json::object(vec![(
+                "threads",
+                vec![Json::object(vec![
+                    ("id", THREAD.into()),
+                    ("name", "main".into()),
+                ])]
+                .into(),
+            )])),
+            "stackTrace" => self.stack_trace(),
+            "source" => self.session().map(|s| {
+                Json::object(vec![
+                    ("content", s.listing.text.clone().into()),
+                    ("mimeType", "text/x-intcode".into()),
+                ])
+            }),
+            "scopes" => self.scopes(),
+            "variables" => self.variables(args),
+            "continue" => {
+                self.resume();
+                Ok(Json::object(vec![("allThreadsContinued", true.into())]))
+            }
+            "next" | "stepIn" => self.step().map(|stepped| {
+                events = stepped;
+                Json::Null
+            }),
+            "stepOut" => Err("stepping out isn't supported".to_string()),
+            "pause" => {
+                if self.running {
+                    self.running = false;
+                    events.push(stopped("pause", None));
+                }
+                Ok(Json::Null)
+            }
+            "evaluate" => self.evaluate(args),
+            "disconnect" => {
+                self.finished = true;
+                Ok(Json::Null)
+            }
+            "terminate" => {
+                self.running = false;
+                events.push(("terminated", Json::object(vec![])));
+                Ok(Json::Null)
+            }
+            _ => Err(format!("unsupported request `{}`", command)),
+        };
+
+        let mut fields = vec![
+            (
+                "request_seq",
+                request.get("seq").cloned().unwrap_or_else(|| 0.into()),
+            ),
+            ("success", result.is_ok().into()),
+            ("command", command.into()),
+        ];
+        match result {
+            Ok(Json::Null) => {}
+            Ok(body) => fields.push(("body", body)),
+            Err(message) => fields.push(("message", message.into())),
+        }
+        let mut replies = vec![self.message("response", fields)];
+        replies.extend(self.events(events));
+        replies
+    }
+
+    /// Run the program a while longer, and say if it stopped.
+    pub fn run(&mut self) -> Vec<Json> {
+        let events = self.advance(CHUNK);
+        self.events(events)
+    }
+
+    fn session(&self) -> Result<&Session, String> {
+        self.session
+            .as_ref()
+            .ok_or_else(|| "no program has been launched".to_string())
+    }
+
+    fn launch(&mut self, args: &Json) -> Result<(), String> {
+        let path = args
+            .get("program")
+            .and_then(Json::as_str)
+            .ok_or("launch needs a `program`")?;
+        let prog = Program::load(path).map_err(|e| format!("{}: {}", path, e))?;
+
+        let mut inputs = Vec::new();
+        for v in args.get("inputs").and_then(Json::as_array).unwrap_or(&[]) {
+            let v = v.as_i64().ok_or(format!("input {} isn't an integer", v))?;
+            inputs.push(v as isize);
+        }
+
+        let mut machine = Machine::new(&prog, &inputs);
+        if args.get("negativeBank").and_then(Json::as_bool) == Some(true) {
+            machine.negative_addresses(NegativeAddresses::Bank);
+        }
+        let name = Path::new(path)
+            .file_name()
+            .map_or("program".into(), |n| n.to_string_lossy());
+        self.session = Some(Session {
+            machine,
+            listing: Listing::new(&prog),
+            name: format!("{}.asm", name),
+            exited: false,
+        });
+        self.stop_on_entry = args.get("stopOnEntry").and_then(Json::as_bool) == Some(true);
+        Ok(())
+    }
+
+    fn set_breakpoints(&mut self, args: &Json) -> Result<Json, String> {
+        let session = self.session()?;
+        let lines = args
+            .get("breakpoints")
+            .and_then(Json::as_array)
+            .unwrap_or(&[]);
+
+        let mut addresses = BTreeSet::new();
+        let mut breakpoints = Vec::new();
+        for line in lines.iter().filter_map(|b| b.get("line")?.as_i64()) {
+            let mut fields = vec![("line", line.into())];
+            match session.listing.address(line) {
+                Some(addr) => {
+                    addresses.insert(addr);
+                    fields.push(("verified", true.into()));
+                }
+                None => {
+                    fields.push(("verified", false.into()));
+                    fields.push(("message", "no instruction on that line".into()));
+                }
+            }
+            breakpoints.push(Json::object(fields));
+        }
+        self.line_breakpoints = addresses;
+        Ok(Json::object(vec![("breakpoints", breakpoints.into())]))
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Json) -> Result<Json, String> {
+        let requested = args
+            .get("breakpoints")
+            .and_then(Json::as_array)
+            .unwrap_or(&[]);
+
+        let mut addresses = BTreeSet::new();
+        let mut breakpoints = Vec::new();
+        for b in requested {
+            let reference = b.get("instructionReference").and_then(Json::as_str);
+            let offset = b.get("offset").and_then(Json::as_i64).unwrap_or(0);
+            let addr = reference
+                .and_then(address)
+                .and_then(|a| a.checked_add(offset))
+                .and_then(|a| usize::try_from(a).ok());
+
+            let mut fields = vec![("verified", addr.is_some().into())];
+            match addr {
+                Some(addr) => {
+                    addresses.insert(addr);
+                    fields.push(("instructionReference", addr.to_string().into()));
+                }
+                None => fields.push(("message", "not an address".into())),
+            }
+            breakpoints.push(Json::object(fields));
+        }
+        self.instruction_breakpoints = addresses;
+        Ok(Json::object(vec![("breakpoints", breakpoints.into())]))
+    }
+
+    /// one instruction, stopping as if it had been a breakpoint
+    fn step(&mut self) -> Result<Events, String> {
+        self.session()?;
+        self.resuming = true;
+        self.running = true;
+        let mut events = self.advance(1);
+        if self.running {
+            self.running = false;
+            events.push(stopped("step", None));
+        }
+        Ok(events)
+    }
+
+    fn resume(&mut self) {
+        if self.session.as_ref().is_some_and(|s| !s.exited) {
+            self.resuming = true;
+            self.running = true;
+        }
+    }
+
+    /// Step the program up to `steps` times, stopping at breakpoints other
+    /// than the one it's leaving and wherever the program can't go on.
+    fn advance(&mut self, steps: usize) -> Events {
+        let mut events = Vec::new();
+        let session = match &mut self.session {
+            Some(session) if !session.exited => session,
+            _ => {
+                self.running = false;
+                return events;
+            }
+        };
+
+        for _ in 0..steps {
+            let pc = session.machine.pc();
+            if !self.resuming
+                && (self.line_breakpoints.contains(&pc)
+                    || self.instruction_breakpoints.contains(&pc))
+            {
+                events.push(stopped("breakpoint", None));
+                break;
+            }
+            self.resuming = false;
+
+            match session.machine.step() {
+                Ok(State::Output(o)) => events.push(output("stdout", format!("{}\n", o))),
+                Ok(State::Halt) => {
+                    session.exited = true;
+                    events.push(("exited", Json::object(vec![("exitCode", 0.into())])));
+                    events.push(("terminated", Json::object(vec![])));
+                    break;
+                }
+                Ok(State::NeedInput) => {
+                    let message = "waiting for input; type `input 1,2` to give it some";
+                    events.push(output("console", format!("{}\n", message)));
+                    events.push(stopped("exception", Some("waiting for input".into())));
+                    break;
+                }
+                Err(e) => {
+                    events.push(output("stderr", format!("{}\n", e)));
+                    events.push(stopped("exception", Some(e.to_string())));
+                    break;
+                }
+                Ok(_) => {}
+            }
+        }
+
+        if !events.is_empty() && events.last().unwrap().0 != "output" {
+            self.running = false;
+        }
+        events
+    }
+
+    fn stack_trace(&self) -> Result<Json, String> {
+        let session = self.session()?;
+        let m = &session.machine;
+        let pc = m.pc();
+        let name = match decode(m.memory(), pc) {
+            Some(ins) => ins.to_string(),
+            None => format!("data {}", m.get(pc)),
+        };
+
+        let mut frame = vec![("id", 1.into()), ("name", name.into())];
+        match session.listing.line(pc) {
+            Some(line) => {
+                let source = Json::object(vec![
+                    ("name", session.name.clone().into()),
+                    ("sourceReference", LISTING.into()),
+                ]);
+                frame.push(("source", source));
+                frame.push(("line", line.into()));
+                frame.push(("column", 1.into()));
+            }
+            // running code it grew past the end of the program
+            None => {
+                frame.push(("line", 0.into()));
+                frame.push(("column", 0.into()));
+            }
+        }
+        frame.push(("instructionPointerReference", pc.to_string().into()));
+
+        Ok(Json::object(vec![
+            ("stackFrames", vec![Json::object(frame)].into()),
+            ("totalFrames", 1.into()),
+        ]))
+    }
+
+    fn scopes(&self) -> Result<Json, String> {
+        let windows = self
+            .session()?
+            .machine
+            .memory()
+            .len()
+            .div_ceil(WINDOW)
+            .max(1);
+        let scope = |name: &str, reference: i64| {
+            vec![
+                ("name", name.into()),
+                ("variablesReference", reference.into()),
+                ("expensive", false.into()),
+            ]
+        };
+        let registers = scope("Registers", REGISTERS);
+        let mut memory = scope("Memory", MEMORY);
+        memory.push(("indexedVariables", (windows as i64).into()));
+
+        Ok(Json::object(vec![(
+            "scopes",
+            vec![Json::object(registers), Json::object(memory)].into(),
+        )]))
+    }
+
+    fn variables(&self, args: &Json) -> Result<Json, String> {
+        let m = &self.session()?.machine;
+        let reference = args
+            .get("variablesReference")
+            .and_then(Json::as_i64)
+            .unwrap_or(0);
+        let variable = |name: String, value: String, reference: i64| {
+            Json::object(vec![
+                ("name", name.into()),
+                ("value", value.into()),
+                ("variablesReference", reference.into()),
+            ])
+        };
+        let cells = |start: usize| -> Vec<String> {
+            (start..start + WINDOW)
+                .map(|addr| m.get(addr).to_string())
+                .collect()
+        };
+
+        let windows = m.memory().len().div_ceil(WINDOW).max(1);
+
+        let variables = match reference {
+            REGISTERS => vec![
+                variable("pc".into(), m.pc().to_string(), 0),
+                variable("rb".into(), m.relative_base().to_string(), 0),
+            ],
+            MEMORY => {
+                let start = args.get("start").and_then(Json::as_i64).unwrap_or(0);
+                let count = args.get("count").and_then(Json::as_i64).unwrap_or(0);
+                let start = (start.max(0) as usize).min(windows);
+                let end = match count {
+                    0 => windows,
+                    n => start.saturating_add(n.max(0) as usize).min(windows),
+                };
+
+                (start..end)
+                    .map(|w| {
+                        let addr = w * WINDOW;
+                        let name = format!("{}..{}", addr, addr + WINDOW - 1);
+                        let value = format!("[{}]", cells(addr).join(", "));
+                        variable(name, value, WINDOWS + w as i64)
+                    })
+                    .collect()
+            }
+            // only windows the memory scope lists
+            r if r >= WINDOWS && r - WINDOWS < windows as i64 => {
+                let start = (r - WINDOWS) as usize * WINDOW;
+                cells(start)
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, v)| variable(format!("[{}]", start + i), v, 0))
+                    .collect()
+            }
+            _ => return Err(format!("no variables {}", reference)),
+        };
+        Ok(Json::object(vec![("variables", variables.into())]))
+    }
+
+    /// `input 1,2` queues input and `[12]` reads a cell
+    fn evaluate(&mut self, args: &Json) -> Result<Json, String> {
+        let expression = args
+            .get("expression")
+            .and_then(Json::as_str)
+            .unwrap_or("")
+            .trim();
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => return Err("no program has been launched".to_string()),
+        };
+
+        let result = if let Some(values) = expression.strip_prefix("input ") {
+            let values: Program = values
+                .parse()
+                .map_err(|e| format!("{}: {}", expression, e))?;
+            for &v in values.iter() {
+                session.machine.push_input(v);
+            }
+            format!("queued {} inputs", values.len())
+        } else {
+            let addr = expression
+                .strip_prefix('[')
+                .and_then(|e| e.strip_suffix(']'))
+                .and_then(address)
+                .and_then(|a| isize::try_from(a).ok())
+                .ok_or("try `input 1,2` or `[address]`")?;
+            session
+                .machine
+                .load(addr)
+                .map_err(|e| e.to_string())?
+                .to_string()
+        };
+        Ok(Json::object(vec![
+            ("result", result.into()),
+            ("variablesReference", 0.into()),
+        ]))
+    }
+}
+
+fn invalid<E: ToString>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+/// the next message, or None at the end of the stream
+fn read_message<R: BufRead>(r: &mut R) -> io::Result<Option<Json>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if r.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        match line.trim_end() {
+            "" if len.is_some() => break,
+            "" => {}
+            header => {
+                if let Some(v) = header.strip_prefix("Content-Length:") {
+                    let n = v.trim().parse::<usize>().map_err(invalid)?;
+                    if n > MESSAGE_SIZE {
+                        return Err(invalid(format!("{}-byte message is too long", n)));
+                    }
+                    len = Some(n);
+                }
+            }
+        }
+    }
+
+    let mut body = vec![0; len.unwrap()];
+    r.read_exact(&mut body)?;
+    let text = String::from_utf8(body).map_err(invalid)?;
+    text.parse().map(Some).map_err(invalid)
+}
+
+fn write_message<W: Write>(w: &mut W, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(w, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    w.flush()
+}
+
+/// Answer requests from `input` on `output` until the client disconnects
+/// or hangs up. Requests are read on a thread of their own so they can be
+/// answered while the program runs.
+pub fn serve<R, W>(input: R, mut output: W) -> io::Result<()>
+where
+    R: BufRead + Send + 'static,
+    W: Write,
+{
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut input = input;
+        loop {
+            let message = read_message(&mut input);
+            let last = !matches!(message, Ok(Some(_)));
+            if tx.send(message).is_err() || last {
+                break;
+            }
+        }
+    });
+
+    let mut adapter = Adapter::new();
+    while !adapter.finished() {
+        let message = if adapter.running() {
+            match rx.try_recv() {
+                Ok(message) => Some(message),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        } else {
+            match rx.recv() {
+                Ok(message) => Some(message),
+                Err(_) => return Ok(()),
+            }
+        };
+
+        let replies = match message {
+            Some(Ok(Some(request))) => adapter.handle(&request),
+            Some(Ok(None)) => return Ok(()),
+            Some(Err(e)) => return Err(e),
+            None => adapter.run(),
+        };
+        for reply in &replies {
+            write_message(&mut output, reply)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // out [9], in -> [9], out [9], halt, then data
+    const PROG: &str = "4,9,3,9,4,9,99,0,-3,42";
+
+    fn launch(prog: &str, args: Vec<(&str, Json)>) -> Adapter {
+        // tests run side by side, so each needs a file of its own
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "intcode-dap-{}-{}.ic",
+            std::process::id(),
+            FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&path, prog).unwrap();
+
+        let mut args = args;
+        args.push(("program", path.to_str().unwrap().into()));
+        let mut a = Adapter::new();
+        let replies = a.handle(&request("launch", args));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            Some(true),
+            replies[0].get("success").and_then(Json::as_bool)
+        );
+        a
+    }
+
+    fn request(command: &str, args: Vec<(&str, Json)>) -> Json {
+        Json::object(vec![
+            ("seq", 1.into()),
+            ("type", "request".into()),
+            ("command", command.into()),
+            ("arguments", Json::object(args)),
+        ])
+    }
+
+    /// the events among `replies`, with what distinguishes them
+    fn events(replies: &[Json]) -> Vec<String> {
+        replies
+            .iter()
+            .filter(|r| r.get("type").and_then(Json::as_str) == Some("event"))
+            .map(|r| {
+                let body = r.get("body").unwrap();
+                let detail = ["reason", "output", "exitCode"]
+                    .iter()
+                    .find_map(|k| body.get(k))
+                    .map_or(String::new(), |d| format!(" {}", d));
+                format!(
+                    "{}{}",
+                    r.get("event").and_then(Json::as_str).unwrap(),
+                    detail
+                )
+            })
+            .collect()
+    }
+
+    /// everything sent until the program next stops
+    fn run(a: &mut Adapter) -> Vec<String> {
+        let mut replies = Vec::new();
+        while a.running() {
+            replies.extend(a.run());
+        }
+        events(&replies)
+    }
+
+    fn body(replies: &[Json]) -> &Json {
+        replies[0].get("body").unwrap()
+    }
+
+    #[test]
+    fn test_listing() {
+        let listing = Listing::new(&PROG.parse::<Program>().unwrap());
+        assert_eq!(
+            "    0: out [9]\n    2: in -> [9]\n    4: out [9]\n    6: halt\n    \
+             7: data 0\n    8: data -3\n    9: data 42\n",
+            listing.text
+        );
+        assert_eq!(Some(2), listing.line(2));
+        assert_eq!(Some(2), listing.line(3));
+        assert_eq!(Some(7), listing.line(9));
+        assert_eq!(None, listing.line(10));
+        assert_eq!(Some(4), listing.address(3));
+        assert_eq!(None, listing.address(0));
+        assert_eq!(None, listing.address(8));
+    }
+
+    #[test]
+    fn test_session() {
+        let mut a = launch(PROG, vec![("inputs", vec![7.into()].into())]);
+        let replies = a.handle(&request(
+            "setBreakpoints",
+            vec![(
+                "breakpoints",
+                vec![
+                    Json::object(vec![("line", 3.into())]),
+                    Json::object(vec![("line", 99.into())]),
+                ]
+                .into(),
+            )],
+        ));
+        let verified: Vec<_> = body(&replies)
+            .get("breakpoints")
+            .and_then(Json::as_array)
+            .unwrap()
+            .iter()
+            .map(|b| b.get("verified").and_then(Json::as_bool).unwrap())
+            .collect();
+        assert_eq!(vec![true, false], verified);
+
+        assert!(events(&a.handle(&request("configurationDone", vec![]))).is_empty());
+        assert_eq!(
+            vec![r#"output "42\n""#, r#"stopped "breakpoint""#],
+            run(&mut a)
+        );
+
+        let trace = a.handle(&request("stackTrace", vec![]));
+        let frame = &body(&trace).get("stackFrames").unwrap().as_array().unwrap()[0];
+        assert_eq!(Some("out [9]"), frame.get("name").and_then(Json::as_str));
+        assert_eq!(Some(3), frame.get("line").and_then(Json::as_i64));
+
+        // stepping off the breakpoint, then running to the end
+        let replies = a.handle(&request("next", vec![]));
+        assert_eq!(
+            vec![r#"output "7\n""#, r#"stopped "step""#],
+            events(&replies)
+        );
+        a.handle(&request("continue", vec![]));
+        assert_eq!(vec!["exited 0", "terminated"], run(&mut a));
+
+        a.handle(&request("disconnect", vec![]));
+        assert!(a.finished());
+    }
+
+    #[test]
+    fn test_input_and_instruction_breakpoints() {
+        let mut a = launch(PROG, vec![("stopOnEntry", true.into())]);
+        let replies = a.handle(&request("configurationDone", vec![]));
+        assert_eq!(vec![r#"stopped "entry""#], events(&replies));
+
+        let replies = a.handle(&request(
+            "setInstructionBreakpoints",
+            vec![(
+                "breakpoints",
+                vec![Json::object(vec![
+                    ("instructionReference", "0x2".into()),
+                    ("offset", 4.into()),
+                ])]
+                .into(),
+            )],
+        ));
+        let b = &body(&replies)
+            .get("breakpoints")
+            .unwrap()
+            .as_array()
+            .unwrap()[0];
+        assert_eq!(
+            Some("6"),
+            b.get("instructionReference").and_then(Json::as_str)
+        );
+
+        a.handle(&request("continue", vec![]));
+        let stop = run(&mut a);
+        assert_eq!(r#"stopped "exception""#, stop[2]);
+
+        let evaluate = |a: &mut Adapter, expression: &str| {
+            let replies = a.handle(&request(
+                "evaluate",
+                vec![("expression", expression.into())],
+            ));
+            body(&replies).get("result").unwrap().clone()
+        };
+        assert_eq!(
+            Json::from("queued 2 inputs"),
+            evaluate(&mut a, "input 8, 9")
+        );
+        a.handle(&request("continue", vec![]));
+        assert_eq!(
+            vec![r#"output "8\n""#, r#"stopped "breakpoint""#],
+            run(&mut a)
+        );
+        assert_eq!(Json::from("8"), evaluate(&mut a, "[9]"));
+        assert_eq!(Json::from("-3"), evaluate(&mut a, "[0x8]"));
+    }
+
+    #[test]
+    fn test_variables() {
+        let prog: Vec<String> = (0..20).map(|n| (100 + n).to_string()).collect();
+        let mut a = launch(&prog.join(","), vec![]);
+
+        let replies = a.handle(&request("scopes", vec![("frameId", 1.into())]));
+        let scopes = body(&replies).get("scopes").unwrap().as_array().unwrap();
+        assert_eq!(
+            Some(2),
+            scopes[1].get("indexedVariables").and_then(Json::as_i64)
+        );
+
+        let variables = |a: &mut Adapter, args: Vec<(&str, Json)>| {
+            let replies = a.handle(&request("variables", args));
+            body(&replies)
+                .get("variables")
+                .unwrap()
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|v| {
+                    let field = |k| v.get(k).and_then(Json::as_str).unwrap().to_string();
+                    format!("{}={}", field("name"), field("value"))
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec!["pc=0", "rb=0"],
+            variables(&mut a, vec![("variablesReference", REGISTERS.into())])
+        );
+
+        let windows = variables(
+            &mut a,
+            vec![
+                ("variablesReference", MEMORY.into()),
+                ("start", 1.into()),
+                ("count", 5.into()),
+            ],
+        );
+        assert_eq!(
+            vec!["16..31=[116, 117, 118, 119, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]"],
+            windows
+        );
+        let cells = variables(&mut a, vec![("variablesReference", (WINDOWS + 1).into())]);
+        assert_eq!(("[16]=116", "[31]=0"), (&cells[0][..], &cells[15][..]));
+
+        for reference in &[0, WINDOWS + 2, i64::MAX] {
+            let replies = a.handle(&request(
+                "variables",
+                vec![("variablesReference", (*reference).into())],
+            ));
+            assert_eq!(
+                Some(false),
+                replies[0].get("success").and_then(Json::as_bool),
+                "{}",
+                reference
+            );
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut a = Adapter::new();
+        let replies = a.handle(&request("stackTrace", vec![]));
+        assert_eq!(
+            Some("no program has been launched"),
+            replies[0].get("message").and_then(Json::as_str)
+        );
+        let replies = a.handle(&request("launch", vec![("program", "/nonexistent".into())]));
+        assert_eq!(
+            Some(false),
+            replies[0].get("success").and_then(Json::as_bool)
+        );
+
+        let mut a = launch("42", vec![]);
+        a.handle(&request("configurationDone", vec![]));
+        assert_eq!(
+            vec![
+                r#"output "invalid opcode 42 at pc 0\n""#,
+                r#"stopped "exception""#
+            ],
+            run(&mut a)
+        );
+    }
+
+    #[test]
+    fn test_serve() {
+        let mut input = Vec::new();
+        for (seq, command) in ["initialize", "threads", "disconnect"].iter().enumerate() {
+            let mut r = request(command, vec![]);
+            if let Json::Object(fields) = &mut r {
+                fields[0].1 = (seq as i64 + 1).into();
+            }
+            write_message(&mut input, &r).unwrap();
+        }
+
+        let mut output = Vec::new();
+        serve(Cursor::new(input), &mut output).unwrap();
+
+        let mut output = Cursor::new(output);
+        let mut responses = Vec::new();
+        while let Some(message) = read_message(&mut output).unwrap() {
+            responses.push(message);
+        }
+        assert_eq!(3, responses.len());
+        assert_eq!(
+            r#"{"seq":2,"type":"response","request_seq":2,"success":true,"command":"threads","body":{"threads":[{"id":1,"name":"main"}]}}"#,
+            responses[1].to_string()
+        );
+    }
+
+    #[test]
+    fn test_message_too_long() {
+        let header = format!("Content-Length: {}\r\n\r\n", usize::MAX);
+        let err = read_message(&mut Cursor::new(header)).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+}
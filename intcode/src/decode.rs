@@ -0,0 +1,215 @@
+//! Decoding instructions from a program image without running it.
+
+use crate::machine::ParameterMode;
+use crate::opcode::Param;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Op {
+    Add,
+    Mul,
+    Input,
+    Output,
+    JumpIfTrue,
+    JumpIfFalse,
+    LessThan,
+    Equals,
+    AdjustBase,
+    Halt,
+}
+
+impl Op {
+    pub fn from_code(code: isize) -> Option<Op> {
+        Some(match code {
+            1 => Op::Add,
+            2 => Op::Mul,
+            3 => Op::Input,
+            4 => Op::Output,
+            5 => Op::JumpIfTrue,
+            6 => Op::JumpIfFalse,
+            7 => Op::LessThan,
+            8 => Op::Equals,
+            9 => Op::AdjustBase,
+            99 => Op::Halt,
+            _ => return None,
+        })
+    }
+
+    pub fn code(self) -> isize {
+        match self {
+            Op::Add => 1,
+            Op::Mul => 2,
+            Op::Input => 3,
+            Op::Output => 4,
+            Op::JumpIfTrue => 5,
+            Op::JumpIfFalse => 6,
+            Op::LessThan => 7,
+            Op::Equals => 8,
+            Op::AdjustBase => 9,
+            Op::Halt => 99,
+        }
+    }
+
+    /// how many parameters follow the opcode
+    pub fn arity(self) -> usize {
+        match self {
+            Op::Add | Op::Mul | Op::LessThan | Op::Equals => 3,
+            Op::JumpIfTrue | Op::JumpIfFalse => 2,
+            Op::Input | Op::Output | Op::AdjustBase => 1,
+            Op::Halt => 0,
+        }
+    }
+
+    /// which parameter (counting from 0) is written to, if any
+    pub fn destination(self) -> Option<usize> {
+        match self {
+            Op::Add | Op::Mul | Op::LessThan | Op::Equals => Some(2),
+            Op::Input => Some(0),
+            _ => None,
+        }
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Op::Add => "add",
+            Op::Mul => "mul",
+            Op::Input => "in",
+            Op::Output => "out",
+            Op::JumpIfTrue => "jnz",
+            Op::JumpIfFalse => "jz",
+            Op::LessThan => "lt",
+            Op::Equals => "eq",
+            Op::AdjustBase => "arb",
+            Op::Halt => "halt",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub pc: usize,
+    pub op: Op,
+    pub params: Vec<Param>,
+}
+
+impl Instruction {
+    /// how many cells it takes up
+    pub fn width(&self) -> usize {
+        1 + self.params.len()
+    }
+
+    /// the instruction word, modes and all
+    pub fn word(&self) -> isize {
+        self.params
+            .iter()
+            .enumerate()
+            .fold(self.op.code(), |word, (n, p)| {
+                word + mode_digit(p.mode) * 10_isize.pow(n as u32 + 2)
+            })
+    }
+
+    /// the cells it's made of, as they'd appear in an image
+    pub fn encode(&self) -> Vec<isize> {
+        let mut cells = vec![self.word()];
+        cells.extend(self.params.iter().map(|p| p.raw));
+        cells
+    }
+}
+
+fn mode_digit(mode: ParameterMode) -> isize {
+    match mode {
+        ParameterMode::Position => 0,
+        ParameterMode::Immediate => 1,
+        ParameterMode::Relative => 2,
+    }
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode {
+            ParameterMode::Position => write!(f, "[{}]", self.raw),
+            ParameterMode::Immediate => write!(f, "#{}", self.raw),
+            ParameterMode::Relative if self.raw < 0 => write!(f, "[rb-{}]", -self.raw),
+            ParameterMode::Relative => write!(f, "[rb+{}]", self.raw),
+        }
+    }
+}
+
+/// `add [9], #3 -> [0]`, with whatever is written to after an arrow
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.op.mnemonic())?;
+        let dest = self.op.destination();
+        for (n, p) in self.params.iter().enumerate() {
+            let sep = if Some(n) == dest {
+                " ->"
+            } else if n == 0 {
+                ""
+            } else {
+                ","
+            };
+            write!(f, "{} {}", sep, p)?;
+        }
+        Ok(())
+    }
+}
+
+/// The instruction at `pc`, or None if there isn't a valid one there: an
+/// unknown opcode, an unknown mode or an immediate destination. Cells past
+/// the end of the image read as zero, as they do when running.
+pub fn decode(prog: &[isize], pc: usize) -> Option<Instruction> {
+    let word = *prog.get(pc)?;
+    let op = Op::from_code(word % 100)?;
+
+    let params = (1..=op.arity())
+        .map(|n| {
+            let mode = ParameterMode::of(word, n)?;
+            let raw = prog.get(pc + n).copied().unwrap_or(0);
+            Some(Param { mode, raw })
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    if let Some(d) = op.destination() {
+        if params[d].mode == ParameterMode::Immediate {
+            return None;
+        }
+    }
+
+    Some(Instruction { pc, op, params })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let prog = &[1002, 4, 3, 4, 33, 109, -7, 21108, 1, 2, 3, 99];
+
+        let mul = decode(prog, 0).unwrap();
+        assert_eq!((Op::Mul, 4), (mul.op, mul.width()));
+        assert_eq!("mul [4], #3 -> [4]", mul.to_string());
+        assert_eq!(&prog[..4], &mul.encode()[..]);
+
+        assert_eq!("arb #-7", decode(prog, 5).unwrap().to_string());
+        assert_eq!("eq #1, #2 -> [rb+3]", decode(prog, 7).unwrap().to_string());
+        assert_eq!(21108, decode(prog, 7).unwrap().word());
+        assert_eq!("halt", decode(prog, 11).unwrap().to_string());
+        assert_eq!("in -> [rb-2]", decode(&[203, -2], 0).unwrap().to_string());
+
+        // parameters past the end read as zero
+        assert_eq!("out [0]", decode(&[4], 0).unwrap().to_string());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(None, decode(&[33], 0));
+        assert_eq!(None, decode(&[301, 0, 0, 0], 0));
+        assert_eq!(None, decode(&[10001, 0, 0, 0], 0));
+        assert_eq!(None, decode(&[103, 0], 0));
+        assert_eq!(None, decode(&[-1], 0));
+        assert_eq!(None, decode(&[99], 1));
+    }
+}
@@ -0,0 +1,825 @@
+//! Turning a program image back into something like C.
+//!
+//! Code is found by following control flow from address 0. Compiled programs
+//! call a function by storing the return address at `[rb+0]` and jumping to
+//! it; the function moves the relative base past its frame with `arb #n` and
+//! returns with `arb #-n` and a jump through `[rb+0]`. Those sequences become
+//! calls and returns. The cells of a function's frame are named `a1`, `a2`,
+//! ... and the cells just past it, where it passes arguments to the functions
+//! it calls and gets their results back, `c1`, `c2`, ... so a callee's `a1`
+//! is its caller's `c1`. Forward branches become `if` and `else`, backward
+//! ones loops, and jumps that fit neither are left as `goto`s.
+//!
+//! Other cells are `m[addr]`, or `m[rb+k]` in functions that move the
+//! relative base some other way. The relative base isn't followed through
+//! such a function, so `m[rb+k]` means the cell k past wherever it is at
+//! that point, a jump through one stays a `goto *m[rb+k]`, and the cells it
+//! passes to the functions it calls aren't named `c1`, `c2`, ... either.
+//! Day9's `main`, which moves it by values it reads from memory, comes out
+//! like that. The image is read as it is loaded, so code a program writes
+//! over itself comes out as it was before running.
+
+use crate::decode::{decode, Instruction, Op};
+use crate::machine::ParameterMode;
+use crate::opcode::Param;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Lt,
+    Eq,
+}
+
+impl BinOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Lt => "<",
+            BinOp::Eq => "==",
+        }
+    }
+
+    fn precedence(self) -> u8 {
+        match self {
+            BinOp::Mul => 3,
+            BinOp::Add | BinOp::Sub => 2,
+            BinOp::Lt => 1,
+            BinOp::Eq => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Const(isize),
+    /// `m[addr]`
+    Mem(isize),
+    /// `m[rb+k]`, where the frame isn't known
+    Rel(isize),
+    /// a cell of the function's frame; 0 holds the return address
+    Local(isize),
+    /// a cell past the frame, passed to the functions it calls
+    Arg(isize),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+/// `left op right`, folded and tidied where that's possible
+fn binary(op: BinOp, left: Expr, right: Expr) -> Expr {
+    if let (Expr::Const(a), Expr::Const(b)) = (&left, &right) {
+        let folded = match op {
+            BinOp::Add => a.checked_add(*b),
+            BinOp::Sub => a.checked_sub(*b),
+            BinOp::Mul => a.checked_mul(*b),
+            BinOp::Lt => Some((a < b) as isize),
+            BinOp::Eq => Some((a == b) as isize),
+        };
+        if let Some(v) = folded {
+            return Expr::Const(v);
+        }
+    }
+
+    match (op, left, right) {
+        (BinOp::Add, Expr::Const(0), e)
+        | (BinOp::Add, e, Expr::Const(0))
+        | (BinOp::Mul, Expr::Const(1), e)
+        | (BinOp::Mul, e, Expr::Const(1)) => e,
+        (BinOp::Add, Expr::Const(c), e) if !matches!(e, Expr::Const(_)) => {
+            binary(BinOp::Add, e, Expr::Const(c))
+        }
+        (BinOp::Add, e, Expr::Const(c)) if c < 0 && c != isize::MIN => {
+            Expr::Binary(BinOp::Sub, Box::new(e), Box::new(Expr::Const(-c)))
+        }
+        (op, left, right) => Expr::Binary(op, Box::new(left), Box::new(right)),
+    }
+}
+
+fn not(e: Expr) -> Expr {
+    match e {
+        Expr::Not(inner) => *inner,
+        Expr::Const(c) => Expr::Const((c == 0) as isize),
+        e => Expr::Not(Box::new(e)),
+    }
+}
+
+/// `e`, in parentheses if it binds more loosely than `min`
+fn operand(f: &mut fmt::Formatter, e: &Expr, min: u8) -> fmt::Result {
+    match e {
+        Expr::Binary(op, ..) if op.precedence() < min => write!(f, "({})", e),
+        _ => write!(f, "{}", e),
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Const(v) => write!(f, "{}", v),
+            Expr::Mem(addr) => write!(f, "m[{}]", addr),
+            Expr::Rel(k) if *k < 0 => write!(f, "m[rb-{}]", -k),
+            Expr::Rel(k) => write!(f, "m[rb+{}]", k),
+            Expr::Local(0) => write!(f, "ret"),
+            Expr::Local(i) => write!(f, "a{}", i),
+            Expr::Arg(i) => write!(f, "c{}", i),
+            Expr::Binary(op, left, right) => {
+                operand(f, left, op.precedence())?;
+                write!(f, " {} ", op.symbol())?;
+                operand(f, right, op.precedence() + 1)
+            }
+            Expr::Not(e) => match **e {
+                Expr::Binary(..) => write!(f, "!({})", e),
+                _ => write!(f, "!{}", e),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Direct(usize),
+    Indirect(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    Assign(Expr, Expr),
+    Input(Expr),
+    Output(Expr),
+    /// `rb += e`, in functions that don't keep a fixed frame
+    AdjustBase(Expr),
+    /// the function at an address, with the arguments it takes
+    Call(usize, Vec<Expr>),
+    Return,
+    Halt,
+    /// a cell control reaches that isn't an instruction
+    Invalid(isize),
+    /// a jump that doesn't fit any structure, taken when the condition holds
+    Goto(Option<Expr>, Target),
+    If(Expr, Vec<Line>, Vec<Line>),
+    While(Expr, Vec<Line>),
+    DoWhile(Vec<Line>, Expr),
+}
+
+/// a statement and the address its code starts at
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub pc: usize,
+    pub stmt: Stmt,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub entry: usize,
+    /// how far it moves the relative base, or None if it moves it some other way
+    pub frame: Option<isize>,
+    pub body: Vec<Line>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decompiled {
+    /// in order of address, starting with `main` at 0
+    pub functions: Vec<Function>,
+}
+
+/// whether a jump's condition is known without running it
+fn constant_condition(ins: &Instruction) -> Option<bool> {
+    let cond = ins.params[0];
+    if cond.mode != ParameterMode::Immediate {
+        return None;
+    }
+    Some(match ins.op {
+        Op::JumpIfTrue => cond.raw != 0,
+        _ => cond.raw == 0,
+    })
+}
+
+fn is_jump(ins: &Instruction) -> bool {
+    ins.op == Op::JumpIfTrue || ins.op == Op::JumpIfFalse
+}
+
+/// where a jump goes, if that's written in the instruction
+fn direct(ins: &Instruction) -> Option<usize> {
+    let target = ins.params[1];
+    if target.mode == ParameterMode::Immediate && target.raw >= 0 {
+        Some(target.raw as usize)
+    } else {
+        None
+    }
+}
+
+fn is_return(ins: &Instruction) -> bool {
+    let slot = Param {
+        mode: ParameterMode::Relative,
+        raw: 0,
+    };
+    is_jump(ins) && constant_condition(ins) == Some(true) && ins.params[1] == slot
+}
+
+/// The function called and the address it returns to, if `pc` stores a
+/// return address at `[rb+0]` and then jumps.
+fn call(prog: &[isize], pc: usize) -> Option<(usize, usize)> {
+    let store = decode(prog, pc)?;
+    let (a, b) = (store.params.first()?, store.params.get(1)?);
+    if a.mode != ParameterMode::Immediate || b.mode != ParameterMode::Immediate {
+        return None;
+    }
+    let ret = match store.op {
+        Op::Add => a.raw.checked_add(b.raw)?,
+        Op::Mul => a.raw.checked_mul(b.raw)?,
+        _ => return None,
+    };
+    let slot = Param {
+        mode: ParameterMode::Relative,
+        raw: 0,
+    };
+    if store.params[2] != slot {
+        return None;
+    }
+
+    let jump = decode(prog, pc + store.width())?;
+    if !is_jump(&jump) || constant_condition(&jump) != Some(true) {
+        return None;
+    }
+    let target = direct(&jump)?;
+    if ret != (jump.pc + jump.width()) as isize {
+        return None;
+    }
+    Some((target, ret as usize))
+}
+
+/// Every address reachable from `entry` without following calls, and the
+/// functions it calls.
+fn walk(prog: &[isize], entry: usize) -> (BTreeSet<usize>, Vec<usize>) {
+    let mut seen = BTreeSet::new();
+    let mut calls = Vec::new();
+    let mut queue = vec![entry];
+
+    while let Some(pc) = queue.pop() {
+        if !seen.insert(pc) {
+            continue;
+        }
+        if let Some((target, ret)) = call(prog, pc) {
+            calls.push(target);
+            queue.push(ret);
+            continue;
+        }
+        let ins = match decode(prog, pc) {
+            Some(ins) => ins,
+            None => continue,
+        };
+        let next = pc + ins.width();
+
+        if ins.op == Op::Halt {
+            continue;
+        }
+        if is_jump(&ins) {
+            let taken = constant_condition(&ins);
+            if taken != Some(true) {
+                queue.push(next);
+            }
+            if taken != Some(false) {
+                queue.extend(direct(&ins));
+            }
+            continue;
+        }
+        queue.push(next);
+    }
+
+    (seen, calls)
+}
+
+/// The frame size, if the function only moves the relative base on the way
+/// in and straight before returning.
+fn frame(prog: &[isize], entry: usize, pcs: &BTreeSet<usize>) -> Option<isize> {
+    let n = match decode(prog, entry) {
+        Some(ins)
+            if ins.op == Op::AdjustBase
+                && ins.params[0].mode == ParameterMode::Immediate
+                && ins.params[0].raw > 0 =>
+        {
+            ins.params[0].raw
+        }
+        _ => 0,
+    };
+
+    let fixed = pcs
+        .iter()
+        .filter(|&&pc| pc != entry || n == 0)
+        .all(|&pc| match decode(prog, pc) {
+            Some(ins) if ins.op == Op::AdjustBase => {
+                let returns = decode(prog, pc + ins.width()).is_some_and(|j| is_return(&j));
+                let p = ins.params[0];
+                n > 0 && p.mode == ParameterMode::Immediate && p.raw == -n && returns
+            }
+            _ => true,
+        });
+
+    if fixed {
+        Some(n)
+    } else {
+        None
+    }
+}
+
+/// what `p` is called in a function with the given frame
+fn name(frame: Option<isize>, p: Param) -> Expr {
+    match (p.mode, frame) {
+        (ParameterMode::Immediate, _) => Expr::Const(p.raw),
+        (ParameterMode::Position, _) => Expr::Mem(p.raw),
+        (ParameterMode::Relative, Some(_)) if p.raw >= 0 => Expr::Arg(p.raw),
+        (ParameterMode::Relative, Some(n)) if p.raw >= -n => Expr::Local(p.raw + n),
+        (ParameterMode::Relative, _) => Expr::Rel(p.raw),
+    }
+}
+
+struct Node {
+    end: usize,
+    kind: Kind,
+}
+
+enum Kind {
+    Stmt(Stmt),
+    /// taken always if there's no condition
+    Jump(Option<Expr>, Target),
+    /// nothing worth showing, like the `arb` that sets up a frame
+    Skip,
+}
+
+/// one node per instruction of a function, or per call or return sequence
+fn lower(
+    prog: &[isize],
+    entry: usize,
+    pcs: &BTreeSet<usize>,
+    frames: &BTreeMap<usize, Option<isize>>,
+) -> BTreeMap<usize, Node> {
+    let frame = frames[&entry];
+    let mut nodes = BTreeMap::new();
+    let mut absorbed = Vec::new();
+
+    for &pc in pcs {
+        if let Some((target, ret)) = call(prog, pc) {
+            let takes = frames.get(&target).copied().flatten().unwrap_or(0);
+            let args = (1..takes)
+                .map(|k| {
+                    let p = Param {
+                        mode: ParameterMode::Relative,
+                        raw: k,
+                    };
+                    name(frame, p)
+                })
+                .collect();
+            let kind = Kind::Stmt(Stmt::Call(target, args));
+            nodes.insert(pc, Node { end: ret, kind });
+            continue;
+        }
+
+        let ins = match decode(prog, pc) {
+            Some(ins) => ins,
+            None => {
+                let word = prog.get(pc).copied().unwrap_or(0);
+                let kind = Kind::Stmt(Stmt::Invalid(word));
+                nodes.insert(pc, Node { end: pc + 1, kind });
+                continue;
+            }
+        };
+        let mut end = pc + ins.width();
+        let operand = |n: usize| name(frame, ins.params[n]);
+
+        let kind = match ins.op {
+            Op::Add | Op::Mul | Op::LessThan | Op::Equals => {
+                let op = match ins.op {
+                    Op::Add => BinOp::Add,
+                    Op::Mul => BinOp::Mul,
+                    Op::LessThan => BinOp::Lt,
+                    _ => BinOp::Eq,
+                };
+                Kind::Stmt(Stmt::Assign(operand(2), binary(op, operand(0), operand(1))))
+            }
+            Op::Input => Kind::Stmt(Stmt::Input(operand(0))),
+            Op::Output => Kind::Stmt(Stmt::Output(operand(0))),
+            Op::AdjustBase if frame.is_some() && pc == entry => Kind::Skip,
+            Op::AdjustBase if frame.is_some() => {
+                // the frame check saw a return straight after it
+                absorbed.push(end);
+                end += 2;
+                Kind::Stmt(Stmt::Return)
+            }
+            Op::AdjustBase => Kind::Stmt(Stmt::AdjustBase(operand(0))),
+            Op::JumpIfTrue | Op::JumpIfFalse if frame == Some(0) && is_return(&ins) => {
+                Kind::Stmt(Stmt::Return)
+            }
+            Op::JumpIfTrue | Op::JumpIfFalse => {
+                let target = match direct(&ins) {
+                    Some(t) => Target::Direct(t),
+                    None => Target::Indirect(operand(1)),
+                };
+                match constant_condition(&ins) {
+                    Some(true) => Kind::Jump(None, target),
+                    Some(false) => Kind::Skip,
+                    None if ins.op == Op::JumpIfTrue => Kind::Jump(Some(operand(0)), target),
+                    None => Kind::Jump(Some(not(operand(0))), target),
+                }
+            }
+            Op::Halt => Kind::Stmt(Stmt::Halt),
+        };
+        nodes.insert(pc, Node { end, kind });
+    }
+
+    for pc in absorbed {
+        nodes.remove(&pc);
+    }
+    nodes
+}
+
+/// Lays a function's nodes out in address order, turning the jumps that
+/// make up branches and loops into structure.
+struct Structurer<'a> {
+    nodes: &'a BTreeMap<usize, Node>,
+    /// addresses some jump goes to
+    targets: BTreeSet<usize>,
+}
+
+impl<'a> Structurer<'a> {
+    fn new(nodes: &'a BTreeMap<usize, Node>) -> Self {
+        let targets = nodes
+            .values()
+            .filter_map(|node| match node.kind {
+                Kind::Jump(_, Target::Direct(t)) => Some(t),
+                _ => None,
+            })
+            .collect();
+        Structurer { nodes, targets }
+    }
+
+    /// The nodes from `lo` up to `hi`. A loop starting at `lo` has already
+    /// been found if `in_loop` is set.
+    fn region(&self, lo: usize, hi: usize, in_loop: bool) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut pc = lo;
+
+        while let Some((&at, node)) = self.nodes.range(pc..hi).next() {
+            if !(in_loop && at == lo) {
+                if let Some((stmt, next)) = self.looped(at, hi) {
+                    lines.push(Line { pc: at, stmt });
+                    pc = next;
+                    continue;
+                }
+            }
+
+            // overlapping instructions come out one after the other
+            pc = at + 1;
+            let stmt = match &node.kind {
+                Kind::Skip => continue,
+                Kind::Stmt(stmt) => stmt.clone(),
+                // a jump into its own instruction can't be structured
+                Kind::Jump(Some(cond), Target::Direct(t)) if *t > node.end && *t <= hi => {
+                    let (stmt, next) = self.branch(node, cond, *t, hi);
+                    pc = next;
+                    stmt
+                }
+                Kind::Jump(cond, target) => Stmt::Goto(cond.clone(), target.clone()),
+            };
+            lines.push(Line { pc: at, stmt });
+        }
+
+        lines
+    }
+
+    /// A loop back to `head`, from the last jump before `hi` that goes there,
+    /// and where to carry on after it.
+    fn looped(&self, head: usize, hi: usize) -> Option<(Stmt, usize)> {
+        let (&back, node) = self.nodes.range(head..hi).rev().find(|(&pc, node)| {
+            matches!(node.kind, Kind::Jump(_, Target::Direct(t)) if t == head)
+                && !self.targets.contains(&pc)
+                && node.end <= hi
+        })?;
+        let after = node.end;
+
+        let stmt = match &node.kind {
+            Kind::Jump(Some(cond), _) => Stmt::DoWhile(self.region(head, back, true), cond.clone()),
+            _ => match &self.nodes[&head].kind {
+                Kind::Jump(Some(cond), Target::Direct(exit))
+                    if *exit == after && self.nodes[&head].end <= back =>
+                {
+                    let body = self.region(self.nodes[&head].end, back, false);
+                    Stmt::While(not(cond.clone()), body)
+                }
+                _ => Stmt::While(Expr::Const(1), self.region(head, back, true)),
+            },
+        };
+        Some((stmt, after))
+    }
+
+    /// An `if` for a jump over the code up to `target`, with an `else` when
+    /// that code ends by jumping over more, and where to carry on after it.
+    fn branch(&self, node: &Node, cond: &Expr, target: usize, hi: usize) -> (Stmt, usize) {
+        let last = self.nodes.range(node.end..target).next_back();
+        if let Some((&pc, last)) = last {
+            if let Kind::Jump(None, Target::Direct(end)) = last.kind {
+                if end >= target && end <= hi && !self.targets.contains(&pc) {
+                    let then = self.region(node.end, pc, false);
+                    let otherwise = self.region(target, end, false);
+                    if then.is_empty() {
+                        return (Stmt::If(cond.clone(), otherwise, then), end);
+                    }
+                    return (Stmt::If(not(cond.clone()), then, otherwise), end);
+                }
+            }
+        }
+        let then = self.region(node.end, target, false);
+        (Stmt::If(not(cond.clone()), then, Vec::new()), target)
+    }
+}
+
+pub fn decompile(prog: &[isize]) -> Decompiled {
+    let mut reached = BTreeMap::new();
+    let mut pending = vec![0];
+    while let Some(entry) = pending.pop() {
+        if reached.contains_key(&entry) {
+            continue;
+        }
+        let (pcs, calls) = walk(prog, entry);
+        reached.insert(entry, pcs);
+        pending.extend(calls);
+    }
+
+    let frames: BTreeMap<_, _> = reached
+        .iter()
+        .map(|(&entry, pcs)| (entry, frame(prog, entry, pcs)))
+        .collect();
+
+    let functions = reached
+        .iter()
+        .map(|(&entry, pcs)| {
+            let nodes = lower(prog, entry, pcs, &frames);
+            let body = Structurer::new(&nodes).region(0, usize::MAX, false);
+            Function {
+                entry,
+                frame: frames[&entry],
+                body,
+            }
+        })
+        .collect();
+
+    Decompiled { functions }
+}
+
+fn function_name(entry: usize) -> String {
+    if entry == 0 {
+        "main".to_string()
+    } else {
+        format!("f{}", entry)
+    }
+}
+
+fn gotos(lines: &[Line], labels: &mut BTreeSet<usize>) {
+    for line in lines {
+        match &line.stmt {
+            Stmt::Goto(_, Target::Direct(t)) => {
+                labels.insert(*t);
+            }
+            Stmt::If(_, then, otherwise) => {
+                gotos(then, labels);
+                gotos(otherwise, labels);
+            }
+            Stmt::While(_, body) | Stmt::DoWhile(body, _) => gotos(body, labels),
+            _ => {}
+        }
+    }
+}
+
+fn block(
+    f: &mut fmt::Formatter,
+    lines: &[Line],
+    depth: usize,
+    labels: &BTreeSet<usize>,
+) -> fmt::Result {
+    let pad = "    ".repeat(depth);
+    for line in lines {
+        if labels.contains(&line.pc) {
+            writeln!(f, "{}L{}:", &pad[4..], line.pc)?;
+        }
+        match &line.stmt {
+            Stmt::Assign(dest, value) => writeln!(f, "{}{} = {};", pad, dest, value)?,
+            Stmt::Input(dest) => writeln!(f, "{}{} = input();", pad, dest)?,
+            Stmt::Output(value) => writeln!(f, "{}output({});", pad, value)?,
+            Stmt::AdjustBase(Expr::Const(v)) if *v < 0 => writeln!(f, "{}rb -= {};", pad, -v)?,
+            Stmt::AdjustBase(by) => writeln!(f, "{}rb += {};", pad, by)?,
+            Stmt::Call(target, args) => {
+                let args: Vec<_> = args.iter().map(|a| a.to_string()).collect();
+                writeln!(f, "{}{}({});", pad, function_name(*target), args.join(", "))?
+            }
+            Stmt::Return => writeln!(f, "{}return;", pad)?,
+            Stmt::Halt => writeln!(f, "{}halt;", pad)?,
+            Stmt::Invalid(word) => writeln!(f, "{}/* {} is not an instruction */", pad, word)?,
+            Stmt::Goto(cond, target) => {
+                write!(f, "{}", pad)?;
+                if let Some(cond) = cond {
+                    write!(f, "if ({}) ", cond)?;
+                }
+                match target {
+                    Target::Direct(t) => writeln!(f, "goto L{};", t)?,
+                    Target::Indirect(e) => writeln!(f, "goto *{};", e)?,
+                }
+            }
+            Stmt::If(cond, then, otherwise) => {
+                writeln!(f, "{}if ({}) {{", pad, cond)?;
+                block(f, then, depth + 1, labels)?;
+                if !otherwise.is_empty() {
+                    writeln!(f, "{}}} else {{", pad)?;
+                    block(f, otherwise, depth + 1, labels)?;
+                }
+                writeln!(f, "{}}}", pad)?;
+            }
+            Stmt::While(cond, body) => {
+                writeln!(f, "{}while ({}) {{", pad, cond)?;
+                block(f, body, depth + 1, labels)?;
+                writeln!(f, "{}}}", pad)?;
+            }
+            Stmt::DoWhile(body, cond) => {
+                writeln!(f, "{}do {{", pad)?;
+                block(f, body, depth + 1, labels)?;
+                writeln!(f, "{}}} while ({});", pad, cond)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for Decompiled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut labels = BTreeSet::new();
+        for func in &self.functions {
+            gotos(&func.body, &mut labels);
+        }
+
+        for (i, func) in self.functions.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            let params: Vec<_> = (1..func.frame.unwrap_or(0))
+                .map(|k| Expr::Local(k).to_string())
+                .collect();
+            writeln!(
+                f,
+                "fn {}({}) {{",
+                function_name(func.entry),
+                params.join(", ")
+            )?;
+            block(f, &func.body, 1, &labels)?;
+            writeln!(f, "}}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_expressions() {
+        let (x, y) = (Expr::Mem(9), Expr::Local(1));
+        assert_eq!(x, binary(BinOp::Add, Expr::Const(0), x.clone()));
+        assert_eq!(x, binary(BinOp::Mul, x.clone(), Expr::Const(1)));
+        assert_eq!(
+            Expr::Const(1),
+            binary(BinOp::Lt, Expr::Const(3), Expr::Const(4))
+        );
+        assert_eq!(
+            "m[9] - 3",
+            binary(BinOp::Add, Expr::Const(-3), x.clone()).to_string()
+        );
+
+        let sum = binary(BinOp::Add, x.clone(), y.clone());
+        let product = binary(BinOp::Mul, sum.clone(), y);
+        assert_eq!("(m[9] + a1) * a1", product.to_string());
+        assert_eq!(
+            "m[9] + a1 < 2",
+            binary(BinOp::Lt, sum, Expr::Const(2)).to_string()
+        );
+        assert_eq!(
+            "!(m[9] * 2)",
+            not(binary(BinOp::Mul, x.clone(), Expr::Const(2))).to_string()
+        );
+        assert_eq!(x, not(not(x.clone())));
+        assert_eq!("m[rb-2]", Expr::Rel(-2).to_string());
+    }
+
+    #[test]
+    fn test_loops() {
+        // count down from the input
+        let prog = &[3, 20, 1006, 20, 14, 4, 20, 1001, 20, -1, 20, 1105, 1, 2, 99];
+        assert_eq!(
+            "fn main() {
+    m[20] = input();
+    while (m[20]) {
+        output(m[20]);
+        m[20] = m[20] - 1;
+    }
+    halt;
+}
+",
+            decompile(prog).to_string()
+        );
+
+        // the same, checking at the bottom
+        let prog = &[3, 20, 4, 20, 1001, 20, -1, 20, 1005, 20, 2, 99];
+        assert_eq!(
+            "fn main() {
+    m[20] = input();
+    do {
+        output(m[20]);
+        m[20] = m[20] - 1;
+    } while (m[20]);
+    halt;
+}
+",
+            decompile(prog).to_string()
+        );
+    }
+
+    #[test]
+    fn test_branches() {
+        // output whichever input is larger, and 0 after an invalid cell
+        let prog = &[
+            3, 30, 3, 31, 7, 30, 31, 32, 1006, 32, 18, 4, 31, 1106, 0, 20, 33, 33, 4, 30, 104, 0,
+            1105, 1, 26, 99, 42,
+        ];
+        assert_eq!(
+            "fn main() {
+    m[30] = input();
+    m[31] = input();
+    m[32] = m[30] < m[31];
+    if (m[32]) {
+        output(m[31]);
+    } else {
+        output(m[30]);
+    }
+    output(0);
+    goto L26;
+L26:
+    /* 42 is not an instruction */
+}
+",
+            decompile(prog).to_string()
+        );
+    }
+
+    #[test]
+    fn test_tangled_jumps() {
+        // a jump into its own instruction, and a loop whose jump back runs
+        // past the end of the region it's in
+        for prog in &[
+            &[
+                2101, 203, -5, 23, 1205, 6, 5, 9, 36, 22101, 9, 1, 206, 1101, 34, 206, 6, 1106, 19,
+                39, 1, 9,
+            ][..],
+            &[
+                5, 3, 7, 20009, 12199, 21008, 109, 13, 21, 21005, -1, 22, 2, 14, 10002, 10, 7,
+                12005, 12201, -4, 21105, -5, 16,
+            ][..],
+        ] {
+            assert!(decompile(prog).to_string().starts_with("fn main() {"));
+        }
+    }
+
+    #[test]
+    fn test_functions() {
+        let day9 = include_str!("../../day9/src/input")
+            .parse::<crate::Program>()
+            .unwrap();
+        let decompiled = decompile(&day9);
+        assert_eq!(
+            vec![(0, None), (922, Some(3))],
+            decompiled
+                .functions
+                .iter()
+                .map(|f| (f.entry, f.frame))
+                .collect::<Vec<_>>()
+        );
+        assert!(decompiled.to_string().ends_with(
+            "
+fn f922(a1, a2) {
+    m[63] = a1 < 3;
+    if (!m[63]) {
+        c1 = a1 - 1;
+        f922(c1, c2);
+        a2 = c1;
+        c1 = a1 - 3;
+        f922(c1, c2);
+        a1 = c1 + a2;
+    } else {
+        a1 = a1;
+    }
+    return;
+}
+"
+        ));
+    }
+}
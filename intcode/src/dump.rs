@@ -0,0 +1,315 @@
+//! Memory laid out in rows of words with their addresses, and the difference
+//! between two memories, a row before and after for each that changed:
+//!
+//! ```text
+//! -0:    1    0    0    3
+//! +0: 3500   12    2    3
+//!     ^^^^ ^^^^ ^^^^
+//! 3 cells changed
+//! ```
+//!
+//! Memory past the end of either reads as zero, as it does to a machine.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// how rows are laid out and cells written
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    columns: usize,
+    hex: bool,
+    /// widest cell
+    cell: usize,
+    /// widest address
+    addr: usize,
+}
+
+impl Layout {
+    fn new(columns: usize, hex: bool, memories: &[&[isize]]) -> Self {
+        let mut layout = Layout {
+            columns,
+            hex,
+            cell: 1,
+            addr: 1,
+        };
+        let len = memories.iter().map(|m| m.len()).max().unwrap_or(0);
+        layout.cell = memories
+            .iter()
+            .flat_map(|m| m.iter())
+            .map(|&v| layout.format(v).len())
+            .max()
+            .unwrap_or(1);
+        layout.addr = layout
+            .address(len.saturating_sub(1) / columns * columns)
+            .len();
+        layout
+    }
+
+    fn format(&self, v: isize) -> String {
+        match (self.hex, v < 0) {
+            (false, _) => format!("{}", v),
+            (true, false) => format!("{:x}", v),
+            (true, true) => format!("-{:x}", v.unsigned_abs()),
+        }
+    }
+
+    fn address(&self, addr: usize) -> String {
+        if self.hex {
+            format!("{:x}", addr)
+        } else {
+            format!("{}", addr)
+        }
+    }
+
+    fn rows(&self, len: usize) -> usize {
+        len.div_ceil(self.columns)
+    }
+
+    /// row `row` of `memory`, reading zeros past its end
+    fn row<'a>(&self, memory: &'a [isize], row: usize) -> impl Iterator<Item = isize> + 'a {
+        let start = row * self.columns;
+        (start..start + self.columns).map(move |addr| memory.get(addr).copied().unwrap_or(0))
+    }
+
+    /// `prefix`, the row's address and its cells, each changed one between
+    /// `mark` and a reset
+    fn write_row(
+        &self,
+        f: &mut fmt::Formatter,
+        prefix: &str,
+        row: usize,
+        cells: impl Iterator<Item = (isize, bool)>,
+        mark: &str,
+    ) -> fmt::Result {
+        let addr = self.address(row * self.columns);
+        write!(f, "{}{:>w$}:", prefix, addr, w = self.addr)?;
+        for (v, changed) in cells {
+            let cell = format!("{:>w$}", self.format(v), w = self.cell);
+            if changed && !mark.is_empty() {
+                write!(f, " {}{}\x1b[0m", mark, cell)?;
+            } else {
+                write!(f, " {}", cell)?;
+            }
+        }
+        writeln!(f)
+    }
+}
+
+/// The words of a memory, `columns` to a row. A run of rows that are all
+/// zero is shown as one `*`.
+pub struct Dump<'a> {
+    memory: &'a [isize],
+    columns: usize,
+    hex: bool,
+}
+
+impl<'a> Dump<'a> {
+    pub fn new(memory: &'a [isize]) -> Self {
+        Dump {
+            memory,
+            columns: 8,
+            hex: false,
+        }
+    }
+
+    pub fn columns(mut self, columns: usize) -> Self {
+        self.columns = columns.max(1);
+        self
+    }
+
+    /// addresses and words in hexadecimal
+    pub fn hex(mut self, hex: bool) -> Self {
+        self.hex = hex;
+        self
+    }
+}
+
+impl fmt::Display for Dump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let layout = Layout::new(self.columns, self.hex, &[self.memory]);
+        let mut skipping = false;
+
+        for row in 0..layout.rows(self.memory.len()) {
+            let zero = layout.row(self.memory, row).all(|v| v == 0);
+            // the first of a run is shown, so it's clear what's left out
+            if zero && row > 0 && layout.row(self.memory, row - 1).all(|v| v == 0) {
+                if !skipping {
+                    writeln!(f, "*")?;
+                    skipping = true;
+                }
+                continue;
+            }
+            skipping = false;
+            // the last row stops at the end of memory
+            let end = self.memory.len().min((row + 1) * self.columns);
+            let cells = self.memory[row * self.columns..end]
+                .iter()
+                .map(|&v| (v, false));
+            layout.write_row(f, "", row, cells, "")?;
+        }
+        Ok(())
+    }
+}
+
+/// What changed between two memories, such as a program before and after
+/// it runs, or after two runs on different inputs.
+pub struct Diff<'a> {
+    before: &'a [isize],
+    after: &'a [isize],
+    columns: usize,
+    hex: bool,
+    color: bool,
+}
+
+impl<'a> Diff<'a> {
+    pub fn new(before: &'a [isize], after: &'a [isize]) -> Self {
+        Diff {
+            before,
+            after,
+            columns: 8,
+            hex: false,
+            color: false,
+        }
+    }
+
+    pub fn columns(mut self, columns: usize) -> Self {
+        self.columns = columns.max(1);
+        self
+    }
+
+    /// addresses and words in hexadecimal
+    pub fn hex(mut self, hex: bool) -> Self {
+        self.hex = hex;
+        self
+    }
+
+    /// Colour the changed cells with ANSI escapes instead of marking them
+    /// on a line of their own.
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// each cell that differs, as its address and the values before and after
+    pub fn changes(&self) -> impl Iterator<Item = (usize, isize, isize)> + '_ {
+        let len = self.before.len().max(self.after.len());
+        (0..len).filter_map(move |addr| {
+            let before = self.before.get(addr).copied().unwrap_or(0);
+            let after = self.after.get(addr).copied().unwrap_or(0);
+            Some((addr, before, after)).filter(|_| before != after)
+        })
+    }
+}
+
+impl fmt::Display for Diff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let layout = Layout::new(self.columns, self.hex, &[self.before, self.after]);
+        let len = self.before.len().max(self.after.len());
+        let (red, green) = if self.color {
+            ("\x1b[31m", "\x1b[32m")
+        } else {
+            ("", "")
+        };
+
+        for row in 0..layout.rows(len) {
+            let changed: Vec<bool> = layout
+                .row(self.before, row)
+                .zip(layout.row(self.after, row))
+                .map(|(b, a)| b != a)
+                .collect();
+            if !changed.contains(&true) {
+                continue;
+            }
+
+            let before = layout.row(self.before, row).zip(changed.iter().copied());
+            layout.write_row(f, "-", row, before, red)?;
+            let after = layout.row(self.after, row).zip(changed.iter().copied());
+            layout.write_row(f, "+", row, after, green)?;
+
+            if !self.color {
+                let marks: String = changed
+                    .iter()
+                    .map(|&c| {
+                        let mark = if c { "^" } else { " " };
+                        format!(" {}", mark.repeat(layout.cell))
+                    })
+                    .collect();
+                writeln!(f, "{:w$}{}", "", marks.trim_end(), w = 2 + layout.addr)?;
+            }
+        }
+
+        match self.changes().count() {
+            0 => writeln!(f, "no cells changed"),
+            1 => writeln!(f, "1 cell changed"),
+            n => writeln!(f, "{} cells changed", n),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    #[test]
+    fn test_dump() {
+        let mut memory = vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50];
+        assert_eq!(
+            "0:  1  9 10  3\n4:  2  3 11  0\n8: 99 30 40 50\n",
+            Dump::new(&memory).columns(4).to_string()
+        );
+        assert_eq!(
+            "0:  1  9  a  3  2\n5:  3  b  0 63 1e\na: 28 32\n",
+            Dump::new(&memory).columns(5).hex(true).to_string()
+        );
+
+        // runs of zero rows are cut short, but not the first of them
+        memory.resize(40, 0);
+        memory.push(-7);
+        assert_eq!(
+            " 0:  1  9 10  3  2  3 11  0\n 8: 99 30 40 50  0  0  0  0\n\
+             16:  0  0  0  0  0  0  0  0\n*\n40: -7\n",
+            Dump::new(&memory).to_string()
+        );
+        assert_eq!("", Dump::new(&[]).to_string());
+    }
+
+    #[test]
+    fn test_diff() {
+        let before = [1, 0, 0, 3, 2, 3, 11, 0, 99];
+        let after = [3500, 12, 2, 3, 2, 3, 11, 0, 99, 0, 5];
+        let diff = Diff::new(&before, &after).columns(4);
+
+        assert_eq!(
+            vec![(0, 1, 3500), (1, 0, 12), (2, 0, 2), (10, 0, 5)],
+            diff.changes().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            concat!(
+                "-0:    1    0    0    3\n",
+                "+0: 3500   12    2    3\n",
+                "    ^^^^ ^^^^ ^^^^\n",
+                "-8:   99    0    0    0\n",
+                "+8:   99    0    5    0\n",
+                "              ^^^^\n",
+                "4 cells changed\n",
+            ),
+            diff.to_string()
+        );
+
+        let colored = Diff::new(&[1, 2], &[1, 4]).color(true).to_string();
+        assert_eq!(
+            "-0: 1 \x1b[31m2\x1b[0m 0 0 0 0 0 0\n\
+             +0: 1 \x1b[32m4\x1b[0m 0 0 0 0 0 0\n\
+             1 cell changed\n",
+            colored
+        );
+        assert_eq!(
+            "no cells changed\n",
+            Diff::new(&before, &before).to_string()
+        );
+    }
+}
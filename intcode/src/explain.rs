@@ -0,0 +1,234 @@
+//! Running a program one instruction at a time, with a sentence saying what
+//! each did in terms of the values it used rather than the raw cells:
+//!
+//! ```text
+//! pc 4: add 3 (from [100]) and 1 (immediate), store 4 into [100]
+//! ```
+
+use crate::decode::{decode, Op};
+use crate::machine::{Error, Machine, ParameterMode, State};
+use crate::opcode::Param;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// `rb+3` or `rb-2`
+fn offset(raw: isize) -> String {
+    if raw < 0 {
+        format!("rb-{}", -raw)
+    } else {
+        format!("rb+{}", raw)
+    }
+}
+
+/// a value read by `p` and where it came from, such as `3 (from [100])`
+fn source(m: &Machine, p: Param) -> Result<(isize, String), Error> {
+    let v = m.value(p)?;
+    let text = match p.mode {
+        ParameterMode::Position => format!("{} (from [{}])", v, p.raw),
+        ParameterMode::Immediate => format!("{} (immediate)", v),
+        ParameterMode::Relative => {
+            format!("{} (from [{}] at {})", v, m.destination(p)?, offset(p.raw))
+        }
+    };
+    Ok((v, text))
+}
+
+/// the address `p` writes to, such as `[100]`
+fn target(m: &Machine, p: Param) -> Result<(isize, String), Error> {
+    let addr = m.destination(p)?;
+    let text = match p.mode {
+        ParameterMode::Relative => format!("[{}] at {}", addr, offset(p.raw)),
+        _ => format!("[{}]", addr),
+    };
+    Ok((addr, text))
+}
+
+/// Run the instruction at the machine's pc and say what it did. Errors are
+/// the machine's own, with nothing to explain.
+pub fn step(m: &mut Machine) -> Result<(State, String), Error> {
+    let pc = m.pc();
+    let instruction = match decode(m.memory(), pc) {
+        Some(instruction) => instruction,
+        None => {
+            // registered, or the step fails
+            let code = m.get(pc) % 100;
+            let state = m.step()?;
+            return Ok((state, format!("pc {}: run registered opcode {}", pc, code)));
+        }
+    };
+
+    let op = instruction.op;
+    let dest = op.destination();
+    let mut sources = Vec::new();
+    let mut written = None;
+    for (n, &p) in instruction.params.iter().enumerate() {
+        if Some(n) == dest {
+            written = Some(target(m, p)?);
+        } else {
+            sources.push(source(m, p)?);
+        }
+    }
+
+    let state = m.step()?;
+    let result = match &written {
+        Some((addr, _)) if state != State::NeedInput => m.load(*addr)?,
+        _ => 0,
+    };
+    let into = written.map(|(_, text)| text).unwrap_or_default();
+    let (a, b) = match &sources[..] {
+        [a, b, ..] => (a.clone(), b.clone()),
+        [a] => (a.clone(), (0, String::new())),
+        [] => ((0, String::new()), (0, String::new())),
+    };
+
+    let text = match op {
+        Op::Add => format!("add {} and {}, store {} into {}", a.1, b.1, result, into),
+        Op::Mul => format!(
+            "multiply {} by {}, store {} into {}",
+            a.1, b.1, result, into
+        ),
+        Op::LessThan => {
+            let is = if a.0 < b.0 { "is" } else { "is not" };
+            format!(
+                "store {} into {}, as {} {} less than {}",
+                result, into, a.1, is, b.1
+            )
+        }
+        Op::Equals => {
+            let equals = if a.0 == b.0 {
+                "equals"
+            } else {
+                "does not equal"
+            };
+            format!(
+                "store {} into {}, as {} {} {}",
+                result, into, a.1, equals, b.1
+            )
+        }
+        Op::Input if state == State::NeedInput => "wait for input".into(),
+        Op::Input => format!("read {} from input, store it into {}", result, into),
+        Op::Output => format!("output {}", a.1),
+        Op::JumpIfTrue | Op::JumpIfFalse => {
+            let zero = if a.0 == 0 { "is zero" } else { "is not zero" };
+            if (a.0 != 0) == (op == Op::JumpIfTrue) {
+                format!("jump to {}, as {} {}", b.1, a.1, zero)
+            } else {
+                format!("carry on, as {} {}", a.1, zero)
+            }
+        }
+        Op::AdjustBase => format!(
+            "add {} to the relative base, making it {}",
+            a.1,
+            m.relative_base()
+        ),
+        Op::Halt => "halt".into(),
+    };
+    Ok((state, format!("pc {}: {}", pc, text)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::opcode::{Context, Opcode};
+    use alloc::vec;
+
+    /// every sentence until the machine stops
+    fn explain(m: &mut Machine) -> Vec<String> {
+        let mut lines = Vec::new();
+        loop {
+            let (state, text) = step(m).unwrap();
+            lines.push(text);
+            if let State::Halt | State::NeedInput = state {
+                return lines;
+            }
+        }
+    }
+
+    #[test]
+    fn test_explain() {
+        let prog = [
+            1001, 100, 1, 100, // add [100], #1 -> [100]
+            1002, 100, 2, 101, // mul [100], #2 -> [101]
+            3, 102, // in -> [102]
+            7, 102, 101, 103, // lt [102], [101] -> [103]
+            108, 4, 101, 104, // eq #4, [101] -> [104]
+            1005, 103, 24, // jnz [103], #24
+            99, 0, 0, // never reached
+            1006, 104, 29, // jz [104], #29
+            4, 100, // skipped
+            109, -7, // arb #-7
+            22201, 107, 108, 109, // add [rb+107], [rb+108] -> [rb+109]
+            204, 109, // out [rb+109]
+            99,
+        ];
+        let mut memory = prog.to_vec();
+        memory.resize(100, 0);
+        memory.push(3);
+        let mut m = Machine::new(&memory, &[5]);
+
+        assert_eq!(
+            vec![
+                "pc 0: add 3 (from [100]) and 1 (immediate), store 4 into [100]",
+                "pc 4: multiply 4 (from [100]) by 2 (immediate), store 8 into [101]",
+                "pc 8: read 5 from input, store it into [102]",
+                "pc 10: store 1 into [103], as 5 (from [102]) is less than 8 (from [101])",
+                "pc 14: store 0 into [104], as 4 (immediate) does not equal 8 (from [101])",
+                "pc 18: jump to 24 (immediate), as 1 (from [103]) is not zero",
+                "pc 24: jump to 29 (immediate), as 0 (from [104]) is zero",
+                "pc 29: add -7 (immediate) to the relative base, making it -7",
+                "pc 31: add 4 (from [100] at rb+107) and 8 (from [101] at rb+108), \
+                 store 12 into [102] at rb+109",
+                "pc 35: output 12 (from [102] at rb+109)",
+                "pc 37: halt",
+            ],
+            explain(&mut m)
+        );
+    }
+
+    #[test]
+    fn test_waiting_and_errors() {
+        let mut m = Machine::new(&[1105, 0, 3, 3, 0, 4, 0, 99], &[]);
+        assert_eq!(
+            vec![
+                "pc 0: carry on, as 0 (immediate) is zero",
+                "pc 3: wait for input",
+            ],
+            explain(&mut m)
+        );
+        m.push_input(7);
+        assert_eq!(
+            vec![
+                "pc 3: read 7 from input, store it into [0]",
+                "pc 5: output 7 (from [0])",
+                "pc 7: halt",
+            ],
+            explain(&mut m)
+        );
+
+        let mut m = Machine::new(&[42], &[]);
+        assert_eq!(Err(Error::InvalidOpcode(0, 42)), step(&mut m));
+    }
+
+    struct Nop;
+
+    impl Opcode for Nop {
+        fn arity(&self) -> usize {
+            0
+        }
+
+        fn execute(&self, _: &mut Context, _: &[Param]) -> Result<State, Error> {
+            Ok(State::Default)
+        }
+    }
+
+    #[test]
+    fn test_registered() {
+        let mut m = Machine::new(&[42, 99], &[]);
+        m.register(42, Nop);
+        assert_eq!(
+            vec!["pc 0: run registered opcode 42", "pc 1: halt"],
+            explain(&mut m)
+        );
+    }
+}
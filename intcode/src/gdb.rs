@@ -0,0 +1,712 @@
+//! A GDB remote serial protocol stub, so gdb or any other RSP client can
+//! debug a `Machine` over TCP:
+//!
+//! ```text
+//! $ intcode gdb day9/src/input --input 1 --port 1234
+//! $ gdb -ex 'target remote localhost:1234'
+//! ```
+//!
+//! As far as the client knows memory is byte addressed, each cell eight
+//! bytes little-endian, so cell n is at address 8n. The two registers, `pc`
+//! and `rb` (the relative base), are byte addresses the same way. Outputs are
+//! printed on the client's console as they're made, `monitor input 1,2`
+//! queues input for a program that's waiting on it, and halting counts as
+//! the program exiting. Cells below zero aren't mapped.
+
+use crate::machine::{Machine, State};
+use crate::program::Program;
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+
+/// bytes to a cell in the client's address space
+pub const WORD: u64 = 8;
+
+/// the longest packet the client may send us
+const PACKET_SIZE: usize = 0x4000;
+
+/// steps between looking for an interrupt while the program runs
+const CHECK_EVERY: usize = 10_000;
+
+/// how many cells past the end of memory the client may write; any further
+/// and a typo could grow memory without bound
+const MAX_GROWTH: usize = 1 << 20;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.intcode.core">
+    <reg name="pc" bitsize="64" type="code_ptr" regnum="0"/>
+    <reg name="rb" bitsize="64" type="data_ptr"/>
+  </feature>
+</target>
+"#;
+
+// stop replies, by the signal they report
+const TRAP: &str = "S05";
+const INTERRUPT: &str = "S02";
+const ILLEGAL: &str = "S04";
+/// SIGTTIN, for a program waiting on input
+const WAITING: &str = "S15";
+const EXITED: &str = "W00";
+
+const MALFORMED: &str = "E01";
+const BAD_ADDRESS: &str = "E02";
+
+const MONITOR_HELP: &str = "\
+monitor commands:
+  input 1,2   queue values for the program's input instructions
+";
+
+pub struct Stub {
+    machine: Machine,
+    /// cells to stop at before executing
+    breakpoints: BTreeSet<usize>,
+    exited: bool,
+    finished: bool,
+}
+
+impl Stub {
+    pub fn new(machine: Machine) -> Self {
+        Stub {
+            machine,
+            breakpoints: BTreeSet::new(),
+            exited: false,
+            finished: false,
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    /// whether the client has detached or killed the program
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// The replies to a packet, given without its framing, in the order
+    /// they're to be sent; console output comes before the stop it led to.
+    /// `interrupted` is asked every so often while the program runs.
+    pub fn handle(&mut self, packet: &str, interrupted: &mut dyn FnMut() -> bool) -> Vec<String> {
+        let reply = |r: Result<String, &str>| vec![r.unwrap_or_else(|e| e.to_string())];
+        if packet.is_empty() || !packet.is_ascii() {
+            return vec![String::new()];
+        }
+        let (cmd, rest) = packet.split_at(1);
+
+        match cmd {
+            "?" => vec![self.stop_reason().to_string()],
+            "g" => vec![self
+                .registers()
+                .iter()
+                .map(|r| hex(&r.to_le_bytes()))
+                .collect()],
+            "G" => reply(self.write_registers(rest)),
+            "p" => reply(self.read_register(rest)),
+            "P" => reply(self.write_register(rest)),
+            "m" => reply(self.read_memory(rest)),
+            "M" => reply(self.write_memory(rest)),
+            "c" | "s" => {
+                if !rest.is_empty() {
+                    match number(rest).and_then(cell) {
+                        Some(pc) => self.machine.set_pc(pc),
+                        None => return vec![MALFORMED.to_string()],
+                    }
+                }
+                self.resume(cmd == "s", interrupted)
+            }
+            "Z" | "z" => reply(self.breakpoint(cmd == "Z", rest)),
+            "H" | "T" => vec!["OK".to_string()],
+            "k" => {
+                self.finished = true;
+                Vec::new()
+            }
+            "D" => {
+                self.finished = true;
+                vec!["OK".to_string()]
+            }
+            "q" => self.query(rest),
+            _ => vec![String::new()],
+        }
+    }
+
+    fn stop_reason(&self) -> &'static str {
+        if self.exited {
+            EXITED
+        } else {
+            TRAP
+        }
+    }
+
+    /// pc and relative base, as byte addresses
+    fn registers(&self) -> [i64; 2] {
+        [
+            (self.machine.pc() as i64).wrapping_mul(WORD as i64),
+            (self.machine.relative_base() as i64).wrapping_mul(WORD as i64),
+        ]
+    }
+
+    fn set_register(&mut self, n: u64, v: i64) -> Result<(), &'static str> {
+        if v % WORD as i64 != 0 {
+            return Err(MALFORMED);
+        }
+        let v = v / WORD as i64;
+        match n {
+            0 => self
+                .machine
+                .set_pc(usize::try_from(v).map_err(|_| BAD_ADDRESS)?),
+            1 => self.machine.set_relative_base(v as isize),
+            _ => return Err(MALFORMED),
+        }
+        Ok(())
+    }
+
+    fn read_register(&self, rest: &str) -> Result<String, &'static str> {
+        let n = number(rest).ok_or(MALFORMED)?;
+        let r = self.registers().get(n as usize).copied().ok_or(MALFORMED)?;
+        Ok(hex(&r.to_le_bytes()))
+    }
+
+    fn write_register(&mut self, rest: &str) -> Result<String, &'static str> {
+        let (n, v) = rest.split_once('=').ok_or(MALFORMED)?;
+        let n = number(n).ok_or(MALFORMED)?;
+        self.set_register(n, word(v).ok_or(MALFORMED)?)?;
+        Ok("OK".to_string())
+    }
+
+    fn write_registers(&mut self, rest: &str) -> Result<String, &'static str> {
+        if rest.len() != 32 {
+            return Err(MALFORMED);
+        }
+        let pc = word(&rest[..16]).ok_or(MALFORMED)?;
+        let rb = word(&rest[16..]).ok_or(MALFORMED)?;
+        // check both before changing either
+        if pc % WORD as i64 != 0 || rb % WORD as i64 != 0 || pc < 0 {
+            return Err(MALFORMED);
+        }
+        self.set_register(0, pc)?;
+        self.set_register(1, rb)?;
+        Ok("OK".to_string())
+    }
+
+    fn byte(&self, addr: u64) -> u8 {
+        let v = usize::try_from(addr / WORD).map_or(0, |cell| self.machine.get(cell));
+        (v as i64).to_le_bytes()[(addr % WORD) as usize]
+    }
+
+    fn read_memory(&self, rest: &str) -> Result<String, &'static str> {
+        let (addr, len) = range(rest).ok_or(MALFORMED)?;
+        let len = len.min(PACKET_SIZE as u64 / 2);
+        let bytes: Vec<u8> = (addr..addr.saturating_add(len))
+            .map(|a| self.byte(a))
+            .collect();
+        Ok(hex(&bytes))
+    }
+
+    fn write_memory(&mut self, rest: &str) -> Result<String, &'static str> {
+        let (range_text, data) = rest.split_once(':').ok_or(MALFORMED)?;
+        let (addr, len) = range(range_text).ok_or(MALFORMED)?;
+        let data = bytes(data).ok_or(MALFORMED)?;
+        if data.len() as u64 != len {
+            return Err(MALFORMED);
+        }
+        if len == 0 {
+            return Ok("OK".to_string());
+        }
+
+        let last = addr.checked_add(len - 1).ok_or(BAD_ADDRESS)? / WORD;
+        if last > (self.machine.memory().len() + MAX_GROWTH) as u64 {
+            return Err(BAD_ADDRESS);
+        }
+        for (a, &b) in (addr..).zip(&data) {
+            let cell = (a / WORD) as usize;
+            let mut v = (self.machine.get(cell) as i64).to_le_bytes();
+            v[(a % WORD) as usize] = b;
+            self.machine.set(cell, i64::from_le_bytes(v) as isize);
+        }
+        Ok("OK".to_string())
+    }
+
+    /// software and hardware breakpoints alike; watchpoints aren't supported
+    fn breakpoint(&mut self, insert: bool, rest: &str) -> Result<String, &'static str> {
+        let mut fields = rest.split(',');
+        match fields.next() {
+            Some("0") | Some("1") => {}
+            _ => return Ok(String::new()),
+        }
+        let addr = fields.next().and_then(number).ok_or(MALFORMED)?;
+        let cell = cell(addr).ok_or(MALFORMED)?;
+        if insert {
+            self.breakpoints.insert(cell);
+        } else {
+            self.breakpoints.remove(&cell);
+        }
+        Ok("OK".to_string())
+    }
+
+    /// Run until a breakpoint, or for one instruction if `single`, and say
+    /// why it stopped.
+    fn resume(&mut self, single: bool, interrupted: &mut dyn FnMut() -> bool) -> Vec<String> {
+        if self.exited {
+            return vec![EXITED.to_string()];
+        }
+
+        let mut replies = Vec::new();
+        for steps in 0.. {
+            // the instruction it was stopped at runs regardless
+            if steps > 0 && self.breakpoints.contains(&self.machine.pc()) {
+                replies.push(TRAP.to_string());
+                break;
+            }
+            if steps > 0 && steps % CHECK_EVERY == 0 && interrupted() {
+                replies.push(INTERRUPT.to_string());
+                break;
+            }
+
+            match self.machine.step() {
+                Ok(State::Output(o)) => replies.push(console(&format!("{}\n", o))),
+                Ok(State::Halt) => {
+                    self.exited = true;
+                    replies.push(EXITED.to_string());
+                    break;
+                }
+                Ok(State::NeedInput) => {
+                    replies.push(console(
+                        "waiting for input; queue some with `monitor input 1,2`\n",
+                    ));
+                    replies.push(WAITING.to_string());
+                    break;
+                }
+                Err(e) => {
+                    replies.push(console(&format!("{}\n", e)));
+                    replies.push(ILLEGAL.to_string());
+                    break;
+                }
+                Ok(_) => {}
+            }
+            if single {
+                replies.push(TRAP.to_string());
+                break;
+            }
+        }
+        replies
+    }
+
+    fn query(&mut self, rest: &str) -> Vec<String> {
+        if let Some(command) = rest.strip_prefix("Rcmd,") {
+            return self.monitor(command);
+        }
+
+        let reply = match rest {
+            "Attached" => "1".to_string(),
+            "C" => "QC1".to_string(),
+            "fThreadInfo" => "m1".to_string(),
+            "sThreadInfo" => "l".to_string(),
+            _ if rest.starts_with("Supported") => {
+                format!("PacketSize={:x};qXfer:features:read+", PACKET_SIZE)
+            }
+            _ => match rest.strip_prefix("Xfer:features:read:target.xml:") {
+                Some(range_text) => match range(range_text) {
+                    Some((offset, len)) => {
+                        let start = (offset as usize).min(TARGET_XML.len());
+                        let end = start.saturating_add(len as usize).min(TARGET_XML.len());
+                        let more = if end < TARGET_XML.len() { "m" } else { "l" };
+                        format!("{}{}", more, &TARGET_XML[start..end])
+                    }
+                    None => MALFORMED.to_string(),
+                },
+                None => String::new(),
+            },
+        };
+        vec![reply]
+    }
+
+    fn monitor(&mut self, command: &str) -> Vec<String> {
+        let command = match bytes(command).and_then(|b| String::from_utf8(b).ok()) {
+            Some(command) => command,
+            None => return vec![MALFORMED.to_string()],
+        };
+
+        let message = match command.trim().split_once(' ') {
+            Some(("input", values)) => match values.parse::<Program>() {
+                Ok(values) => {
+                    for &v in values.iter() {
+                        self.machine.push_input(v);
+                    }
+                    format!("queued {} inputs\n", values.len())
+                }
+                Err(e) => format!("input {}: {}\n", values, e),
+            },
+            _ => MONITOR_HELP.to_string(),
+        };
+        vec![console(&message), "OK".to_string()]
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn bytes(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// a number as the client sends addresses and lengths, big-endian hex
+fn number(text: &str) -> Option<u64> {
+    u64::from_str_radix(text, 16).ok()
+}
+
+/// a register's value as the client sends it, in target byte order
+fn word(text: &str) -> Option<i64> {
+    let bytes = <[u8; 8]>::try_from(bytes(text)?.as_slice()).ok()?;
+    Some(i64::from_le_bytes(bytes))
+}
+
+/// `addr,len`
+fn range(text: &str) -> Option<(u64, u64)> {
+    let (addr, len) = text.split_once(',')?;
+    Some((number(addr)?, number(len)?))
+}
+
+/// the cell at a byte address, if it's the start of one
+fn cell(addr: u64) -> Option<usize> {
+    if addr.is_multiple_of(WORD) {
+        usize::try_from(addr / WORD).ok()
+    } else {
+        None
+    }
+}
+
+/// text for the client's console
+fn console(text: &str) -> String {
+    format!("O{}", hex(text.as_bytes()))
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &b| sum.wrapping_add(b))
+}
+
+/// a reply as it goes on the wire, with the characters that would end it
+/// early escaped
+fn frame(payload: &str) -> Vec<u8> {
+    let mut body = Vec::with_capacity(payload.len());
+    for &b in payload.as_bytes() {
+        if let b'$' | b'#' | b'}' | b'*' = b {
+            body.extend_from_slice(&[b'}', b ^ 0x20]);
+        } else {
+            body.push(b);
+        }
+    }
+
+    let mut out = vec![b'$'];
+    out.extend_from_slice(&body);
+    out.extend_from_slice(format!("#{:02x}", checksum(&body)).as_bytes());
+    out
+}
+
+#[derive(Debug, PartialEq)]
+enum Packet {
+    Data(String),
+    /// ^C, sent bare rather than in a packet
+    Interrupt,
+}
+
+/// The next packet from the client, acknowledged, or `None` once it hangs
+/// up. Its acknowledgements of our replies are skipped; nothing is resent.
+/// A packet longer than the advertised `PacketSize` is refused with a `-`
+/// and ends the session rather than being buffered.
+fn read_packet<R: BufRead, W: Write>(r: &mut R, w: &mut W) -> io::Result<Option<Packet>> {
+    loop {
+        let mut byte = [0];
+        if r.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        match byte[0] {
+            0x03 => return Ok(Some(Packet::Interrupt)),
+            b'$' => {}
+            _ => continue,
+        }
+
+        let mut data = Vec::new();
+        r.by_ref()
+            .take(PACKET_SIZE as u64 + 1)
+            .read_until(b'#', &mut data)?;
+        if data.len() > PACKET_SIZE && data.last() != Some(&b'#') {
+            w.write_all(b"-")?;
+            w.flush()?;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("packet longer than {} bytes", PACKET_SIZE),
+            ));
+        }
+        let mut sum = [0; 2];
+        if data.pop() != Some(b'#') || r.read_exact(&mut sum).is_err() {
+            return Ok(None);
+        }
+
+        let sum = std::str::from_utf8(&sum)
+            .ok()
+            .and_then(|s| u8::from_str_radix(s, 16).ok());
+        if sum == Some(checksum(&data)) {
+            w.write_all(b"+")?;
+            w.flush()?;
+            return Ok(Some(Packet::Data(
+                String::from_utf8_lossy(&data).into_owned(),
+            )));
+        }
+        w.write_all(b"-")?;
+        w.flush()?;
+    }
+}
+
+/// whether the client has sent ^C (or hung up) since we last looked
+fn interrupt_pending(stream: &TcpStream) -> bool {
+    let mut buf = [0; 64];
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let got = (&*stream).read(&mut buf);
+    let _ = stream.set_nonblocking(false);
+    match got {
+        Ok(0) => true,
+        Ok(n) => buf[..n].contains(&0x03),
+        Err(_) => false,
+    }
+}
+
+/// Debug `stub`'s machine for the client on `stream` until it detaches,
+/// kills the program or hangs up.
+pub fn serve(stub: &mut Stub, stream: TcpStream) -> io::Result<()> {
+    let mut out = stream.try_clone()?;
+    let mut input = BufReader::new(stream);
+
+    while !stub.finished() {
+        let replies = match read_packet(&mut input, &mut out)? {
+            None => return Ok(()),
+            Some(Packet::Interrupt) => vec![INTERRUPT.to_string()],
+            Some(Packet::Data(data)) => {
+                let stream = input.get_ref();
+                stub.handle(&data, &mut || interrupt_pending(stream))
+            }
+        };
+        for r in replies {
+            out.write_all(&frame(&r))?;
+        }
+        out.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn stub(prog: &[isize], inputs: &[isize]) -> Stub {
+        Stub::new(Machine::new(prog, inputs))
+    }
+
+    fn send(stub: &mut Stub, packet: &str) -> Vec<String> {
+        stub.handle(packet, &mut || false)
+    }
+
+    #[test]
+    fn test_framing() {
+        assert_eq!(b"$OK#9a".to_vec(), frame("OK"));
+        assert_eq!(b"$}]#da".to_vec(), frame("}"));
+
+        // a bad checksum is refused and the client's acks are skipped
+        let mut wire = Cursor::new(b"+$?#00$?#3f\x03".to_vec());
+        let mut acks = Vec::new();
+        assert_eq!(
+            Some(Packet::Data("?".to_string())),
+            read_packet(&mut wire, &mut acks).unwrap()
+        );
+        assert_eq!(
+            Some(Packet::Interrupt),
+            read_packet(&mut wire, &mut acks).unwrap()
+        );
+        assert_eq!(None, read_packet(&mut wire, &mut acks).unwrap());
+        assert_eq!(b"-+".to_vec(), acks);
+
+        // an overlong packet is refused without reading the rest of it
+        let mut long = b"$".to_vec();
+        long.extend(vec![b'm'; 2 * PACKET_SIZE]);
+        let mut wire = Cursor::new(long);
+        let mut acks = Vec::new();
+        let err = read_packet(&mut wire, &mut acks).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert_eq!(b"-".to_vec(), acks);
+        assert_eq!(PACKET_SIZE as u64 + 2, wire.position());
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut s = stub(&[109, 3, 99], &[]);
+        assert_eq!(vec!["0".repeat(32)], send(&mut s, "g"));
+        send(&mut s, "s");
+        assert_eq!(
+            vec!["1000000000000000".to_string() + "1800000000000000"],
+            send(&mut s, "g")
+        );
+        assert_eq!(vec!["1800000000000000"], send(&mut s, "p1"));
+
+        assert_eq!(vec!["OK"], send(&mut s, "P0=0800000000000000"));
+        assert_eq!(vec!["OK"], send(&mut s, "P1=f8ffffffffffffff"));
+        assert_eq!((1, -1), (s.machine().pc(), s.machine().relative_base()));
+
+        assert_eq!(
+            vec!["OK"],
+            send(&mut s, "G00000000000000002000000000000000")
+        );
+        assert_eq!((0, 4), (s.machine().pc(), s.machine().relative_base()));
+
+        // not the start of a cell, below zero, no such register
+        assert_eq!(vec![MALFORMED], send(&mut s, "P0=0400000000000000"));
+        assert_eq!(vec![BAD_ADDRESS], send(&mut s, "P0=f8ffffffffffffff"));
+        assert_eq!(vec![MALFORMED], send(&mut s, "p2"));
+        assert_eq!(vec![MALFORMED], send(&mut s, "G0000"));
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut s = stub(&[1101, -2, 3, 5, 99, 0], &[]);
+        assert_eq!(
+            vec!["4d04000000000000feffffffffffffff"],
+            send(&mut s, "m0,10")
+        );
+        // part of a cell, and past the end
+        assert_eq!(vec!["04"], send(&mut s, "m1,1"));
+        assert_eq!(vec!["0000"], send(&mut s, "m100,2"));
+
+        assert_eq!(vec!["OK"], send(&mut s, "M28,8:0700000000000000"));
+        assert_eq!(vec!["OK"], send(&mut s, "M29,1:01"));
+        assert_eq!(&[1101, -2, 3, 5, 99, 263][..], s.machine().memory());
+
+        assert_eq!(vec!["OK"], send(&mut s, "M40,2:2a00"));
+        assert_eq!(42, s.machine().get(8));
+
+        assert_eq!(vec![MALFORMED], send(&mut s, "M0,2:00"));
+        assert_eq!(vec![BAD_ADDRESS], send(&mut s, "Mffffffff00,1:00"));
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut s = stub(&[104, 1, 104, 2, 104, 3, 99], &[]);
+        assert_eq!(vec!["OK"], send(&mut s, "Z0,10,8"));
+        assert_eq!(vec!["OK"], send(&mut s, "Z1,20,8"));
+        assert_eq!(vec![MALFORMED], send(&mut s, "Z0,11,8"));
+        assert_eq!(vec![""], send(&mut s, "Z2,10,8"));
+
+        assert_eq!(vec![console("1\n"), TRAP.to_string()], send(&mut s, "c"));
+        assert_eq!(2, s.machine().pc());
+        assert_eq!(vec![console("2\n"), TRAP.to_string()], send(&mut s, "c"));
+
+        assert_eq!(vec!["OK"], send(&mut s, "z1,20,8"));
+        assert_eq!(vec![console("3\n"), TRAP.to_string()], send(&mut s, "s"));
+        assert_eq!(vec![EXITED], send(&mut s, "c"));
+        assert_eq!(vec![EXITED], send(&mut s, "?"));
+        assert_eq!(vec![EXITED], send(&mut s, "s"));
+
+        // continuing from somewhere else
+        let mut s = stub(&[104, 1, 104, 2, 99], &[]);
+        assert_eq!(
+            vec![console("2\n"), EXITED.to_string()],
+            send(&mut s, "c10")
+        );
+    }
+
+    #[test]
+    fn test_input_and_errors() {
+        let mut s = stub(&[3, 5, 4, 5, 99, 0], &[]);
+        let waiting = send(&mut s, "c");
+        assert_eq!(WAITING, waiting[1]);
+
+        let command = hex(b"input 7");
+        assert_eq!(
+            vec![console("queued 1 inputs\n"), "OK".to_string()],
+            send(&mut s, &format!("qRcmd,{}", command))
+        );
+        assert_eq!(vec![console("7\n"), EXITED.to_string()], send(&mut s, "c"));
+
+        let help = send(&mut s, &format!("qRcmd,{}", hex(b"help")));
+        assert_eq!(vec![console(MONITOR_HELP), "OK".to_string()], help);
+
+        let mut s = stub(&[42], &[]);
+        assert_eq!(
+            vec![console("invalid opcode 42 at pc 0\n"), ILLEGAL.to_string()],
+            send(&mut s, "c")
+        );
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let mut s = stub(&[1105, 1, 0], &[]);
+        let mut asked = 0;
+        let replies = s.handle("c", &mut || {
+            asked += 1;
+            asked == 3
+        });
+        assert_eq!(vec![INTERRUPT], replies);
+        assert_eq!(3, asked);
+    }
+
+    #[test]
+    fn test_queries() {
+        let mut s = stub(&[99], &[]);
+        assert_eq!(
+            vec!["PacketSize=4000;qXfer:features:read+"],
+            send(&mut s, "qSupported:multiprocess+;swbreak+")
+        );
+        assert_eq!(vec!["1"], send(&mut s, "qAttached"));
+        assert_eq!(vec![""], send(&mut s, "qTStatus"));
+        assert_eq!(vec![""], send(&mut s, "vMustReplyEmpty"));
+
+        let first = send(&mut s, "qXfer:features:read:target.xml:0,10");
+        assert_eq!(vec![format!("m{}", &TARGET_XML[..16])], first);
+        let rest = send(&mut s, "qXfer:features:read:target.xml:10,1000");
+        assert_eq!(vec![format!("l{}", &TARGET_XML[16..])], rest);
+
+        assert_eq!(Vec::<String>::new(), send(&mut s, "k"));
+        assert!(s.finished());
+    }
+
+    #[test]
+    fn test_serve() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut s = stub(&[104, 5, 99], &[]);
+            serve(&mut s, stream).unwrap();
+            s.machine().pc()
+        });
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.write_all(&frame("c")).unwrap();
+        client.write_all(&frame("D")).unwrap();
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).unwrap();
+
+        let mut expected = b"+".to_vec();
+        expected.extend(frame(&console("5\n")));
+        expected.extend(frame(EXITED));
+        expected.extend(b"+");
+        expected.extend(frame("OK"));
+        assert_eq!(
+            String::from_utf8(expected).unwrap(),
+            String::from_utf8(received).unwrap()
+        );
+        assert_eq!(2, server.join().unwrap());
+    }
+}
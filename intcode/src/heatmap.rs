@@ -0,0 +1,283 @@
+//! How often each memory cell is read, written and executed over a run, and
+//! pictures of it where code, data and the stack stand apart.
+//!
+//! Each cell is a pixel: red for writes, green for reads and blue for
+//! instructions executed, brighter the more often, on a log scale against the
+//! busiest cell. Cells of the loaded image nothing touched are dark grey.
+
+use crate::decode::{decode, Op};
+use crate::machine::{Error, Machine, ParameterMode, State};
+use std::convert::TryFrom;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Counts {
+    pub reads: u64,
+    pub writes: u64,
+    pub execs: u64,
+}
+
+const UNTOUCHED: (u8, u8, u8) = (40, 40, 40);
+const OUTSIDE: (u8, u8, u8) = (0, 0, 0);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heatmap {
+    /// cells of the loaded image, drawn whether they're touched or not
+    image_len: usize,
+    cells: Vec<Counts>,
+}
+
+impl Heatmap {
+    pub fn new(image_len: usize) -> Self {
+        Heatmap {
+            image_len,
+            cells: vec![Counts::default(); image_len],
+        }
+    }
+
+    /// how many cells there are to draw
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn counts(&self, addr: usize) -> Counts {
+        self.cells.get(addr).copied().unwrap_or_default()
+    }
+
+    fn cell(&mut self, addr: usize) -> &mut Counts {
+        if self.cells.len() <= addr {
+            self.cells.resize(addr + 1, Counts::default());
+        }
+        &mut self.cells[addr]
+    }
+
+    /// forget everything counted so far, as at the start of a new frame
+    pub fn clear(&mut self) {
+        self.cells = vec![Counts::default(); self.image_len];
+    }
+
+    /// add in what `other` counted
+    pub fn merge(&mut self, other: &Heatmap) {
+        for (addr, c) in other.cells.iter().enumerate() {
+            let cell = self.cell(addr);
+            cell.reads += c.reads;
+            cell.writes += c.writes;
+            cell.execs += c.execs;
+        }
+    }
+
+    /// Step `m`, counting the cells the instruction was fetched from and the
+    /// ones it read and wrote. Registered opcodes only count as executed,
+    /// and the negative address bank isn't drawn.
+    pub fn step(&mut self, m: &mut Machine) -> Result<State, Error> {
+        let (pc, relative_base) = (m.pc(), m.relative_base());
+        let ins = decode(m.memory(), pc);
+        let state = m.step()?;
+        if state == State::NeedInput {
+            return Ok(state);
+        }
+
+        let ins = match ins {
+            Some(ins) => ins,
+            None => {
+                self.cell(pc).execs += 1;
+                return Ok(state);
+            }
+        };
+        for addr in pc..pc + ins.width() {
+            self.cell(addr).execs += 1;
+        }
+
+        let jump = ins.op == Op::JumpIfTrue || ins.op == Op::JumpIfFalse;
+        let jumped = m.pc() != pc + ins.width();
+        for (n, p) in ins.params.iter().enumerate() {
+            let addr = match p.mode {
+                ParameterMode::Immediate => continue,
+                ParameterMode::Position => Some(p.raw),
+                ParameterMode::Relative => relative_base.checked_add(p.raw),
+            };
+            let addr = match addr.and_then(|a| usize::try_from(a).ok()) {
+                Some(addr) => addr,
+                None => continue,
+            };
+
+            if Some(n) == ins.op.destination() {
+                self.cell(addr).writes += 1;
+            } else if !(jump && n == 1 && !jumped) {
+                // a jump only reads its target when it's taken
+                self.cell(addr).reads += 1;
+            }
+        }
+        Ok(state)
+    }
+
+    /// the busiest cell's count for each kind of access
+    fn maxima(&self) -> Counts {
+        self.cells.iter().fold(Counts::default(), |max, c| Counts {
+            reads: max.reads.max(c.reads),
+            writes: max.writes.max(c.writes),
+            execs: max.execs.max(c.execs),
+        })
+    }
+
+    fn colour(&self, addr: usize, max: Counts) -> (u8, u8, u8) {
+        // anything touched at all is bright enough to see
+        let level = |c: u64, max: u64| {
+            if c == 0 {
+                0
+            } else {
+                (64.0 + 191.0 * (c as f64).ln_1p() / (max as f64).ln_1p()) as u8
+            }
+        };
+
+        match self.cells.get(addr) {
+            None => OUTSIDE,
+            Some(&c) if c == Counts::default() && addr < self.image_len => UNTOUCHED,
+            Some(&c) => (
+                level(c.writes, max.writes),
+                level(c.reads, max.reads),
+                level(c.execs, max.execs),
+            ),
+        }
+    }
+
+    fn rows(&self, width: usize) -> usize {
+        self.len().div_ceil(width).max(1)
+    }
+
+    /// A binary PPM, `width` cells to a row and each cell `scale` pixels
+    /// square.
+    pub fn write_ppm<W: Write>(&self, mut w: W, width: usize, scale: usize) -> io::Result<()> {
+        let max = self.maxima();
+        let rows = self.rows(width);
+        writeln!(w, "P6\n{} {}\n255", width * scale, rows * scale)?;
+
+        let mut line = Vec::with_capacity(width * scale * 3);
+        for y in 0..rows {
+            line.clear();
+            for x in 0..width {
+                let (r, g, b) = self.colour(y * width + x, max);
+                for _ in 0..scale {
+                    line.extend_from_slice(&[r, g, b]);
+                }
+            }
+            for _ in 0..scale {
+                w.write_all(&line)?;
+            }
+        }
+        w.flush()
+    }
+
+    /// The same picture for a terminal with 24-bit colour, `width` cells to
+    /// a row and two rows to a line of half blocks.
+    pub fn ansi(&self, width: usize) -> String {
+        let max = self.maxima();
+        let rows = self.rows(width);
+        let mut out = String::new();
+
+        for y in (0..rows).step_by(2) {
+            for x in 0..width {
+                let (r, g, b) = self.colour(y * width + x, max);
+                let (br, bg, bb) = self.colour((y + 1) * width + x, max);
+                write!(
+                    out,
+                    "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}",
+                    r, g, b, br, bg, bb
+                )
+                .unwrap();
+            }
+            out.push_str("\x1b[0m\n");
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // [13] = [12] + 5, jz [12], [11] (not taken), out [13]
+    const PROG: &[isize] = &[1001, 12, 5, 13, 6, 12, 11, 4, 13, 99, 0, 0, 7, 0];
+
+    fn run() -> Heatmap {
+        let mut heat = Heatmap::new(PROG.len());
+        let mut m = Machine::new(PROG, &[]);
+        while heat.step(&mut m).unwrap() != State::Halt {}
+        heat
+    }
+
+    #[test]
+    fn test_counts() {
+        let heat = run();
+        let counts = |reads, writes, execs| Counts {
+            reads,
+            writes,
+            execs,
+        };
+
+        assert_eq!(counts(0, 0, 1), heat.counts(0));
+        assert_eq!(counts(0, 0, 1), heat.counts(6));
+        assert_eq!(counts(0, 0, 1), heat.counts(9));
+        assert_eq!(counts(0, 0, 0), heat.counts(11));
+        assert_eq!(counts(2, 0, 0), heat.counts(12));
+        assert_eq!(counts(1, 1, 0), heat.counts(13));
+
+        let mut twice = heat.clone();
+        twice.merge(&heat);
+        assert_eq!(counts(4, 0, 0), twice.counts(12));
+        twice.clear();
+        assert_eq!(Heatmap::new(PROG.len()), twice);
+    }
+
+    #[test]
+    fn test_relative_and_growth() {
+        // arb #100, [rb+5] = input
+        let mut heat = Heatmap::new(5);
+        let mut m = Machine::new(&[109, 100, 203, 5, 99], &[]);
+        heat.step(&mut m).unwrap();
+        assert_eq!(Ok(State::NeedInput), heat.step(&mut m));
+        assert_eq!(5, heat.len());
+
+        m.push_input(1);
+        heat.step(&mut m).unwrap();
+        assert_eq!(106, heat.len());
+        assert_eq!(1, heat.counts(105).writes);
+        assert_eq!(1, heat.counts(2).execs);
+    }
+
+    #[test]
+    fn test_ppm() {
+        let mut ppm = Vec::new();
+        run().write_ppm(&mut ppm, 4, 1).unwrap();
+
+        let header = b"P6\n4 4\n255\n";
+        assert_eq!(header, &ppm[..header.len()]);
+        let pixels = &ppm[header.len()..];
+        assert_eq!(4 * 4 * 3, pixels.len());
+        let pixel = |addr: usize| &pixels[addr * 3..addr * 3 + 3];
+
+        assert_eq!(&[0, 0, 255], pixel(0));
+        assert_eq!(&[40, 40, 40], pixel(10));
+        assert_eq!(&[0, 255, 0], pixel(12));
+        assert_eq!(&[255, 184, 0], pixel(13));
+        assert_eq!(&[0, 0, 0], pixel(15));
+
+        let mut ppm = Vec::new();
+        run().write_ppm(&mut ppm, 4, 3).unwrap();
+        assert_eq!(b"P6\n12 12\n255\n".len() + 12 * 12 * 3, ppm.len());
+    }
+
+    #[test]
+    fn test_ansi() {
+        let text = run().ansi(4);
+        assert_eq!(2, text.lines().count());
+        assert_eq!(8, text.matches('\u{2580}').count());
+        assert!(text.starts_with("\x1b[38;2;0;0;255m\x1b[48;2;0;0;255m"));
+        assert!(text.ends_with("\x1b[0m\n"));
+    }
+}
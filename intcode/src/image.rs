@@ -0,0 +1,516 @@
+//! A compact binary form for programs, much smaller than the text and quicker
+//! to load. All of it is little-endian:
+//!
+//! ```text
+//! magic    "\0ICP"
+//! version  1 byte, 1
+//! width    1 byte, the bits needed for the widest word: 8, 16, 32 or 64
+//! flags    1 byte, 1 if an entry point follows the words, 2 if symbols do
+//! count    varint
+//! words    zig-zag varints, `count` of them
+//! entry    varint
+//! symbols  varint count, then for each a varint length, that many bytes of
+//!          UTF-8 for its name and a varint address
+//! ```
+//!
+//! Varints are unsigned LEB128, seven bits to a byte. In text, the entry
+//! point and symbols are kept as `#!` comments, which a plain `Program`
+//! parse skips, so symbol names can't be empty or contain whitespace:
+//!
+//! ```text
+//! #! entry 0
+//! #! symbol main 12
+//! 1101,100,-1,4,0
+//! ```
+
+use crate::program::{ParseError, Program, Reason as TextReason};
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+use core::str::FromStr;
+
+pub const MAGIC: [u8; 4] = *b"\0ICP";
+pub const VERSION: u8 = 1;
+
+const ENTRY: u8 = 1;
+const SYMBOLS: u8 = 2;
+
+/// A program with what the binary form can say about it besides its words.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Image {
+    pub program: Program,
+    /// where execution is meant to start, if not at 0. It's only carried
+    /// along: `Program::load` drops it and every command starts at 0.
+    pub entry: Option<usize>,
+    /// names for addresses, such as the functions a decompile found
+    symbols: BTreeMap<String, usize>,
+}
+
+/// A symbol name that's empty or has whitespace, which the text form
+/// couldn't read back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BadName(pub String);
+
+impl fmt::Display for BadName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bad symbol name {:?}", self.0)
+    }
+}
+
+impl core::error::Error for BadName {}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(char::is_whitespace)
+}
+
+impl From<Program> for Image {
+    fn from(program: Program) -> Self {
+        Image {
+            program,
+            ..Image::default()
+        }
+    }
+}
+
+/// bits in the narrowest of 8, 16, 32 and 64 that holds every word
+fn width(words: &[isize]) -> u8 {
+    let fits = |bits: u32| {
+        let max = (1i64 << (bits - 1)) - 1;
+        words
+            .iter()
+            .all(|&w| (-max - 1..=max).contains(&(w as i64)))
+    };
+    [8, 16, 32]
+        .iter()
+        .find(|&&b| fits(b))
+        .map_or(64, |&b| b as u8)
+}
+
+fn put_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+impl Image {
+    pub fn symbols(&self) -> &BTreeMap<String, usize> {
+        &self.symbols
+    }
+
+    /// name `addr`, replacing whatever had the name before
+    pub fn add_symbol(&mut self, name: &str, addr: usize) -> Result<(), BadName> {
+        if !valid_name(name) {
+            return Err(BadName(name.to_string()));
+        }
+        self.symbols.insert(name.to_string(), addr);
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let words = &self.program;
+        // most words in real programs fit a byte or two
+        let mut out = Vec::with_capacity(8 + words.len() * 2);
+        out.extend_from_slice(&MAGIC);
+        out.push(VERSION);
+        out.push(width(words));
+
+        let mut flags = 0;
+        if self.entry.is_some() {
+            flags |= ENTRY;
+        }
+        if !self.symbols.is_empty() {
+            flags |= SYMBOLS;
+        }
+        out.push(flags);
+
+        put_varint(&mut out, words.len() as u64);
+        for &w in words.iter() {
+            let w = w as i64;
+            put_varint(&mut out, ((w << 1) ^ (w >> 63)) as u64);
+        }
+        if let Some(entry) = self.entry {
+            put_varint(&mut out, entry as u64);
+        }
+        if !self.symbols.is_empty() {
+            put_varint(&mut out, self.symbols.len() as u64);
+            for (name, &addr) in &self.symbols {
+                put_varint(&mut out, name.len() as u64);
+                out.extend_from_slice(name.as_bytes());
+                put_varint(&mut out, addr as u64);
+            }
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Image, DecodeError> {
+        let mut r = Reader { bytes, at: 0 };
+        if !bytes.starts_with(&MAGIC) {
+            return r.fail(Reason::BadMagic);
+        }
+        r.at = MAGIC.len();
+
+        let version = r.byte()?;
+        if version != VERSION {
+            return Err(DecodeError {
+                reason: Reason::Version(version),
+                offset: r.at - 1,
+            });
+        }
+        let width = r.byte()?;
+        if ![8, 16, 32, 64].contains(&width) || u32::from(width) > isize::BITS {
+            return Err(DecodeError {
+                reason: Reason::Width(width),
+                offset: r.at - 1,
+            });
+        }
+        let flags = r.byte()?;
+        if flags & !(ENTRY | SYMBOLS) != 0 {
+            return Err(DecodeError {
+                reason: Reason::Flags(flags),
+                offset: r.at - 1,
+            });
+        }
+
+        let count = r.size()?;
+        // every word takes at least a byte, so a bad count can't ask for
+        // more memory than the input is long
+        let mut words = Vec::with_capacity(count.min(bytes.len()));
+        let (min, max) = match width {
+            64 => (i64::MIN, i64::MAX),
+            w => (-(1i64 << (w - 1)), (1i64 << (w - 1)) - 1),
+        };
+        for _ in 0..count {
+            let start = r.at;
+            let n = r.varint()?;
+            let w = (n >> 1) as i64 ^ -((n & 1) as i64);
+            match isize::try_from(w) {
+                Ok(w) if (min..=max).contains(&(w as i64)) => words.push(w),
+                _ => {
+                    return Err(DecodeError {
+                        reason: Reason::Overflow,
+                        offset: start,
+                    })
+                }
+            }
+        }
+
+        let mut image = Image::from(Program::new(words));
+        if flags & ENTRY != 0 {
+            image.entry = Some(r.size()?);
+        }
+        if flags & SYMBOLS != 0 {
+            for _ in 0..r.size()? {
+                let len = r.size()?;
+                let start = r.at;
+                let name = match bytes.get(start..start.saturating_add(len)) {
+                    Some(name) => name,
+                    None => return r.fail(Reason::Truncated),
+                };
+                let name = match core::str::from_utf8(name) {
+                    Ok(name) if valid_name(name) => name.to_string(),
+                    _ => return r.fail(Reason::BadSymbol),
+                };
+                r.at += len;
+                let addr = r.size()?;
+                image.symbols.insert(name, addr);
+            }
+        }
+
+        if r.at != bytes.len() {
+            return r.fail(Reason::TrailingBytes);
+        }
+        Ok(image)
+    }
+}
+
+/// the binary form's reading position
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl Reader<'_> {
+    fn fail<T>(&self, reason: Reason) -> Result<T, DecodeError> {
+        Err(DecodeError {
+            reason,
+            offset: self.at,
+        })
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        match self.bytes.get(self.at) {
+            Some(&b) => {
+                self.at += 1;
+                Ok(b)
+            }
+            None => self.fail(Reason::Truncated),
+        }
+    }
+
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let start = self.at;
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            let bits = u64::from(b & 0x7f);
+            if shift == 63 && bits > 1 {
+                break;
+            }
+            n |= bits << shift;
+            if b & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(DecodeError {
+            reason: Reason::Overflow,
+            offset: start,
+        })
+    }
+
+    /// a length or address
+    fn size(&mut self) -> Result<usize, DecodeError> {
+        let start = self.at;
+        let n = self.varint()?;
+        usize::try_from(n).map_err(|_| DecodeError {
+            reason: Reason::Overflow,
+            offset: start,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+    /// it doesn't start with `MAGIC`
+    BadMagic,
+    Version(u8),
+    /// wider than an `isize`, or not a width at all
+    Width(u8),
+    /// flags this version doesn't know
+    Flags(u8),
+    /// it ends part way through
+    Truncated,
+    /// a number too big for where it goes
+    Overflow,
+    /// a symbol name that isn't UTF-8, or is empty or has whitespace
+    BadSymbol,
+    /// more after the end
+    TrailingBytes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodeError {
+    pub reason: Reason,
+    /// where in the input the problem starts
+    pub offset: usize,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "byte {}: ", self.offset)?;
+        match self.reason {
+            Reason::BadMagic => write!(f, "not a binary Intcode program"),
+            Reason::Version(v) => write!(f, "unsupported version {}", v),
+            Reason::Width(w) => write!(f, "unsupported word width {}", w),
+            Reason::Flags(flags) => write!(f, "unknown flags {:#04x}", flags),
+            Reason::Truncated => write!(f, "unexpected end"),
+            Reason::Overflow => write!(f, "number out of range"),
+            Reason::BadSymbol => write!(f, "bad symbol name"),
+            Reason::TrailingBytes => write!(f, "unexpected bytes after the end"),
+        }
+    }
+}
+
+impl core::error::Error for DecodeError {}
+
+/// the `#!` lines, then the program on one line
+impl fmt::Display for Image {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(entry) = self.entry {
+            writeln!(f, "#! entry {}", entry)?;
+        }
+        for (name, addr) in &self.symbols {
+            writeln!(f, "#! symbol {} {}", name, addr)?;
+        }
+        writeln!(f, "{}", self.program)
+    }
+}
+
+impl FromStr for Image {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Image, ParseError> {
+        let mut image = Image::from(s.parse::<Program>()?);
+
+        for (line_no, line) in s.lines().enumerate() {
+            let directive = match line.trim_start().strip_prefix("#!") {
+                Some(directive) => directive,
+                None => continue,
+            };
+            let fields: Vec<&str> = directive.split_whitespace().collect();
+            match fields[..] {
+                ["entry", addr] if addr.parse::<usize>().is_ok() => {
+                    image.entry = addr.parse().ok();
+                }
+                ["symbol", name, addr] if addr.parse::<usize>().is_ok() => {
+                    image
+                        .symbols
+                        .insert(name.to_string(), addr.parse().unwrap());
+                }
+                _ => {
+                    let start = line.len() - line.trim_start().len();
+                    return Err(ParseError {
+                        reason: TextReason::BadDirective,
+                        index: 0,
+                        token: line.trim().to_string(),
+                        line: line_no + 1,
+                        column: line[..start].chars().count() + 1,
+                    });
+                }
+            }
+        }
+
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_round_trip() {
+        let prog = Program::new(vec![1101, 100, -1, 4, 0, 104, 1125899906842624, 99]);
+        let mut image = Image::from(prog.clone());
+        assert_eq!(image, Image::from_bytes(&image.to_bytes()).unwrap());
+
+        image.entry = Some(5);
+        image.add_symbol("main", 0).unwrap();
+        image.add_symbol("élan", 5).unwrap();
+        let bytes = image.to_bytes();
+        assert_eq!(&MAGIC[..], &bytes[..4]);
+        assert_eq!([VERSION, 64, ENTRY | SYMBOLS], bytes[4..7]);
+        assert_eq!(image, Image::from_bytes(&bytes).unwrap());
+
+        assert_eq!(
+            "#! entry 5\n#! symbol main 0\n#! symbol élan 5\n\
+             1101,100,-1,4,0,104,1125899906842624,99\n",
+            image.to_string()
+        );
+        assert_eq!(image, image.to_string().parse().unwrap());
+        assert_eq!(prog, image.to_string().parse().unwrap());
+
+        let extremes = Program::new(vec![isize::MIN, isize::MAX, 0, -1]);
+        assert_eq!(extremes, Program::from_bytes(&extremes.to_bytes()).unwrap());
+        assert_eq!(
+            Program::default(),
+            Program::from_bytes(&Program::default().to_bytes()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_encoding() {
+        // zig-zag: 0, -1, 1, -2, 64 become 0, 1, 2, 3, 128
+        let bytes = Program::new(vec![0, -1, 1, -2, 64]).to_bytes();
+        assert_eq!(
+            [0, b'I', b'C', b'P', 1, 8, 0, 5, 0, 1, 2, 3, 0x80, 1],
+            bytes[..]
+        );
+
+        assert_eq!(16, width(&[1, -129]));
+        assert_eq!(32, width(&[1 << 20]));
+        assert_eq!(64, width(&[1 << 40]));
+        assert_eq!(8, width(&[-128, 127]));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let mut image = Image::from(Program::new(vec![1, 300, 99]));
+        image.add_symbol("f", 2).unwrap();
+        let bytes = image.to_bytes();
+        let err = |bytes: &[u8]| Image::from_bytes(bytes).unwrap_err();
+
+        assert_eq!(Reason::BadMagic, err(b"1,2,3").reason);
+        let mut bad = bytes.clone();
+        bad[4] = 2;
+        assert_eq!(
+            (Reason::Version(2), 4),
+            (err(&bad).reason, err(&bad).offset)
+        );
+        bad = bytes.clone();
+        bad[5] = 12;
+        assert_eq!(Reason::Width(12), err(&bad).reason);
+        bad = bytes.clone();
+        bad[6] |= 0x10;
+        assert_eq!(Reason::Flags(0x12), err(&bad).reason);
+
+        // 300 doesn't fit the 16 bits claimed once they're 8
+        bad = bytes.clone();
+        bad[5] = 8;
+        assert_eq!((Reason::Overflow, 9), (err(&bad).reason, err(&bad).offset));
+
+        for end in 4..bytes.len() {
+            assert_eq!(Reason::Truncated, err(&bytes[..end]).reason, "{}", end);
+        }
+        bad = bytes.clone();
+        bad.push(0);
+        assert_eq!(
+            (Reason::TrailingBytes, bytes.len()),
+            (err(&bad).reason, err(&bad).offset)
+        );
+
+        // the symbol's name is the second to last byte
+        for name in [b' ', 0xff] {
+            bad = bytes.clone();
+            bad[bytes.len() - 2] = name;
+            assert_eq!(
+                (Reason::BadSymbol, bytes.len() - 2),
+                (err(&bad).reason, err(&bad).offset)
+            );
+        }
+
+        // an eleven byte varint
+        let mut long = bytes[..7].to_vec();
+        long.extend_from_slice(&[0xff; 10]);
+        long.push(0);
+        assert_eq!(
+            (Reason::Overflow, 7),
+            (err(&long).reason, err(&long).offset)
+        );
+
+        assert_eq!(
+            "byte 4: unsupported version 2",
+            DecodeError {
+                reason: Reason::Version(2),
+                offset: 4
+            }
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn test_bad_directive() {
+        let err = "#! entry\n1,2".parse::<Image>().unwrap_err();
+        assert_eq!((TextReason::BadDirective, 1), (err.reason, err.line));
+        assert_eq!("#! entry", err.token);
+        // ordinary comments are left alone
+        assert!("# entry\n1,2".parse::<Image>().is_ok());
+    }
+
+    #[test]
+    fn test_bad_names() {
+        let mut image = Image::default();
+        for name in ["", "a b", "tab\t", "new\nline"] {
+            assert_eq!(Err(BadName(name.to_string())), image.add_symbol(name, 1));
+        }
+        assert!(image.symbols().is_empty());
+        assert_eq!(
+            "bad symbol name \"a b\"",
+            BadName("a b".to_string()).to_string()
+        );
+    }
+}
@@ -0,0 +1,374 @@
+//! Just enough JSON for the debug adapter's messages. Numbers are kept as
+//! they were written so large Intcode values survive the trip.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    /// in the order written, which is kept when it's printed
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+        )
+    }
+
+    /// the field `key` of an object
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Self {
+        Json::String(s)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(n: i64) -> Self {
+        Json::Number(n.to_string())
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Self {
+        Json::Bool(b)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(items: Vec<Json>) -> Self {
+        Json::Array(items)
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// compact, with no whitespace
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, k)?;
+                    write!(f, ":{}", v)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// where the text stopped making sense, as a byte offset
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError(pub usize);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid JSON at byte {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+struct Parser<'a> {
+    text: &'a str,
+    at: usize,
+}
+
+impl Parser<'_> {
+    fn fail<T>(&self) -> Result<T, ParseError> {
+        Err(ParseError(self.at))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.at..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.at += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_space(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.at += 1;
+        }
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), ParseError> {
+        if self.text[self.at..].starts_with(s) {
+            self.at += s.len();
+            Ok(())
+        } else {
+            self.fail()
+        }
+    }
+
+    /// past a comma, or the `close` ending a list, saying which
+    fn separator(&mut self, close: char) -> Result<bool, ParseError> {
+        match self.peek() {
+            Some(',') => {}
+            Some(c) if c == close => {}
+            _ => return self.fail(),
+        }
+        Ok(self.bump() == Some(close))
+    }
+
+    fn value(&mut self) -> Result<Json, ParseError> {
+        self.skip_space();
+        let value = match self.peek() {
+            Some('n') => self.expect("null").map(|_| Json::Null)?,
+            Some('t') => self.expect("true").map(|_| Json::Bool(true))?,
+            Some('f') => self.expect("false").map(|_| Json::Bool(false))?,
+            Some('"') => Json::String(self.string()?),
+            Some('[') => {
+                self.at += 1;
+                let mut items = Vec::new();
+                self.skip_space();
+                if self.peek() == Some(']') {
+                    self.at += 1;
+                } else {
+                    loop {
+                        items.push(self.value()?);
+                        if self.separator(']')? {
+                            break;
+                        }
+                    }
+                }
+                Json::Array(items)
+            }
+            Some('{') => {
+                self.at += 1;
+                let mut fields = Vec::new();
+                self.skip_space();
+                if self.peek() == Some('}') {
+                    self.at += 1;
+                } else {
+                    loop {
+                        self.skip_space();
+                        let key = self.string()?;
+                        self.skip_space();
+                        self.expect(":")?;
+                        fields.push((key, self.value()?));
+                        if self.separator('}')? {
+                            break;
+                        }
+                    }
+                }
+                Json::Object(fields)
+            }
+            Some('-' | '0'..='9') => self.number()?,
+            _ => return self.fail(),
+        };
+        self.skip_space();
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<Json, ParseError> {
+        let start = self.at;
+        while let Some('-' | '+' | '.' | 'e' | 'E' | '0'..='9') = self.peek() {
+            self.at += 1;
+        }
+        let text = &self.text[start..self.at];
+        if text.parse::<f64>().is_err() {
+            return Err(ParseError(start));
+        }
+        Ok(Json::Number(text.to_string()))
+    }
+
+    fn hex4(&mut self) -> Result<u32, ParseError> {
+        let digits = self.text.get(self.at..self.at + 4);
+        match digits.and_then(|d| u32::from_str_radix(d, 16).ok()) {
+            Some(n) => {
+                self.at += 4;
+                Ok(n)
+            }
+            None => self.fail(),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.expect("\"")?;
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(s),
+                Some('\\') => {
+                    let c = match self.bump() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let mut n = self.hex4()?;
+                            // the second half of a surrogate pair
+                            if (0xd800..0xdc00).contains(&n) {
+                                self.expect("\\u")?;
+                                let low = self.hex4()?;
+                                n = 0x10000 + ((n - 0xd800) << 10) + (low.wrapping_sub(0xdc00));
+                            }
+                            match char::from_u32(n) {
+                                Some(c) => c,
+                                None => return self.fail(),
+                            }
+                        }
+                        _ => return self.fail(),
+                    };
+                    s.push(c);
+                }
+                Some(c) if (c as u32) >= 0x20 => s.push(c),
+                _ => return self.fail(),
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Json {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Json, ParseError> {
+        let mut p = Parser { text, at: 0 };
+        let value = p.value()?;
+        if p.at != text.len() {
+            return p.fail();
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let text = r#" {"seq": 1, "args": {"inputs": [1, -2, 12345678901234567], "stop": true},
+            "name": "a \"b\"\né😀", "none": null, "empty": [], "x": {} }"#;
+        let json: Json = text.parse().unwrap();
+
+        assert_eq!(Some(1), json.get("seq").and_then(Json::as_i64));
+        let args = json.get("args").unwrap();
+        let inputs: Vec<i64> = args
+            .get("inputs")
+            .and_then(Json::as_array)
+            .unwrap()
+            .iter()
+            .filter_map(Json::as_i64)
+            .collect();
+        assert_eq!(vec![1, -2, 12345678901234567], inputs);
+        assert_eq!(Some(true), args.get("stop").and_then(Json::as_bool));
+        assert_eq!(
+            Some("a \"b\"\n\u{e9}\u{1f600}"),
+            json.get("name").and_then(Json::as_str)
+        );
+
+        assert_eq!(
+            r#"{"seq":1,"args":{"inputs":[1,-2,12345678901234567],"stop":true},"name":"a \"b\"\né😀","none":null,"empty":[],"x":{}}"#,
+            json.to_string()
+        );
+        assert_eq!(json, json.to_string().parse().unwrap());
+    }
+
+    #[test]
+    fn test_errors() {
+        for (text, at) in &[
+            ("", 0),
+            ("{\"a\" 1}", 5),
+            ("[1,]", 3),
+            ("[1 2]", 3),
+            ("\"abc", 4),
+            ("nul", 0),
+            ("1 2", 2),
+            ("-", 0),
+        ] {
+            assert_eq!(Err(ParseError(*at)), text.parse::<Json>(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,44 @@
+//! The machine, its opcodes, decoding and program parsing (text or binary)
+//! only need `alloc`, and are all that's built with the `std` feature turned
+//! off. Everything else here does I/O or runs threads, and needs it on (as it
+//! is by default).
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod conformance;
+#[cfg(feature = "std")]
+pub mod dap;
+pub mod decode;
+#[cfg(feature = "std")]
+pub mod decompile;
+pub mod dump;
+pub mod explain;
+#[cfg(feature = "std")]
+pub mod gdb;
+#[cfg(feature = "std")]
+pub mod heatmap;
+pub mod image;
+#[cfg(feature = "std")]
+mod json;
+pub mod machine;
+pub mod opcode;
+#[cfg(feature = "std")]
+pub mod optimize;
+#[cfg(feature = "std")]
+pub mod pool;
+pub mod program;
+#[cfg(feature = "std")]
+pub mod record;
+#[cfg(feature = "std")]
+pub mod search;
+#[cfg(feature = "std")]
+pub mod symbolic;
+
+pub use crate::machine::{Machine, State};
+pub use crate::opcode::{Context, Opcode, Param};
+#[cfg(feature = "std")]
+pub use crate::pool::MachinePool;
+pub use crate::program::Program;
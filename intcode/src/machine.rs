@@ -0,0 +1,797 @@
+use crate::opcode::{Context, Opcode, Param};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+
+pub fn run_to_completion(prog: &[isize], input: &[isize]) -> Vec<isize> {
+    let mut m = Machine::new(prog, input);
+    let mut acc = Vec::new();
+
+    loop {
+        match m.step().unwrap() {
+            State::Halt => return acc,
+            State::Output(o) => acc.push(o),
+            State::NeedInput => panic!("no input available"),
+            _ => {}
+        }
+    }
+}
+
+/// cells per page when tracking what a run has written
+const PAGE: usize = 64;
+
+/// Forking is cheap: memory is shared between a machine and its forks until
+/// one of them writes to it, when the writer takes its own copy.
+#[derive(Clone)]
+pub struct Machine {
+    prog: Arc<Vec<isize>>,
+    /// length of the program the machine was loaded with
+    image_len: usize,
+    /// which pages of the image have been written since loading
+    dirty: Vec<bool>,
+    /// cells -1, -2, ... when they're allowed
+    negative: Arc<Vec<isize>>,
+    negative_addresses: NegativeAddresses,
+    /// how many cells memory may grow to on either side of zero
+    memory_limit: Option<usize>,
+    pc: usize,
+    relative_base: isize,
+    input: VecDeque<isize>,
+    opcodes: Arc<BTreeMap<isize, Arc<dyn Opcode>>>,
+}
+
+impl Machine {
+    pub fn new(prog: &[isize], input: &[isize]) -> Self {
+        Machine {
+            prog: Arc::new(prog.to_vec()),
+            image_len: prog.len(),
+            dirty: vec![false; prog.len().div_ceil(PAGE)],
+            negative: Arc::default(),
+            negative_addresses: NegativeAddresses::default(),
+            memory_limit: None,
+            pc: 0,
+            relative_base: 0,
+            input: input.to_vec().into(),
+            opcodes: Arc::default(),
+        }
+    }
+
+    /// Put the machine back the way `new` left it, loaded with `prog` and
+    /// nothing queued for input. If `prog` is the program the machine was made
+    /// with (or last reset to) only the pages written since are copied back;
+    /// any other program is loaded in full. Registered opcodes, the
+    /// negative address policy and the memory limit are kept.
+    pub fn reset(&mut self, prog: &[isize]) {
+        let pages = |page: usize| page * PAGE..((page + 1) * PAGE).min(prog.len());
+        let dirty = &self.dirty;
+        match Arc::get_mut(&mut self.prog) {
+            // pages nobody wrote still hold the image, so `prog` is the same
+            // program if it matches them and the same length
+            Some(mem)
+                if prog.len() == self.image_len
+                    && dirty
+                        .iter()
+                        .enumerate()
+                        .all(|(page, &dirty)| dirty || mem[pages(page)] == prog[pages(page)]) =>
+            {
+                mem.truncate(prog.len());
+                for (page, dirty) in self.dirty.iter_mut().enumerate() {
+                    if *dirty {
+                        mem[pages(page)].copy_from_slice(&prog[pages(page)]);
+                        *dirty = false;
+                    }
+                }
+            }
+            // shared with a fork, or a different program
+            _ => {
+                self.prog = Arc::new(prog.to_vec());
+                self.image_len = prog.len();
+                self.dirty.clear();
+                self.dirty.resize(prog.len().div_ceil(PAGE), false);
+            }
+        }
+
+        match Arc::get_mut(&mut self.negative) {
+            Some(negative) => negative.clear(),
+            None => self.negative = Arc::default(),
+        }
+        self.pc = 0;
+        self.relative_base = 0;
+        self.input.clear();
+    }
+
+    /// Handle `code` with `op` from now on. Only codes the machine doesn't
+    /// already understand can be registered, and only with as many
+    /// parameters as an instruction has room for modes.
+    pub fn register<O: Opcode + 'static>(&mut self, code: isize, op: O) -> &mut Self {
+        assert!(
+            (0..100).contains(&code) && !BUILTIN.contains(&code),
+            "opcode {} is not free to register",
+            code
+        );
+        assert!(
+            op.arity() <= MAX_ARITY,
+            "opcode {} takes more than {} parameters",
+            code,
+            MAX_ARITY
+        );
+        Arc::make_mut(&mut self.opcodes).insert(code, Arc::new(op));
+        self
+    }
+
+    /// what happens when the program touches an address below zero
+    pub fn negative_addresses(&mut self, policy: NegativeAddresses) -> &mut Self {
+        self.negative_addresses = policy;
+        self
+    }
+
+    pub fn negative_address_policy(&self) -> NegativeAddresses {
+        self.negative_addresses
+    }
+
+    /// Let the program grow memory to at most `cells` cells, and the
+    /// negative bank to as many. A store past that fails with
+    /// `Error::OutOfMemory` rather than trying to allocate whatever the
+    /// address asks for. Memory the program was loaded with is always there.
+    pub fn memory_limit(&mut self, cells: usize) -> &mut Self {
+        self.memory_limit = Some(cells);
+        self
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// move execution elsewhere, as a debugger might
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+
+    /// may go below zero, as long as nothing is addressed through it there
+    pub fn relative_base(&self) -> isize {
+        self.relative_base
+    }
+
+    pub fn set_relative_base(&mut self, base: isize) {
+        self.relative_base = base;
+    }
+
+    /// memory as it stands, including anything grown past the original program
+    pub fn memory(&self) -> &[isize] {
+        &self.prog
+    }
+
+    /// queue a value for the next input instruction
+    pub fn push_input(&mut self, v: isize) {
+        self.input.push_back(v);
+    }
+
+    pub(crate) fn pop_input(&mut self) -> Option<isize> {
+        self.input.pop_front()
+    }
+
+    fn raw_parameter(&self, n: usize) -> isize {
+        self.get(self.pc + n)
+    }
+
+    /// a jump target; code never runs from the negative bank
+    fn address(&self, x: isize) -> Result<usize, Error> {
+        usize::try_from(x).map_err(|_| Error::NegativeAddress(self.pc))
+    }
+
+    fn relative(&self, offset: isize) -> Result<isize, Error> {
+        self.relative_base
+            .checked_add(offset)
+            .ok_or(Error::Overflow(self.pc))
+    }
+
+    /// where negative `addr` lives in the bank, if the policy allows one
+    fn bank_index(&self, addr: isize) -> Result<usize, Error> {
+        match self.negative_addresses {
+            NegativeAddresses::Fault => Err(Error::NegativeAddress(self.pc)),
+            NegativeAddresses::Bank => Ok((-1 - addr) as usize),
+        }
+    }
+
+    /// value at `addr`, checked against the negative address policy
+    pub fn load(&self, addr: isize) -> Result<isize, Error> {
+        match usize::try_from(addr) {
+            Ok(index) => Ok(self.get(index)),
+            Err(_) => Ok(self
+                .negative
+                .get(self.bank_index(addr)?)
+                .copied()
+                .unwrap_or(0)),
+        }
+    }
+
+    /// whether writing `index` of memory `len` cells long keeps to the limit
+    fn within_limit(&self, index: usize, len: usize) -> Result<(), Error> {
+        match self.memory_limit {
+            Some(limit) if index >= len && index >= limit => Err(Error::OutOfMemory(self.pc)),
+            _ => Ok(()),
+        }
+    }
+
+    /// write `v` at `addr`, checked against the negative address policy and
+    /// the memory limit
+    pub fn store(&mut self, addr: isize, v: isize) -> Result<(), Error> {
+        match usize::try_from(addr) {
+            Ok(index) => {
+                self.within_limit(index, self.prog.len())?;
+                self.set(index, v)
+            }
+            Err(_) => {
+                let index = self.bank_index(addr)?;
+                self.within_limit(index, self.negative.len())?;
+                let negative = Arc::make_mut(&mut self.negative);
+                if negative.len() <= index {
+                    negative.resize(index + 1, 0);
+                }
+                negative[index] = v;
+            }
+        }
+        Ok(())
+    }
+
+    /// parameter n of the current instruction, undecoded
+    fn param(&self, n: usize) -> Result<Param, Error> {
+        Ok(Param {
+            mode: self.parameter_mode(n)?,
+            raw: self.raw_parameter(n),
+        })
+    }
+
+    /// the address a parameter writes to
+    pub(crate) fn destination(&self, p: Param) -> Result<isize, Error> {
+        match p.mode {
+            ParameterMode::Position => Ok(p.raw),
+            ParameterMode::Immediate => Err(Error::InvalidMode(self.pc)),
+            ParameterMode::Relative => self.relative(p.raw),
+        }
+    }
+
+    /// the value a parameter reads, dereferenced according to its mode
+    pub(crate) fn value(&self, p: Param) -> Result<isize, Error> {
+        match p.mode {
+            ParameterMode::Position => self.load(p.raw),
+            ParameterMode::Immediate => Ok(p.raw),
+            ParameterMode::Relative => self.load(self.relative(p.raw)?),
+        }
+    }
+
+    fn position_param(&self, n: usize) -> Result<isize, Error> {
+        self.destination(self.param(n)?)
+    }
+
+    /// value for parameter, decoded and dereferenced based on position mode
+    fn parameter(&self, n: usize) -> Result<isize, Error> {
+        self.value(self.param(n)?)
+    }
+
+    /// value at `index`; memory past the end reads as zero
+    pub fn get(&self, index: usize) -> isize {
+        if index >= self.prog.len() {
+            0
+        } else {
+            self.prog[index]
+        }
+    }
+
+    /// write `v` at `dest`, growing memory to fit
+    pub fn set(&mut self, dest: usize, v: isize) {
+        if dest < self.image_len {
+            self.dirty[dest / PAGE] = true;
+        }
+        let mem = Arc::make_mut(&mut self.prog);
+        if mem.len() <= dest {
+            mem.resize(dest + 1, 0);
+        }
+        mem[dest] = v
+    }
+
+    /// An independent copy of the machine as it stands, input queue and all.
+    /// Memory is only copied once one side writes to it.
+    pub fn fork(&self) -> Machine {
+        self.clone()
+    }
+
+    /// decode parameter at position (params start at 1)
+    fn parameter_mode(&self, n: usize) -> Result<ParameterMode, Error> {
+        ParameterMode::of(self.get(self.pc), n).ok_or(Error::InvalidMode(self.pc))
+    }
+
+    /// extract opcode from current pc
+    fn opcode(&self) -> isize {
+        self.get(self.pc) % 100
+    }
+
+    /// store the result of a three parameter instruction
+    fn arithmetic(&mut self, f: fn(isize, isize) -> Option<isize>) -> Result<State, Error> {
+        let left = self.parameter(1)?;
+        let right = self.parameter(2)?;
+        let dest_pos = self.position_param(3)?;
+
+        let acc = f(left, right).ok_or(Error::Overflow(self.pc))?;
+        self.store(dest_pos, acc)?;
+        self.pc += 4;
+        Ok(State::Default)
+    }
+
+    /// Single step. Asking for input with none queued leaves the machine
+    /// where it is, so it can carry on once some has been pushed.
+    pub fn step(&mut self) -> Result<State, Error> {
+        match self.opcode() {
+            1 => self.arithmetic(isize::checked_add),
+            2 => self.arithmetic(isize::checked_mul),
+            3 => {
+                let dest_pos = self.position_param(1)?;
+                let acc = match self.input.pop_front() {
+                    Some(acc) => acc,
+                    None => return Ok(State::NeedInput),
+                };
+
+                self.store(dest_pos, acc)?;
+                self.pc += 2;
+                Ok(State::Input(dest_pos))
+            }
+            4 => {
+                let acc = self.parameter(1)?;
+
+                self.pc += 2;
+                Ok(State::Output(acc))
+            }
+            op @ 5 | op @ 6 => {
+                // jmp if true, jmp if false
+                let acc = self.parameter(1)?;
+
+                self.pc = if (acc != 0) == (op == 5) {
+                    self.address(self.parameter(2)?)?
+                } else {
+                    self.pc + 3
+                };
+                Ok(State::Default)
+            }
+            7 => self.arithmetic(|l, r| Some((l < r) as isize)),
+            8 => self.arithmetic(|l, r| Some((l == r) as isize)),
+            9 => {
+                // modify relative base
+                let acc = self.parameter(1)?;
+                self.relative_base = self.relative(acc)?;
+
+                self.pc += 2;
+                Ok(State::Default)
+            }
+            99 => Ok(State::Halt),
+            op => match self.opcodes.get(&op).cloned() {
+                Some(handler) => self.custom(&*handler),
+                None => Err(Error::InvalidOpcode(self.pc, op)),
+            },
+        }
+    }
+
+    /// run a registered opcode, moving past it unless it jumped, halted or
+    /// is waiting on input
+    fn custom(&mut self, handler: &dyn Opcode) -> Result<State, Error> {
+        let arity = handler.arity();
+        let params = (1..=arity)
+            .map(|n| self.param(n))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut ctx = Context::new(self);
+        let state = handler.execute(&mut ctx, &params)?;
+        let jump = ctx.jumped();
+
+        match state {
+            State::NeedInput | State::Halt => {}
+            _ => self.pc = jump.unwrap_or(self.pc + 1 + arity),
+        }
+        Ok(state)
+    }
+}
+
+/// What to do when a program addresses memory below zero.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum NegativeAddresses {
+    /// stop with `Error::NegativeAddress`
+    #[default]
+    Fault,
+    /// give them memory of their own, zeroed like everything past the end
+    Bank,
+}
+
+const BUILTIN: [isize; 10] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 99];
+
+/// the most parameters an instruction has mode digits for, the last at 10^18
+pub const MAX_ARITY: usize = 17;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParameterMode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+impl ParameterMode {
+    /// mode of parameter `n` (params start at 1) of `instruction`, None if
+    /// it's not one of these
+    pub fn of(instruction: isize, n: usize) -> Option<ParameterMode> {
+        let place = u32::try_from(n)
+            .ok()
+            .and_then(|n| 10_isize.checked_pow(n.checked_add(1)?))?;
+        match instruction / place % 10 {
+            0 => Some(ParameterMode::Position),
+            1 => Some(ParameterMode::Immediate),
+            2 => Some(ParameterMode::Relative),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum State {
+    Default,
+    Input(isize),
+    /// an input instruction found nothing queued
+    NeedInput,
+    Output(isize),
+    Halt,
+}
+
+/// what stopped the machine, each with the pc of the offending instruction
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    NegativeAddress(usize),
+    InvalidMode(usize),
+    InvalidOpcode(usize, isize),
+    Overflow(usize),
+    /// a store past the memory limit
+    OutOfMemory(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NegativeAddress(pc) => write!(f, "negative address at pc {}", pc),
+            Error::InvalidMode(pc) => write!(f, "invalid parameter mode at pc {}", pc),
+            Error::InvalidOpcode(pc, op) => write!(f, "invalid opcode {} at pc {}", op, pc),
+            Error::Overflow(pc) => write!(f, "arithmetic overflow at pc {}", pc),
+            Error::OutOfMemory(pc) => write!(f, "memory limit exceeded at pc {}", pc),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prog_1() {
+        let input: &[isize] = &[
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        let output = run_to_completion(input, &[]);
+        assert!(input.iter().zip(output.iter()).all(|(x, y)| x == y));
+    }
+
+    #[test]
+    fn test_prog_2() {
+        let input: &[isize] = &[1102, 34915192, 34915192, 7, 4, 7, 99, 0];
+        let output = run_to_completion(input, &[]);
+        assert!(format!("{}", output.first().unwrap()).len() == 16);
+    }
+
+    #[test]
+    fn test_prog_3() {
+        let input: &[isize] = &[104, 1125899906842624, 99];
+        let output = run_to_completion(input, &[]);
+        assert_eq!(1125899906842624, *output.first().unwrap());
+    }
+
+    #[test]
+    fn test_need_input() {
+        let mut m = Machine::new(&[3, 5, 4, 5, 99, 0], &[]);
+        assert_eq!(Ok(State::NeedInput), m.step());
+        assert_eq!(0, m.pc());
+
+        m.push_input(7);
+        assert_eq!(Ok(State::Input(5)), m.step());
+        assert_eq!(Ok(State::Output(7)), m.step());
+        assert_eq!(Ok(State::Halt), m.step());
+    }
+
+    #[test]
+    fn test_errors() {
+        let step = |prog: &[isize]| Machine::new(prog, &[]).step();
+
+        assert_eq!(Err(Error::InvalidOpcode(0, 42)), step(&[42]));
+        assert_eq!(Err(Error::InvalidMode(0)), step(&[301, 0, 0, 0]));
+        assert_eq!(Err(Error::InvalidMode(0)), step(&[10001, 0, 0, 0]));
+        assert_eq!(Err(Error::NegativeAddress(0)), step(&[1, -1, 0, 0]));
+        assert_eq!(Err(Error::NegativeAddress(0)), step(&[204, -1]));
+        assert_eq!(Err(Error::NegativeAddress(0)), step(&[1105, 1, -3]));
+        assert_eq!(Err(Error::Overflow(0)), step(&[1102, isize::MAX, 2, 0]));
+    }
+
+    #[test]
+    fn test_negative_relative_base() {
+        // base goes to -5, then reads [base + 7] = [2]
+        let output = run_to_completion(&[109, -5, 204, 7, 99], &[]);
+        assert_eq!(vec![204], output);
+    }
+
+    #[test]
+    fn test_negative_addresses() {
+        // [-1] = 42, then output [-1]
+        let prog = &[1101, 40, 2, -1, 4, -1, 99];
+
+        let mut m = Machine::new(prog, &[]);
+        assert_eq!(Err(Error::NegativeAddress(0)), m.step());
+
+        let mut m = Machine::new(prog, &[]);
+        m.negative_addresses(NegativeAddresses::Bank);
+        assert_eq!(Ok(State::Default), m.step());
+        assert_eq!(Ok(State::Output(42)), m.step());
+        assert_eq!(Ok(42), m.load(-1));
+        assert_eq!(Ok(0), m.load(-100));
+        assert_eq!(&prog[..], m.memory());
+
+        // relative writes land in the bank too
+        let mut m = Machine::new(&[109, -3, 203, 1, 99], &[9]);
+        m.negative_addresses(NegativeAddresses::Bank);
+        m.step().unwrap();
+        assert_eq!(Ok(State::Input(-2)), m.step());
+        assert_eq!(Ok(9), m.load(-2));
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        // [1 << 40] = 2, as a typo for 40 might
+        let far = &[1101, 1, 1, 1 << 40, 99];
+        let mut m = Machine::new(far, &[]);
+        m.memory_limit(100);
+        assert_eq!(Err(Error::OutOfMemory(0)), m.step());
+        assert_eq!(&far[..], m.memory());
+        assert_eq!(
+            "memory limit exceeded at pc 0",
+            Error::OutOfMemory(0).to_string()
+        );
+
+        // up to the limit is fine, and so is memory already there
+        let mut m = Machine::new(&[1101, 1, 1, 5, 99], &[]);
+        m.memory_limit(6);
+        assert_eq!(Ok(State::Default), m.step());
+        assert_eq!(6, m.memory().len());
+        let mut m = Machine::new(&[1101, 1, 1, 6, 99], &[]);
+        m.memory_limit(6);
+        assert_eq!(Err(Error::OutOfMemory(0)), m.step());
+        let mut m = Machine::new(&[1101, 1, 1, 3, 99], &[]);
+        m.memory_limit(2);
+        assert_eq!(Ok(State::Default), m.step());
+
+        // the bank is held to it too
+        let mut m = Machine::new(&[1101, 1, 1, -(1 << 40), 99], &[]);
+        m.negative_addresses(NegativeAddresses::Bank)
+            .memory_limit(100);
+        assert_eq!(Err(Error::OutOfMemory(0)), m.step());
+        m.reset(&[1101, 1, 1, -100, 99]);
+        assert_eq!(Ok(State::Default), m.step());
+        assert_eq!(Ok(2), m.load(-100));
+    }
+
+    #[test]
+    fn test_reset() {
+        // a long program that writes near the start, in the middle and past the end
+        let mut prog = vec![0; 300];
+        prog[..12].copy_from_slice(&[3, 200, 1101, 1, 2, 5, 1101, 3, 4, 400, 99, 0]);
+        prog[200] = 7;
+
+        let mut m = Machine::new(&prog, &[9]);
+        m.negative_addresses(NegativeAddresses::Bank);
+        m.store(-4, 1).unwrap();
+        while m.step().unwrap() != State::Halt {}
+        assert_eq!((9, 3, 7), (m.get(200), m.get(5), m.get(400)));
+
+        m.reset(&prog);
+        assert_eq!(&prog[..], m.memory());
+        assert_eq!((0, 0), (m.pc(), m.relative_base()));
+        assert_eq!(Ok(0), m.load(-4));
+        assert_eq!(Ok(State::NeedInput), m.step());
+
+        m.push_input(5);
+        while m.step().unwrap() != State::Halt {}
+        assert_eq!(5, m.get(200));
+
+        // a different program is loaded in full
+        m.reset(&[104, 1, 99]);
+        assert_eq!(&[104, 1, 99][..], m.memory());
+        assert_eq!(Ok(State::Output(1)), m.step());
+
+        // even one the same length, which differs in a page the last run
+        // didn't write
+        m.reset(&prog);
+        while m.step() != Ok(State::NeedInput) {}
+        let mut other = prog.clone();
+        other[1] = 250;
+        other[280] = 6;
+        m.reset(&other);
+        assert_eq!(&other[..], m.memory());
+    }
+
+    #[test]
+    fn test_fork_shares_until_written() {
+        let mut parent = Machine::new(&[1101, 1, 1, 5, 99, 0], &[]);
+        let mut child = parent.fork();
+        assert!(Arc::ptr_eq(&parent.prog, &child.prog));
+
+        child.step().unwrap();
+        assert!(!Arc::ptr_eq(&parent.prog, &child.prog));
+        assert_eq!((0, 2), (parent.get(5), child.get(5)));
+        assert_eq!((0, 4), (parent.pc(), child.pc()));
+
+        parent.set(5, 7);
+        assert_eq!((7, 2), (parent.get(5), child.get(5)));
+
+        // resetting a machine whose memory is shared leaves the other alone
+        let mut child = parent.fork();
+        child.reset(&[1101, 1, 1, 5, 99, 0]);
+        assert_eq!((7, 0), (parent.get(5), child.get(5)));
+    }
+
+    #[test]
+    fn test_fork_at_input() {
+        // output 10 * in1 + in2
+        let prog = &[
+            3, 15, 3, 16, 1002, 15, 10, 15, 1, 15, 16, 15, 4, 15, 99, 0, 0,
+        ];
+
+        // breadth first over two rounds of input, forking at each read
+        let mut frontier = VecDeque::from(vec![(Machine::new(prog, &[]), Vec::new())]);
+        let mut outputs = Vec::new();
+        while let Some((mut m, path)) = frontier.pop_front() {
+            match m.step().unwrap() {
+                State::NeedInput => {
+                    for candidate in 1..=3 {
+                        let mut child = m.fork();
+                        child.push_input(candidate);
+                        let mut path = path.clone();
+                        path.push(candidate);
+                        frontier.push_back((child, path));
+                    }
+                }
+                State::Output(o) => outputs.push((path, o)),
+                State::Halt => {}
+                _ => frontier.push_front((m, path)),
+            }
+        }
+
+        outputs.sort();
+        assert_eq!(9, outputs.len());
+        assert_eq!((vec![1, 1], 11), outputs[0]);
+        assert_eq!((vec![2, 3], 23), outputs[5]);
+        assert_eq!((vec![3, 3], 33), outputs[8]);
+    }
+
+    // the heatmap needs std
+    #[cfg(feature = "std")]
+    mod properties {
+        use super::*;
+        use crate::heatmap::Heatmap;
+        use proptest::prelude::*;
+
+        /// steps a random program gets before it's taken to be looping
+        const LIMIT: usize = 500;
+
+        /// A few instructions the machine knows, each in random modes and
+        /// with parameters that mostly land inside or just past the program.
+        fn program() -> impl Strategy<Value = Vec<isize>> {
+            let modes = (0..3_isize, 0..3_isize, 0..3_isize);
+            let params = prop::collection::vec(-2..48_isize, 3);
+            let instruction = (prop::sample::select(&BUILTIN[..]), modes, params).prop_map(
+                |(op, (a, b, c), mut params)| {
+                    params.truncate(match op {
+                        1 | 2 | 7 | 8 => 3,
+                        5 | 6 => 2,
+                        99 => 0,
+                        _ => 1,
+                    });
+                    let mut words = vec![op + 100 * a + 1000 * b + 10000 * c];
+                    words.extend(params);
+                    words
+                },
+            );
+            prop::collection::vec(instruction, 1..16).prop_map(|ins| ins.concat())
+        }
+
+        fn inputs() -> impl Strategy<Value = Vec<isize>> {
+            prop::collection::vec(-50..50_isize, 0..6)
+        }
+
+        /// step `m` at most `steps` times, until it stops, and collect its outputs
+        fn run(m: &mut Machine, steps: usize) -> Vec<isize> {
+            let mut outputs = Vec::new();
+            for _ in 0..steps {
+                match m.step() {
+                    Ok(State::Output(o)) => outputs.push(o),
+                    Ok(State::Default) | Ok(State::Input(_)) => {}
+                    _ => break,
+                }
+            }
+            outputs
+        }
+
+        proptest! {
+            #[test]
+            fn test_unwritten_memory_reads_zero(prog in program(), inputs in inputs()) {
+                let mut heat = Heatmap::new(prog.len());
+                let mut m = Machine::new(&prog, &inputs);
+                for _ in 0..LIMIT {
+                    match heat.step(&mut m) {
+                        Ok(State::Halt) | Ok(State::NeedInput) | Err(_) => break,
+                        _ => {}
+                    }
+                }
+
+                for addr in prog.len()..m.memory().len() + 16 {
+                    if heat.counts(addr).writes == 0 {
+                        prop_assert_eq!(0, m.get(addr), "at {}", addr);
+                    }
+                }
+            }
+
+            #[test]
+            fn test_immediate_destinations_error(
+                op in prop::sample::select(vec![1, 2, 3, 7, 8]),
+                (a, b) in (0..3_isize, 0..3_isize),
+                // sources are read first, so none of them can be negative
+                // for the destination to be what fails
+                params in prop::collection::vec(0..8_isize, 3),
+            ) {
+                let word = match op {
+                    3 => 103,
+                    _ => op + 100 * a + 1000 * b + 10000,
+                };
+                let mut prog = vec![word];
+                prog.extend(params);
+                prog.push(99);
+
+                let mut m = Machine::new(&prog, &[1]);
+                prop_assert_eq!(Err(Error::InvalidMode(0)), m.step());
+                prop_assert_eq!(&prog[..], m.memory());
+                prop_assert_eq!(0, m.pc());
+            }
+
+            #[test]
+            fn test_halting_is_idempotent(prog in program(), inputs in inputs()) {
+                let mut m = Machine::new(&prog, &inputs);
+                run(&mut m, LIMIT);
+                if m.opcode() == 99 {
+                    let (pc, memory) = (m.pc(), m.memory().to_vec());
+                    for _ in 0..3 {
+                        prop_assert_eq!(Ok(State::Halt), m.step());
+                    }
+                    prop_assert_eq!((pc, &memory[..]), (m.pc(), m.memory()));
+                }
+            }
+
+            #[test]
+            fn test_fork_mid_run(prog in program(), inputs in inputs(), at in 0..LIMIT) {
+                let straight = run(&mut Machine::new(&prog, &inputs), LIMIT);
+
+                let mut m = Machine::new(&prog, &inputs);
+                let mut outputs = run(&mut m, at);
+                let mut snapshot = m.fork();
+                // the original carrying on mustn't disturb the snapshot
+                run(&mut m, LIMIT);
+                outputs.extend(run(&mut snapshot, LIMIT - at));
+
+                prop_assert_eq!(straight, outputs);
+            }
+        }
+    }
+}
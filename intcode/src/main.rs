@@ -0,0 +1,1057 @@
+use intcode::dap;
+use intcode::decompile::decompile;
+use intcode::dump::{Diff, Dump};
+use intcode::explain;
+use intcode::gdb::{self, Stub};
+use intcode::heatmap::Heatmap;
+use intcode::image::{self, Image};
+use intcode::machine::{Error, NegativeAddresses};
+use intcode::optimize::{optimize, verify};
+use intcode::record::{self, Recorder, Recording};
+use intcode::{Machine, Program, State};
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::net::TcpListener;
+use std::process;
+
+const USAGE: &str = "\
+usage: intcode run <program> [--input 1,5] [--max-steps N] [--trace out.jsonl] [--json]
+                     [--negative-bank] [--record session.log]
+       intcode replay <program> <session.log>
+       intcode optimize <program> [--input 1,5] [--max-steps N] [--output out.ic]
+       intcode explain <program> [--input 1,5] [--max-steps N] [--negative-bank] [--set 1=12]
+       intcode decompile <program>
+       intcode convert <program> [--output out.icb]
+       intcode dump <program> [--input 1,5] [--max-steps N] [--negative-bank] [--set 1=12]
+                    [--width 8] [--hex]
+       intcode diff <program> [--input 1,5] [--against 2,6] [--max-steps N] [--negative-bank]
+                    [--set 1=12] [--width 8] [--hex]
+       intcode heatmap <program> [--input 1,5] [--max-steps N] [--negative-bank]
+                       [--width 64] [--scale 4] [--every N] [--output heat.ppm]
+       intcode gdb <program> [--input 1,5] [--negative-bank] [--port 1234]
+       intcode dap
+
+Options a command doesn't list are an error. Addresses below zero are an
+error unless --negative-bank gives them memory of their own. A session
+recorded with --record can be replayed, under the same --negative-bank
+setting, to check the program still consumes and produces exactly the same
+values at the same steps.
+
+optimize rewrites what it can prove does nothing or can be done more directly,
+checks the result against the original on the given inputs and prints it (or
+writes it to --output). It exits 1 if it can't, and reports why.
+
+explain runs the program and says what each instruction does in a sentence,
+with the values it reads and writes and where they come from:
+`pc 4: add 3 (from [100]) and 1 (immediate), store 4 into [100]`. It exits
+as run does.
+
+decompile prints the program as C-like pseudo-code, with the functions it
+calls through the relative base and the loops and branches it can recognise.
+
+convert turns a text program into the compact binary form, which every
+command here can load too, or a binary one back into text. The entry point
+and symbols a binary program carries are kept in text as `#!` comments. They
+are only carried along: every command starts a program at 0.
+
+dump runs the program and prints its memory, --width words to a row, with
+runs of zero rows shown as `*`. --max-steps 0 shows it as loaded. diff shows
+the rows that changed between the program as loaded and after the run, or
+with --against, between runs with --input and with the --against inputs.
+--set addr=value patches the program before it's run, as day 2 does with
+`--set 1=12 --set 2=2`. Both exit as run does.
+
+heatmap runs the program and draws its memory, a row of --width cells at a
+time: red for writes, green for reads, blue for code executed. It's drawn in
+the terminal unless --output names a PPM file. --every N also draws what each
+N steps touched, as heat-0000.ppm, heat-0001.ppm, ... next to the output or
+one after another in the terminal. It exits as run does.
+
+gdb waits on localhost for a GDB remote protocol client, such as gdb's
+`target remote localhost:1234`, and lets it debug the program. Cell n is at
+byte address 8n; `monitor input 1,2` queues input.
+
+dap is a Debug Adapter Protocol server on stdin and stdout, for editors to
+run as their debug adapter. The program and its inputs come from launch.
+
+exit status of run:
+  0  halted
+  1  runtime error
+  2  bad arguments or unreadable program
+  3  starved for input
+  4  ran out of steps";
+
+/// Run any Intcode program from the command line.
+fn main() {
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some("run") => match Options::parse("run", args) {
+            Ok(opts) => process::exit(run(&opts)),
+            Err(msg) => usage(&msg),
+        },
+        Some("optimize") => match Options::parse("optimize", args) {
+            Ok(opts) => process::exit(optimize_command(&opts)),
+            Err(msg) => usage(&msg),
+        },
+        Some("convert") => match Options::parse("convert", args) {
+            Ok(opts) => process::exit(convert(&opts)),
+            Err(msg) => usage(&msg),
+        },
+        Some("explain") => match Options::parse("explain", args) {
+            Ok(opts) => process::exit(explain_command(&opts)),
+            Err(msg) => usage(&msg),
+        },
+        Some("dump") => match Options::parse("dump", args) {
+            Ok(opts) => process::exit(dump(&opts)),
+            Err(msg) => usage(&msg),
+        },
+        Some("diff") => match Options::parse("diff", args) {
+            Ok(opts) => process::exit(diff(&opts)),
+            Err(msg) => usage(&msg),
+        },
+        Some("heatmap") => match Options::parse("heatmap", args) {
+            Ok(opts) => process::exit(heatmap(&opts)),
+            Err(msg) => usage(&msg),
+        },
+        Some("gdb") => match Options::parse("gdb", args) {
+            Ok(opts) => process::exit(debug(&opts)),
+            Err(msg) => usage(&msg),
+        },
+        Some("dap") => match args.next() {
+            None => {
+                let stdin = io::BufReader::new(io::stdin());
+                if let Err(e) = dap::serve(stdin, io::stdout()) {
+                    eprintln!("intcode: {}", e);
+                    process::exit(2);
+                }
+            }
+            Some(arg) => usage(&format!("unexpected argument `{}`", arg)),
+        },
+        Some("decompile") => match (args.next(), args.next()) {
+            (Some(path), None) => match Program::load(&path) {
+                Ok(prog) => print!("{}", decompile(&prog)),
+                Err(e) => {
+                    eprintln!("{}: {}", path, e);
+                    process::exit(2);
+                }
+            },
+            _ => usage("decompile needs a program"),
+        },
+        Some("replay") => match (args.next(), args.next(), args.next()) {
+            (Some(prog), Some(recording), None) => process::exit(replay(&prog, &recording)),
+            _ => usage("replay needs a program and a recording"),
+        },
+        Some("-h") | Some("--help") => println!("{}", USAGE),
+        Some(cmd) => usage(&format!("unknown command `{}`", cmd)),
+        None => usage("no command given"),
+    }
+}
+
+fn usage(msg: &str) -> ! {
+    eprintln!("intcode: {}\n\n{}", msg, USAGE);
+    process::exit(2)
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    path: String,
+    inputs: Vec<isize>,
+    max_steps: Option<usize>,
+    trace: Option<String>,
+    json: bool,
+    negative_bank: bool,
+    record: Option<String>,
+    output: Option<String>,
+    width: Option<usize>,
+    scale: Option<usize>,
+    every: Option<usize>,
+    port: Option<u16>,
+    hex: bool,
+    /// cells patched before running
+    sets: Vec<(usize, isize)>,
+    /// inputs for a second run to compare with
+    against: Option<Vec<isize>>,
+}
+
+/// the commands that take options
+const COMMANDS: [&str; 8] = [
+    "run", "optimize", "explain", "convert", "dump", "diff", "heatmap", "gdb",
+];
+
+/// the options each command reads
+fn takes(command: &str) -> &'static [&'static str] {
+    match command {
+        "run" => &[
+            "--input",
+            "--max-steps",
+            "--trace",
+            "--json",
+            "--negative-bank",
+            "--record",
+        ],
+        "optimize" => &["--input", "--max-steps", "--output"],
+        "explain" => &["--input", "--max-steps", "--negative-bank", "--set"],
+        "convert" => &["--output"],
+        "dump" => &[
+            "--input",
+            "--max-steps",
+            "--negative-bank",
+            "--set",
+            "--width",
+            "--hex",
+        ],
+        "diff" => &[
+            "--input",
+            "--against",
+            "--max-steps",
+            "--negative-bank",
+            "--set",
+            "--width",
+            "--hex",
+        ],
+        "heatmap" => &[
+            "--input",
+            "--max-steps",
+            "--negative-bank",
+            "--width",
+            "--scale",
+            "--every",
+            "--output",
+        ],
+        "gdb" => &["--input", "--negative-bank", "--port"],
+        _ => &[],
+    }
+}
+
+impl Options {
+    /// `command`'s options, refusing any it wouldn't use
+    fn parse<I: Iterator<Item = String>>(command: &str, mut args: I) -> Result<Options, String> {
+        let mut opts = Options::default();
+        let mut path = None;
+
+        while let Some(arg) = args.next() {
+            let known = COMMANDS.iter().any(|c| takes(c).contains(&arg.as_str()));
+            if known && !takes(command).contains(&arg.as_str()) {
+                return Err(format!("{} doesn't take {}", command, arg));
+            }
+            let mut value =
+                |name: &str| args.next().ok_or_else(|| format!("{} needs a value", name));
+
+            match arg.as_str() {
+                "--input" => {
+                    let v = value("--input")?;
+                    let inputs: Program = v.parse().map_err(|e| format!("--input {}: {}", v, e))?;
+                    opts.inputs.extend(inputs.iter());
+                }
+                "--max-steps" => {
+                    opts.max_steps = Some(count("--max-steps", value("--max-steps")?)?)
+                }
+                "--width" => opts.width = Some(count("--width", value("--width")?)?),
+                "--scale" => opts.scale = Some(count("--scale", value("--scale")?)?),
+                "--every" => opts.every = Some(count("--every", value("--every")?)?),
+                "--port" => {
+                    let v = value("--port")?;
+                    opts.port = Some(v.parse().map_err(|_| format!("--port {}: not a port", v))?)
+                }
+                "--against" => {
+                    let v = value("--against")?;
+                    let inputs: Program =
+                        v.parse().map_err(|e| format!("--against {}: {}", v, e))?;
+                    opts.against
+                        .get_or_insert_with(Vec::new)
+                        .extend(inputs.iter());
+                }
+                "--set" => {
+                    let v = value("--set")?;
+                    let set = v
+                        .split_once('=')
+                        .and_then(|(a, v)| Some((a.trim().parse().ok()?, v.trim().parse().ok()?)));
+                    opts.sets
+                        .push(set.ok_or_else(|| format!("--set {}: not addr=value", v))?);
+                }
+                "--trace" => opts.trace = Some(value("--trace")?),
+                "--record" => opts.record = Some(value("--record")?),
+                "--output" => opts.output = Some(value("--output")?),
+                "--json" => opts.json = true,
+                "--hex" => opts.hex = true,
+                "--negative-bank" => opts.negative_bank = true,
+                _ if arg.starts_with("--") => return Err(format!("unknown option `{}`", arg)),
+                _ if path.is_none() => path = Some(arg),
+                _ => return Err(format!("unexpected argument `{}`", arg)),
+            }
+        }
+
+        opts.path = path.ok_or("no program given")?;
+        Ok(opts)
+    }
+}
+
+/// a positive number given for option `name`
+fn count(name: &str, v: String) -> Result<usize, String> {
+    match v.parse() {
+        Ok(n) if n > 0 || name == "--max-steps" => Ok(n),
+        _ => Err(format!("{} {}: not a count", name, v)),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Status {
+    Halted,
+    Faulted(Error),
+    Starved,
+    Exhausted,
+}
+
+impl Status {
+    fn exit_code(&self) -> i32 {
+        match self {
+            Status::Halted => 0,
+            Status::Faulted(_) => 1,
+            Status::Starved => 3,
+            Status::Exhausted => 4,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Status::Halted => "halted",
+            Status::Faulted(_) => "faulted",
+            Status::Starved => "starved",
+            Status::Exhausted => "exhausted",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Summary {
+    status: Status,
+    steps: usize,
+    pc: usize,
+    outputs: Vec<isize>,
+}
+
+/// Step `m` until it stops or uses up `max_steps`, writing a JSON line per
+/// instruction executed to `trace` and recording inputs, outputs and the
+/// halt to `record` as they happen. Returns the machine as it was left.
+fn execute(
+    m: Machine,
+    max_steps: Option<usize>,
+    mut trace: Option<&mut dyn Write>,
+    record: &mut dyn Write,
+) -> io::Result<(Machine, Summary)> {
+    let mut recorder = Recorder::new(m, record);
+    let mut outputs = Vec::new();
+
+    let status = loop {
+        let steps = recorder.steps();
+        if max_steps.is_some_and(|max| steps >= max) {
+            break Status::Exhausted;
+        }
+
+        let m = recorder.machine();
+        let (pc, relative_base, instruction) = (m.pc(), m.relative_base(), m.get(m.pc()));
+        let state = match recorder.step() {
+            Ok(State::Halt) => break Status::Halted,
+            Ok(State::NeedInput) => break Status::Starved,
+            Ok(state) => state,
+            Err(e) => break Status::Faulted(e),
+        };
+
+        if let Some(w) = trace.as_mut() {
+            write!(
+                w,
+                r#"{{"step":{},"pc":{},"instruction":{},"relative_base":{}"#,
+                steps, pc, instruction, relative_base
+            )?;
+            match state {
+                // it was only just stored there, so the load can't fail
+                State::Input(dest) => {
+                    write!(w, r#","input":{}"#, recorder.machine().load(dest).unwrap())?
+                }
+                State::Output(o) => write!(w, r#","output":{}"#, o)?,
+                _ => {}
+            }
+            writeln!(w, "}}")?;
+        }
+
+        if let State::Output(o) = state {
+            outputs.push(o);
+        }
+    };
+
+    let steps = recorder.steps();
+    let (m, _) = recorder.finish()?;
+    let summary = Summary {
+        status,
+        steps,
+        pc: m.pc(),
+        outputs,
+    };
+    Ok((m, summary))
+}
+
+fn run(opts: &Options) -> i32 {
+    let prog = match Program::load(&opts.path) {
+        Ok(prog) => prog,
+        Err(e) => {
+            eprintln!("{}: {}", opts.path, e);
+            return 2;
+        }
+    };
+
+    let mut trace = match &opts.trace {
+        Some(path) => match File::create(path) {
+            Ok(f) => Some(BufWriter::new(f)),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                return 2;
+            }
+        },
+        None => None,
+    };
+
+    let mut record: Box<dyn Write> = match &opts.record {
+        Some(path) => match File::create(path) {
+            Ok(f) => Box::new(BufWriter::new(f)),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                return 2;
+            }
+        },
+        None => Box::new(io::sink()),
+    };
+
+    let mut m = Machine::new(&prog, &opts.inputs);
+    if opts.negative_bank {
+        m.negative_addresses(NegativeAddresses::Bank);
+    }
+    let summary = execute(
+        m,
+        opts.max_steps,
+        trace.as_mut().map(|w| w as &mut dyn Write),
+        &mut record,
+    )
+    .and_then(|(_, summary)| {
+        if let Some(w) = trace.as_mut() {
+            w.flush()?;
+        }
+        Ok(summary)
+    });
+    let summary = match summary {
+        Ok(summary) => summary,
+        Err(e) => {
+            // the trace or the recording
+            let paths = [&opts.trace, &opts.record];
+            let paths: Vec<&str> = paths.iter().filter_map(|p| p.as_deref()).collect();
+            eprintln!("{}: {}", paths.join(", "), e);
+            return 2;
+        }
+    };
+
+    if opts.json {
+        println!("{}", json(&summary));
+    } else {
+        for o in &summary.outputs {
+            println!("{}", o);
+        }
+        match &summary.status {
+            Status::Halted => {}
+            Status::Faulted(e) => eprintln!("intcode: {}", e),
+            Status::Starved => eprintln!(
+                "intcode: starved for input at pc {} after {} steps",
+                summary.pc, summary.steps
+            ),
+            Status::Exhausted => eprintln!(
+                "intcode: stopped at pc {} after {} steps",
+                summary.pc, summary.steps
+            ),
+        }
+    }
+
+    summary.status.exit_code()
+}
+
+/// 0 once the optimized program is written, 1 if it couldn't be optimized or
+/// doesn't behave the same
+fn optimize_command(opts: &Options) -> i32 {
+    let prog = match Program::load(&opts.path) {
+        Ok(prog) => prog,
+        Err(e) => {
+            eprintln!("{}: {}", opts.path, e);
+            return 2;
+        }
+    };
+
+    let (optimized, report) = match optimize(&prog) {
+        Ok(result) => result,
+        Err(refusal) => {
+            eprintln!("intcode: can't optimize: {}", refusal);
+            return 1;
+        }
+    };
+    let max_steps = opts.max_steps.unwrap_or(1_000_000);
+    let (before, after) = match verify(&prog, &optimized, &opts.inputs, max_steps) {
+        Ok(steps) => steps,
+        Err(mismatch) => {
+            eprintln!("intcode: optimized program differs: {}", mismatch);
+            return 1;
+        }
+    };
+
+    eprintln!(
+        "folded {}, moves {}, jumps {}, bypassed {}, threaded {}: {} instructions saved",
+        report.folded, report.moves, report.jumps, report.bypassed, report.threaded, report.saved
+    );
+    eprintln!("{} steps before, {} after", before, after);
+
+    let text = Program::new(optimized).to_string();
+    match &opts.output {
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, text + "\n") {
+                eprintln!("{}: {}", path, e);
+                return 2;
+            }
+        }
+        None => println!("{}", text),
+    }
+    0
+}
+
+/// text to binary or binary to text, written to --output or stdout
+fn convert(opts: &Options) -> i32 {
+    let binary = match std::fs::read(&opts.path) {
+        Ok(bytes) => bytes.starts_with(&image::MAGIC),
+        Err(e) => {
+            eprintln!("{}: {}", opts.path, e);
+            return 2;
+        }
+    };
+    let image = match Image::load(&opts.path) {
+        Ok(image) => image,
+        Err(e) => {
+            eprintln!("{}: {}", opts.path, e);
+            return 2;
+        }
+    };
+
+    let out = if binary {
+        image.to_string().into_bytes()
+    } else {
+        image.to_bytes()
+    };
+    let written = match &opts.output {
+        Some(path) => std::fs::write(path, out).map_err(|e| (path.as_str(), e)),
+        None => io::stdout().write_all(&out).map_err(|e| ("stdout", e)),
+    };
+    match written {
+        Ok(()) => 0,
+        Err((path, e)) => {
+            eprintln!("{}: {}", path, e);
+            2
+        }
+    }
+}
+
+/// the program as loaded and patched with --set, or the exit status if it
+/// couldn't be loaded
+fn load_patched(opts: &Options) -> Result<Vec<isize>, i32> {
+    let mut prog = match Program::load(&opts.path) {
+        Ok(prog) => prog.into_inner(),
+        Err(e) => {
+            eprintln!("{}: {}", opts.path, e);
+            return Err(2);
+        }
+    };
+    for &(addr, v) in &opts.sets {
+        if addr >= prog.len() {
+            prog.resize(addr + 1, 0);
+        }
+        prog[addr] = v;
+    }
+    Ok(prog)
+}
+
+/// why a run that didn't halt stopped
+fn report(status: &Status, steps: usize) {
+    match status {
+        Status::Halted => {}
+        Status::Faulted(e) => eprintln!("intcode: {}", e),
+        Status::Starved => eprintln!("intcode: starved for input after {} steps", steps),
+        Status::Exhausted => eprintln!("intcode: stopped after {} steps", steps),
+    }
+}
+
+/// The program as loaded and patched with --set, and its memory after
+/// running on `inputs`, or the exit status if it couldn't be loaded.
+fn run_for_memory(
+    opts: &Options,
+    inputs: &[isize],
+) -> Result<(Vec<isize>, Vec<isize>, Status), i32> {
+    let prog = load_patched(opts)?;
+    let mut m = Machine::new(&prog, inputs);
+    if opts.negative_bank {
+        m.negative_addresses(NegativeAddresses::Bank);
+    }
+    // nothing is written, so this can't fail
+    let (m, summary) = execute(m, opts.max_steps, None, &mut io::sink()).unwrap();
+    report(&summary.status, summary.steps);
+    Ok((prog, m.memory().to_vec(), summary.status))
+}
+
+/// a sentence for each instruction as it runs; exits as run does
+fn explain_command(opts: &Options) -> i32 {
+    let prog = match load_patched(opts) {
+        Ok(prog) => prog,
+        Err(code) => return code,
+    };
+    let mut m = Machine::new(&prog, &opts.inputs);
+    if opts.negative_bank {
+        m.negative_addresses(NegativeAddresses::Bank);
+    }
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut steps = 0;
+    let status = loop {
+        if opts.max_steps.is_some_and(|max| steps >= max) {
+            break Status::Exhausted;
+        }
+        let (state, text) = match explain::step(&mut m) {
+            Ok(step) => step,
+            Err(e) => break Status::Faulted(e),
+        };
+        match writeln!(out, "{}", text) {
+            Ok(()) => {}
+            // piped into something like head, which has seen enough
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return 0,
+            Err(e) => {
+                eprintln!("intcode: {}", e);
+                return 2;
+            }
+        }
+        match state {
+            State::Halt => break Status::Halted,
+            State::NeedInput => break Status::Starved,
+            _ => steps += 1,
+        }
+    };
+
+    if let Err(e) = out.flush() {
+        eprintln!("intcode: {}", e);
+        return 2;
+    }
+    report(&status, steps);
+    status.exit_code()
+}
+
+/// memory after the run; exits as run does
+fn dump(opts: &Options) -> i32 {
+    match run_for_memory(opts, &opts.inputs) {
+        Ok((_, memory, status)) => {
+            let dump = Dump::new(&memory).columns(opts.width.unwrap_or(8));
+            print!("{}", dump.hex(opts.hex));
+            status.exit_code()
+        }
+        Err(code) => code,
+    }
+}
+
+/// what the run changed, or what differs between two runs; exits as run
+/// does, with the first run's status if neither halted
+fn diff(opts: &Options) -> i32 {
+    let (before, after, status) = match run_for_memory(opts, &opts.inputs) {
+        Ok(run) => run,
+        Err(code) => return code,
+    };
+    let (before, after, status) = match &opts.against {
+        Some(inputs) => match run_for_memory(opts, inputs) {
+            Ok((_, other, other_status)) if status == Status::Halted => {
+                (after, other, other_status)
+            }
+            Ok((_, other, _)) => (after, other, status),
+            Err(code) => return code,
+        },
+        None => (before, after, status),
+    };
+
+    let diff = Diff::new(&before, &after)
+        .columns(opts.width.unwrap_or(8))
+        .hex(opts.hex)
+        .color(io::stdout().is_terminal());
+    print!("{}", diff);
+    status.exit_code()
+}
+
+/// `heat.ppm` with a frame number before the extension
+fn frame_path(path: &str, frame: usize) -> String {
+    match path.rfind('.') {
+        Some(dot) if !path[dot..].contains('/') => {
+            format!("{}-{:04}{}", &path[..dot], frame, &path[dot..])
+        }
+        _ => format!("{}-{:04}", path, frame),
+    }
+}
+
+/// the heatmap as a PPM at `path`, or in the terminal without one
+fn draw(heat: &Heatmap, path: Option<&str>, width: usize, scale: usize) -> io::Result<()> {
+    match path {
+        Some(path) => File::create(path)
+            .and_then(|f| heat.write_ppm(BufWriter::new(f), width, scale))
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e))),
+        None => {
+            print!("{}", heat.ansi(width));
+            io::stdout().flush()
+        }
+    }
+}
+
+/// exit status as for run, or 2 if a picture can't be written
+fn heatmap(opts: &Options) -> i32 {
+    let prog = match Program::load(&opts.path) {
+        Ok(prog) => prog,
+        Err(e) => {
+            eprintln!("{}: {}", opts.path, e);
+            return 2;
+        }
+    };
+    let (width, scale) = (opts.width.unwrap_or(64), opts.scale.unwrap_or(4));
+
+    let mut m = Machine::new(&prog, &opts.inputs);
+    if opts.negative_bank {
+        m.negative_addresses(NegativeAddresses::Bank);
+    }
+    let mut total = Heatmap::new(prog.len());
+    // what's been touched since the last frame
+    let mut window = Heatmap::new(prog.len());
+    let mut frames = 0;
+    let mut steps = 0;
+
+    let mut frame = |window: &mut Heatmap, total: &mut Heatmap| -> io::Result<()> {
+        if opts.every.is_some() {
+            let path = opts.output.as_ref().map(|p| frame_path(p, frames));
+            if path.is_none() {
+                print!("\x1b[2J\x1b[H");
+            }
+            draw(window, path.as_deref(), width, scale)?;
+            frames += 1;
+        }
+        total.merge(window);
+        window.clear();
+        Ok(())
+    };
+
+    let status = loop {
+        if opts.max_steps.is_some_and(|max| steps >= max) {
+            break Status::Exhausted;
+        }
+        match window.step(&mut m) {
+            Ok(State::Halt) => break Status::Halted,
+            Ok(State::NeedInput) => break Status::Starved,
+            Ok(_) => {}
+            Err(e) => break Status::Faulted(e),
+        }
+        steps += 1;
+
+        if opts.every.is_some_and(|n| steps % n == 0) {
+            if let Err(e) = frame(&mut window, &mut total) {
+                eprintln!("intcode: {}", e);
+                return 2;
+            }
+        }
+    };
+
+    // the last frame, unless it ended on one
+    let drawn = if opts.every.is_some_and(|n| steps % n == 0) {
+        Ok(())
+    } else {
+        frame(&mut window, &mut total)
+    };
+    let drawn = drawn.and_then(|_| draw(&total, opts.output.as_deref(), width, scale));
+    if let Err(e) = drawn {
+        eprintln!("intcode: {}", e);
+        return 2;
+    }
+
+    report(&status, steps);
+    status.exit_code()
+}
+
+/// serve one gdb session; 2 if it couldn't be had
+fn debug(opts: &Options) -> i32 {
+    let prog = match Program::load(&opts.path) {
+        Ok(prog) => prog,
+        Err(e) => {
+            eprintln!("{}: {}", opts.path, e);
+            return 2;
+        }
+    };
+
+    let mut m = Machine::new(&prog, &opts.inputs);
+    if opts.negative_bank {
+        m.negative_addresses(NegativeAddresses::Bank);
+    }
+    let mut stub = Stub::new(m);
+
+    let session = TcpListener::bind(("127.0.0.1", opts.port.unwrap_or(1234)))
+        .and_then(|listener| {
+            eprintln!("waiting for gdb on {}", listener.local_addr()?);
+            listener.accept()
+        })
+        .and_then(|(stream, peer)| {
+            eprintln!("debugging for {}", peer);
+            gdb::serve(&mut stub, stream)
+        });
+    match session {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("intcode: {}", e);
+            2
+        }
+    }
+}
+
+/// 0 if the program reproduces the recording, 1 where it doesn't
+fn replay(prog_path: &str, recording_path: &str) -> i32 {
+    let prog = match Program::load(prog_path) {
+        Ok(prog) => prog,
+        Err(e) => {
+            eprintln!("{}: {}", prog_path, e);
+            return 2;
+        }
+    };
+    let recording = match Recording::load(recording_path) {
+        Ok(recording) => recording,
+        Err(e) => {
+            eprintln!("{}: {}", recording_path, e);
+            return 2;
+        }
+    };
+
+    match record::replay(Machine::new(&prog, &[]), &recording) {
+        Ok(()) => {
+            println!("replayed {} events", recording.events.len());
+            0
+        }
+        Err(divergence) => {
+            eprintln!("intcode: {}", divergence);
+            1
+        }
+    }
+}
+
+fn json(summary: &Summary) -> String {
+    let outputs: Vec<String> = summary.outputs.iter().map(|o| o.to_string()).collect();
+    let mut s = format!(
+        r#"{{"status":"{}","steps":{},"pc":{},"outputs":[{}]"#,
+        summary.status.name(),
+        summary.steps,
+        summary.pc,
+        outputs.join(",")
+    );
+    if let Status::Faulted(e) = &summary.status {
+        // error messages never need escaping
+        s += &format!(r#","error":"{}""#, e);
+    }
+    s + "}"
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn parse(command: &str, args: &[&str]) -> Result<Options, String> {
+        Options::parse(command, args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_parse_options() {
+        assert_eq!(
+            Ok(Options {
+                path: "prog.ic".to_string(),
+                inputs: vec![1, 5, -2],
+                max_steps: Some(100),
+                negative_bank: true,
+                width: Some(32),
+                hex: true,
+                sets: vec![(1, 12), (2, -2)],
+                against: Some(vec![3, 4]),
+                ..Options::default()
+            }),
+            parse(
+                "diff",
+                &[
+                    "prog.ic",
+                    "--input",
+                    "1,5",
+                    "--max-steps",
+                    "100",
+                    "--input",
+                    "-2",
+                    "--negative-bank",
+                    "--width",
+                    "32",
+                    "--hex",
+                    "--set",
+                    "1=12",
+                    "--set",
+                    "2 = -2",
+                    "--against",
+                    "3,4",
+                ]
+            )
+        );
+        assert_eq!(
+            Ok(Options {
+                path: "prog.ic".to_string(),
+                trace: Some("out.jsonl".to_string()),
+                json: true,
+                ..Options::default()
+            }),
+            parse("run", &["--trace", "out.jsonl", "prog.ic", "--json"])
+        );
+        assert_eq!(
+            Ok(Some(4000)),
+            parse("gdb", &["a.ic", "--port", "4000"]).map(|o| o.port)
+        );
+
+        assert!(parse("run", &[]).is_err());
+        assert!(parse("run", &["a.ic", "b.ic"]).is_err());
+        assert!(parse("run", &["a.ic", "--max-steps"]).is_err());
+        assert!(parse("run", &["a.ic", "--max-steps", "-1"]).is_err());
+        assert!(parse("dump", &["a.ic", "--width", "0"]).is_err());
+        assert!(parse("gdb", &["a.ic", "--port", "70000"]).is_err());
+        assert!(parse("run", &["a.ic", "--input", "1,,2"]).is_err());
+        assert!(parse("run", &["a.ic", "--verbose"]).is_err());
+        assert!(parse("dump", &["a.ic", "--set", "1"]).is_err());
+        assert!(parse("dump", &["a.ic", "--set", "-1=2"]).is_err());
+    }
+
+    #[test]
+    fn test_unused_options() {
+        for (command, arg) in [
+            ("run", "--set"),
+            ("run", "--width"),
+            ("heatmap", "--set"),
+            ("gdb", "--set"),
+            ("gdb", "--max-steps"),
+            ("convert", "--input"),
+        ] {
+            assert_eq!(
+                Err(format!("{} doesn't take {}", command, arg)),
+                parse(command, &["a.ic", arg, "1=2"])
+            );
+        }
+        assert_eq!(
+            Err("unknown option `--verbose`".to_string()),
+            parse("run", &["a.ic", "--verbose"])
+        );
+
+        // every option a command lists is one parse knows
+        for command in COMMANDS {
+            for arg in takes(command) {
+                let unknown = format!("unknown option `{}`", arg);
+                assert_ne!(Err(unknown), parse(command, &["a.ic", arg, "1"]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_frame_path() {
+        assert_eq!("heat-0003.ppm", frame_path("heat.ppm", 3));
+        assert_eq!("out/heat-0012", frame_path("out/heat", 12));
+        assert_eq!("a.b/heat-0000", frame_path("a.b/heat", 0));
+    }
+
+    #[test]
+    fn test_execute() {
+        // echo one input
+        let prog = &[3, 5, 4, 5, 99, 0];
+
+        let run = |m, max_steps| execute(m, max_steps, None, &mut io::sink()).unwrap().1;
+
+        let summary = run(Machine::new(prog, &[7]), None);
+        assert_eq!(
+            (Status::Halted, 2, vec![7]),
+            (summary.status, summary.steps, summary.outputs)
+        );
+
+        let summary = run(Machine::new(prog, &[]), None);
+        assert_eq!((Status::Starved, 0), (summary.status, summary.steps));
+
+        let summary = run(Machine::new(prog, &[7]), Some(1));
+        assert_eq!((Status::Exhausted, 2), (summary.status, summary.pc));
+
+        let summary = run(Machine::new(&[42], &[]), None);
+        assert_eq!(Status::Faulted(Error::InvalidOpcode(0, 42)), summary.status);
+        assert_eq!(1, summary.status.exit_code());
+    }
+
+    #[test]
+    fn test_trace_and_json() {
+        let mut trace = Vec::new();
+        let (_, summary) = execute(
+            Machine::new(&[3, 5, 4, 5, 99, 0], &[7]),
+            None,
+            Some(&mut trace),
+            &mut io::sink(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            "{\"step\":0,\"pc\":0,\"instruction\":3,\"relative_base\":0,\"input\":7}\n\
+             {\"step\":1,\"pc\":2,\"instruction\":4,\"relative_base\":0,\"output\":7}\n",
+            String::from_utf8(trace).unwrap()
+        );
+        assert_eq!(
+            r#"{"status":"halted","steps":2,"pc":4,"outputs":[7]}"#,
+            json(&summary)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_steps_match_trace(
+            // words that are mostly instructions or addresses inside the program
+            prog in prop::collection::vec(
+                prop_oneof![
+                    prop::sample::select(vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 99, 1001, 1101, 1105, 109, 204]),
+                    -2..40_isize,
+                ],
+                1..40,
+            ),
+            inputs in prop::collection::vec(-50..50_isize, 0..6),
+            max_steps in 0..300_usize,
+        ) {
+            let mut trace = Vec::new();
+            let m = Machine::new(&prog, &inputs);
+            let (_, summary) = execute(m, Some(max_steps), Some(&mut trace), &mut io::sink()).unwrap();
+
+            let trace = String::from_utf8(trace).unwrap();
+            prop_assert_eq!(summary.steps, trace.lines().count());
+            for (n, line) in trace.lines().enumerate() {
+                let step = format!("{{\"step\":{},", n);
+                prop_assert!(line.starts_with(&step), "{}", line);
+            }
+        }
+    }
+
+    #[test]
+    fn test_record() {
+        let mut log = Vec::new();
+        let prog = &[3, 5, 4, 5, 99, 0];
+        execute(Machine::new(prog, &[7]), None, None, &mut log).unwrap();
+
+        let log = String::from_utf8(log).unwrap();
+        assert_eq!("input 0 7\noutput 1 7\nhalt 2\n", log);
+        let recording: Recording = log.parse().unwrap();
+        assert_eq!(Ok(()), record::replay(Machine::new(prog, &[]), &recording));
+        // the policy travels with the recording
+        let prog = &[1101, 5, 6, -1, 4, -1, 99];
+        let mut m = Machine::new(prog, &[]);
+        m.negative_addresses(NegativeAddresses::Bank);
+        let mut log = Vec::new();
+        execute(m, None, None, &mut log).unwrap();
+        let recording: Recording = String::from_utf8(log).unwrap().parse().unwrap();
+        assert_eq!(Ok(()), record::replay(Machine::new(prog, &[]), &recording));
+    }
+}
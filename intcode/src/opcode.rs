@@ -0,0 +1,234 @@
+//! Extra instructions for prototyping extended instruction sets. Anything
+//! registered with `Machine::register` is consulted when the machine meets
+//! an opcode it doesn't understand itself.
+
+use crate::machine::{Error, Machine, ParameterMode, State};
+
+/// A parameter as it appears in the instruction, before dereferencing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Param {
+    pub mode: ParameterMode,
+    pub raw: isize,
+}
+
+pub trait Opcode: Send + Sync {
+    /// how many parameters follow the instruction
+    fn arity(&self) -> usize;
+
+    /// Carry out the instruction. The machine moves past it afterwards
+    /// unless it jumped, or returned `Halt` or `NeedInput`; a handler
+    /// returning `NeedInput` should do so before changing anything.
+    fn execute(&self, ctx: &mut Context, params: &[Param]) -> Result<State, Error>;
+}
+
+/// What an opcode can see and change of the machine running it.
+pub struct Context<'a> {
+    machine: &'a mut Machine,
+    jump: Option<usize>,
+}
+
+impl<'a> Context<'a> {
+    pub(crate) fn new(machine: &'a mut Machine) -> Self {
+        Context {
+            machine,
+            jump: None,
+        }
+    }
+
+    pub(crate) fn jumped(&self) -> Option<usize> {
+        self.jump
+    }
+
+    /// address of the instruction being executed
+    pub fn pc(&self) -> usize {
+        self.machine.pc()
+    }
+
+    pub fn relative_base(&self) -> isize {
+        self.machine.relative_base()
+    }
+
+    pub fn get(&self, addr: usize) -> isize {
+        self.machine.get(addr)
+    }
+
+    pub fn set(&mut self, addr: usize, v: isize) {
+        self.machine.set(addr, v)
+    }
+
+    /// the value `p` stands for, dereferenced according to its mode
+    pub fn read(&self, p: Param) -> Result<isize, Error> {
+        self.machine.value(p)
+    }
+
+    /// store `v` where `p` points
+    pub fn write(&mut self, p: Param, v: isize) -> Result<(), Error> {
+        let dest = self.machine.destination(p)?;
+        self.machine.store(dest, v)
+    }
+
+    /// the next queued input, if there is one
+    pub fn input(&mut self) -> Option<isize> {
+        self.machine.pop_input()
+    }
+
+    /// carry on from `target` instead of the next instruction
+    pub fn jump(&mut self, target: usize) {
+        self.jump = Some(target);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// [p3] = p1 op p2, failing where op does
+    struct Binary(fn(isize, isize) -> Option<isize>);
+
+    impl Opcode for Binary {
+        fn arity(&self) -> usize {
+            3
+        }
+
+        fn execute(&self, ctx: &mut Context, params: &[Param]) -> Result<State, Error> {
+            let left = ctx.read(params[0])?;
+            let right = ctx.read(params[1])?;
+            let acc = (self.0)(left, right).ok_or(Error::Overflow(ctx.pc()))?;
+            ctx.write(params[2], acc)?;
+            Ok(State::Default)
+        }
+    }
+
+    /// print the zero terminated string at p1
+    struct PrintStr(Arc<Mutex<String>>);
+
+    impl Opcode for PrintStr {
+        fn arity(&self) -> usize {
+            1
+        }
+
+        fn execute(&self, ctx: &mut Context, params: &[Param]) -> Result<State, Error> {
+            let mut addr = ctx.read(params[0])? as usize;
+            let mut out = self.0.lock().unwrap();
+            while ctx.get(addr) != 0 {
+                out.push(ctx.get(addr) as u8 as char);
+                addr += 1;
+            }
+            Ok(State::Default)
+        }
+    }
+
+    /// jump to p1 if the next input is odd
+    struct JumpIfOddInput;
+
+    impl Opcode for JumpIfOddInput {
+        fn arity(&self) -> usize {
+            1
+        }
+
+        fn execute(&self, ctx: &mut Context, params: &[Param]) -> Result<State, Error> {
+            let target = ctx.read(params[0])? as usize;
+            match ctx.input() {
+                Some(v) if v % 2 != 0 => ctx.jump(target),
+                Some(_) => {}
+                None => return Ok(State::NeedInput),
+            }
+            Ok(State::Default)
+        }
+    }
+
+    #[test]
+    fn test_mod_div() {
+        // out(17 % 5), out([12] / 3)
+        let prog = &[1110, 17, 5, 13, 4, 13, 1011, 12, 3, 13, 4, 13, 99, 14];
+        let mut m = Machine::new(prog, &[]);
+        m.register(10, Binary(isize::checked_rem))
+            .register(11, Binary(isize::checked_div));
+
+        let mut outputs = Vec::new();
+        loop {
+            match m.step().unwrap() {
+                State::Output(o) => outputs.push(o),
+                State::Halt => break,
+                _ => {}
+            }
+        }
+        assert_eq!(vec![2, 33], outputs);
+
+        let mut m = Machine::new(&[1111, 1, 0, 5, 99, 0], &[]);
+        m.register(11, Binary(isize::checked_div));
+        assert_eq!(Err(Error::Overflow(0)), m.step());
+    }
+
+    #[test]
+    fn test_print_str() {
+        let out = Arc::new(Mutex::new(String::new()));
+        let mut m = Machine::new(&[112, 3, 99, 104, 105, 0], &[]);
+        m.register(12, PrintStr(out.clone()));
+
+        assert_eq!(Ok(State::Default), m.step());
+        assert_eq!(Ok(State::Halt), m.step());
+        assert_eq!("hi", *out.lock().unwrap());
+    }
+
+    #[test]
+    fn test_jump_and_starve() {
+        let prog = &[120, 4, 104, 0, 104, 1, 99];
+        let mut m = Machine::new(prog, &[]);
+        m.register(20, JumpIfOddInput);
+
+        assert_eq!(Ok(State::NeedInput), m.step());
+        assert_eq!(0, m.pc());
+
+        m.push_input(3);
+        assert_eq!(Ok(State::Default), m.step());
+        assert_eq!(4, m.pc());
+        assert_eq!(Ok(State::Output(1)), m.step());
+
+        let mut m = Machine::new(prog, &[2]);
+        m.register(20, JumpIfOddInput);
+        m.step().unwrap();
+        assert_eq!(2, m.pc());
+    }
+
+    #[test]
+    fn test_unregistered() {
+        let mut m = Machine::new(&[10, 0, 0, 0], &[]);
+        assert_eq!(Err(Error::InvalidOpcode(0, 10)), m.step());
+
+        m.register(11, Binary(isize::checked_div));
+        assert_eq!(Err(Error::InvalidOpcode(0, 10)), m.step());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_register_builtin() {
+        Machine::new(&[], &[]).register(1, JumpIfOddInput);
+    }
+
+    struct Wide;
+
+    impl Opcode for Wide {
+        fn arity(&self) -> usize {
+            18
+        }
+
+        fn execute(&self, _: &mut Context, _: &[Param]) -> Result<State, Error> {
+            Ok(State::Default)
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_register_too_wide() {
+        Machine::new(&[], &[]).register(10, Wide);
+    }
+
+    #[test]
+    fn test_modes_past_the_word() {
+        assert_eq!(Some(ParameterMode::Position), ParameterMode::of(10, 17));
+        assert_eq!(None, ParameterMode::of(10, 18));
+        assert_eq!(None, ParameterMode::of(10, usize::MAX));
+    }
+}
@@ -0,0 +1,643 @@
+//! A peephole optimizer over program images.
+//!
+//! Rewriting is only safe when the control flow and the code itself are
+//! known up front, so the optimizer first walks every path from address 0
+//! and refuses programs it can't account for: jumps to computed addresses,
+//! relative mode (whose addresses aren't known until run time) and writes
+//! that land in reachable code. Instructions whose cells the program reads
+//! as data are left alone too.
+//!
+//! Nothing can be deleted outright, since that would move every address
+//! after it, so the savings come from jumping over instructions that no
+//! longer do anything and from jumps that go straight to where a chain of
+//! jumps would have ended up.
+
+use crate::decode::{decode, Instruction, Op};
+use crate::machine::{Error, Machine, ParameterMode, State};
+use crate::opcode::Param;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// why a program can't be optimized
+#[derive(Debug, Clone, PartialEq)]
+pub enum Refusal {
+    /// no valid instruction at this reachable address
+    Invalid(usize),
+    /// a jump whose target is read from memory
+    IndirectJump(usize),
+    RelativeMode(usize),
+    /// the instruction at `pc` writes into code at `addr`
+    SelfModifying {
+        pc: usize,
+        addr: usize,
+    },
+    /// the instruction at `pc` would be rewritten, but shares cells with
+    /// the one at `other`
+    Overlapping {
+        pc: usize,
+        other: usize,
+    },
+    /// the instruction at this address would be rewritten, but runs past
+    /// the end of the image
+    Truncated(usize),
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Refusal::Invalid(pc) => write!(f, "no valid instruction at {}", pc),
+            Refusal::IndirectJump(pc) => write!(f, "jump to a computed address at {}", pc),
+            Refusal::RelativeMode(pc) => write!(f, "relative mode at {}", pc),
+            Refusal::SelfModifying { pc, addr } => {
+                write!(f, "instruction at {} writes to code at {}", pc, addr)
+            }
+            Refusal::Overlapping { pc, other } => write!(
+                f,
+                "instruction at {} overlaps the one at {} and can't be rewritten",
+                pc, other
+            ),
+            Refusal::Truncated(pc) => write!(
+                f,
+                "instruction at {} runs past the end and can't be rewritten",
+                pc
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Refusal {}
+
+/// What was rewritten, and how many instructions a pass over each rewritten
+/// spot no longer executes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    /// arithmetic on two immediates, now a move of the result
+    pub folded: usize,
+    /// adds of zero and multiplies by one, now plain moves
+    pub moves: usize,
+    /// jumps whose condition is an immediate, now unconditional or gone
+    pub jumps: usize,
+    /// runs of instructions that do nothing, now jumped over
+    pub bypassed: usize,
+    /// jumps retargeted past other jumps or do-nothing instructions
+    pub threaded: usize,
+    pub saved: usize,
+}
+
+/// what's to become of an instruction
+#[derive(Debug, Clone, PartialEq)]
+enum Rewrite {
+    Keep,
+    Replace(Instruction),
+    /// does nothing; execution carries on with the next instruction
+    Nop,
+}
+
+fn imm(raw: isize) -> Param {
+    Param {
+        mode: ParameterMode::Immediate,
+        raw,
+    }
+}
+
+fn is_imm(p: &Param, v: isize) -> bool {
+    p.mode == ParameterMode::Immediate && p.raw == v
+}
+
+/// `add #v, #0 -> dest`
+fn move_const(ins: &Instruction, v: isize) -> Instruction {
+    Instruction {
+        pc: ins.pc,
+        op: Op::Add,
+        params: vec![imm(v), imm(0), ins.params[2]],
+    }
+}
+
+/// `add src, #0 -> dest`, or nothing at all when src is dest
+fn move_from(ins: &Instruction, src: Param) -> Rewrite {
+    if src == ins.params[2] {
+        return Rewrite::Nop;
+    }
+    Rewrite::Replace(Instruction {
+        pc: ins.pc,
+        op: Op::Add,
+        params: vec![src, imm(0), ins.params[2]],
+    })
+}
+
+fn jump(pc: usize, target: usize) -> Instruction {
+    Instruction {
+        pc,
+        op: Op::JumpIfFalse,
+        params: vec![imm(0), imm(target as isize)],
+    }
+}
+
+/// an unconditional jump's target
+fn always_jumps(ins: &Instruction) -> Option<usize> {
+    let taken = match ins.op {
+        Op::JumpIfTrue => ins.params[0].mode == ParameterMode::Immediate && ins.params[0].raw != 0,
+        Op::JumpIfFalse => is_imm(&ins.params[0], 0),
+        _ => false,
+    };
+    if taken {
+        Some(ins.params[1].raw as usize)
+    } else {
+        None
+    }
+}
+
+/// a simpler equivalent of one instruction on its own
+fn simplify(ins: &Instruction) -> Rewrite {
+    let p = &ins.params;
+    let both_imm = |f: fn(isize, isize) -> Option<isize>| {
+        if p[0].mode == ParameterMode::Immediate && p[1].mode == ParameterMode::Immediate {
+            f(p[0].raw, p[1].raw)
+        } else {
+            None
+        }
+    };
+
+    let rewrite = match ins.op {
+        Op::LessThan | Op::Equals => {
+            let folded = if ins.op == Op::LessThan {
+                both_imm(|a, b| Some((a < b) as isize))
+            } else {
+                both_imm(|a, b| Some((a == b) as isize))
+            };
+            match folded {
+                Some(v) => Rewrite::Replace(move_const(ins, v)),
+                None => Rewrite::Keep,
+            }
+        }
+        Op::Add | Op::Mul => {
+            let (folded, identity) = if ins.op == Op::Add {
+                (both_imm(isize::checked_add), 0)
+            } else {
+                (both_imm(isize::checked_mul), 1)
+            };
+
+            if let Some(v) = folded {
+                Rewrite::Replace(move_const(ins, v))
+            } else if ins.op == Op::Mul && (is_imm(&p[0], 0) || is_imm(&p[1], 0)) {
+                Rewrite::Replace(move_const(ins, 0))
+            } else if is_imm(&p[1], identity) {
+                move_from(ins, p[0])
+            } else if is_imm(&p[0], identity) {
+                move_from(ins, p[1])
+            } else {
+                Rewrite::Keep
+            }
+        }
+        Op::JumpIfTrue | Op::JumpIfFalse if p[0].mode == ParameterMode::Immediate => {
+            match always_jumps(ins) {
+                Some(target) => Rewrite::Replace(jump(ins.pc, target)),
+                None => Rewrite::Nop,
+            }
+        }
+        _ => Rewrite::Keep,
+    };
+
+    match rewrite {
+        Rewrite::Replace(ref new) if new == ins => Rewrite::Keep,
+        r => r,
+    }
+}
+
+/// whatever writes to `pc`, when that explains there being no valid
+/// instruction there yet
+fn patched(code: &BTreeMap<usize, Instruction>, pc: usize) -> Option<Refusal> {
+    code.values().find_map(|ins| {
+        let dest = ins.params[ins.op.destination()?];
+        if dest.mode == ParameterMode::Position && dest.raw == pc as isize {
+            Some(Refusal::SelfModifying {
+                pc: ins.pc,
+                addr: pc,
+            })
+        } else {
+            None
+        }
+    })
+}
+
+/// every instruction reachable from 0, checked against the rules above
+fn analyse(prog: &[isize]) -> Result<(BTreeMap<usize, Instruction>, BTreeSet<usize>), Refusal> {
+    let mut code = BTreeMap::new();
+    let mut pending = vec![0];
+
+    while let Some(mut pc) = pending.pop() {
+        while !code.contains_key(&pc) {
+            let ins = match decode(prog, pc) {
+                Some(ins) => ins,
+                None => return Err(patched(&code, pc).unwrap_or(Refusal::Invalid(pc))),
+            };
+            if ins.params.iter().any(|p| p.mode == ParameterMode::Relative) {
+                return Err(Refusal::RelativeMode(pc));
+            }
+
+            let next = pc + ins.width();
+            let mut falls_through = true;
+            match ins.op {
+                Op::JumpIfTrue | Op::JumpIfFalse => {
+                    let target = ins.params[1];
+                    if target.mode != ParameterMode::Immediate {
+                        return Err(Refusal::IndirectJump(pc));
+                    }
+                    let never = ins.params[0].mode == ParameterMode::Immediate
+                        && always_jumps(&ins).is_none();
+                    if !never {
+                        if target.raw < 0 {
+                            return Err(Refusal::Invalid(pc));
+                        }
+                        pending.push(target.raw as usize);
+                    }
+                    falls_through = always_jumps(&ins).is_none();
+                }
+                Op::Halt => falls_through = false,
+                _ => {}
+            }
+
+            code.insert(pc, ins);
+            if !falls_through {
+                break;
+            }
+            pc = next;
+        }
+    }
+
+    let cells: BTreeSet<usize> = code
+        .values()
+        .flat_map(|ins| ins.pc..ins.pc + ins.width())
+        .collect();
+
+    // what the program reads as data, and checking nothing writes to code
+    let mut reads = BTreeSet::new();
+    for ins in code.values() {
+        for (n, p) in ins.params.iter().enumerate() {
+            if p.mode != ParameterMode::Position {
+                continue;
+            }
+            if p.raw < 0 {
+                return Err(Refusal::Invalid(ins.pc));
+            }
+            let addr = p.raw as usize;
+            if ins.op.destination() == Some(n) {
+                if cells.contains(&addr) {
+                    return Err(Refusal::SelfModifying { pc: ins.pc, addr });
+                }
+            } else {
+                reads.insert(addr);
+            }
+        }
+    }
+
+    Ok((code, reads))
+}
+
+/// Optimize `prog`, returning the new image and what changed.
+pub fn optimize(prog: &[isize]) -> Result<(Vec<isize>, Report), Refusal> {
+    let (code, reads) = analyse(prog)?;
+    let mut report = Report::default();
+
+    // instructions the program also reads as data have to stay as they are
+    let read = |ins: &Instruction| (ins.pc..ins.pc + ins.width()).any(|cell| reads.contains(&cell));
+    // writing over an instruction can only change that instruction
+    let untangled = |ins: &Instruction| -> Result<(), Refusal> {
+        let end = ins.pc + ins.width();
+        if end > prog.len() {
+            return Err(Refusal::Truncated(ins.pc));
+        }
+        // no instruction is wider than 4
+        let mut near = code.range(ins.pc.saturating_sub(3)..end);
+        match near.find(|(&pc, other)| pc != ins.pc && pc + other.width() > ins.pc) {
+            Some((&other, _)) => Err(Refusal::Overlapping { pc: ins.pc, other }),
+            None => Ok(()),
+        }
+    };
+    let mut rewrites: BTreeMap<usize, Rewrite> = BTreeMap::new();
+    for (&pc, ins) in &code {
+        if read(ins) {
+            continue;
+        }
+        let rewrite = simplify(ins);
+        if rewrite != Rewrite::Keep {
+            untangled(ins)?;
+        }
+        match (&rewrite, ins.op) {
+            (Rewrite::Keep, _) => continue,
+            (_, Op::JumpIfTrue) | (_, Op::JumpIfFalse) => report.jumps += 1,
+            (Rewrite::Replace(new), _) if new.params[0].mode == ParameterMode::Immediate => {
+                report.folded += 1
+            }
+            _ => report.moves += 1,
+        }
+        rewrites.insert(pc, rewrite);
+    }
+
+    let rewritten = |pc: usize| -> Option<Instruction> {
+        match rewrites.get(&pc) {
+            Some(Rewrite::Replace(new)) => Some(new.clone()),
+            Some(Rewrite::Nop) => None,
+            _ => code.get(&pc).cloned(),
+        }
+    };
+    let is_nop = |pc: usize| rewrites.get(&pc) == Some(&Rewrite::Nop);
+
+    // where execution really ends up from `target`, and how many
+    // instructions it gets through on the way
+    let resolve = |mut target: usize| -> (usize, usize) {
+        let mut skipped = 0;
+        let mut seen = BTreeSet::new();
+        while seen.insert(target) {
+            let next = match code.get(&target) {
+                Some(ins) if is_nop(target) => target + ins.width(),
+                Some(_) => match rewritten(target).as_ref().and_then(always_jumps) {
+                    Some(next) => next,
+                    None => break,
+                },
+                None => break,
+            };
+            target = next;
+            skipped += 1;
+        }
+        (target, skipped)
+    };
+
+    let mut out = prog.to_vec();
+    let write = |out: &mut Vec<isize>, ins: &Instruction| {
+        out[ins.pc..ins.pc + ins.width()].copy_from_slice(&ins.encode());
+    };
+
+    for (&pc, rewrite) in &rewrites {
+        if let Rewrite::Replace(new) = rewrite {
+            write(&mut out, new);
+        }
+        // runs of nops are dealt with from their first instruction
+        let starts_run = code
+            .range(..pc)
+            .next_back()
+            .is_none_or(|(&prev, ins)| prev + ins.width() != pc || !is_nop(prev));
+        if *rewrite == Rewrite::Nop && starts_run && code[&pc].width() >= 3 {
+            let (target, skipped) = resolve(pc);
+            if skipped > 1 {
+                write(&mut out, &jump(pc, target));
+                report.bypassed += 1;
+                report.saved += skipped - 1;
+            }
+        }
+    }
+
+    // jumps, old or new, that land on more jumps or on nops
+    for (&pc, ins) in &code {
+        if is_nop(pc) || read(ins) {
+            continue;
+        }
+        // only ever rewritten as itself, so it's still valid
+        let current = decode(&out, pc).unwrap();
+        if let Some(target) = always_jumps(&current) {
+            let (resolved, skipped) = resolve(target);
+            if skipped > 0 && resolved != target {
+                untangled(ins)?;
+                let threaded = Instruction {
+                    params: vec![current.params[0], imm(resolved as isize)],
+                    ..current
+                };
+                write(&mut out, &threaded);
+                report.threaded += 1;
+                report.saved += skipped;
+            }
+        }
+    }
+
+    Ok((out, report))
+}
+
+/// how a run of a program ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stop {
+    Halted,
+    NeedInput,
+    Error(Error),
+    OutOfSteps,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    pub outputs: Vec<isize>,
+    pub steps: usize,
+    pub stop: Stop,
+}
+
+fn run(prog: &[isize], inputs: &[isize], max_steps: usize) -> Run {
+    let mut m = Machine::new(prog, inputs);
+    let mut outputs = Vec::new();
+
+    for steps in 0..max_steps {
+        let stop = match m.step() {
+            Ok(State::Output(o)) => {
+                outputs.push(o);
+                continue;
+            }
+            Ok(State::Halt) => Stop::Halted,
+            Ok(State::NeedInput) => Stop::NeedInput,
+            Ok(_) => continue,
+            Err(e) => Stop::Error(e),
+        };
+        return Run {
+            outputs,
+            steps,
+            stop,
+        };
+    }
+
+    Run {
+        outputs,
+        steps: max_steps,
+        stop: Stop::OutOfSteps,
+    }
+}
+
+/// the original and optimized runs, when they don't agree
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub original: Run,
+    pub optimized: Run,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "original gave {:?} then {:?}, optimized gave {:?} then {:?}",
+            self.original.outputs, self.original.stop, self.optimized.outputs, self.optimized.stop
+        )
+    }
+}
+
+impl std::error::Error for Mismatch {}
+
+/// Run both versions with the same inputs and check they produce the same
+/// outputs and stop the same way, returning how many steps each took.
+pub fn verify(
+    original: &[isize],
+    optimized: &[isize],
+    inputs: &[isize],
+    max_steps: usize,
+) -> Result<(usize, usize), Mismatch> {
+    let original = run(original, inputs, max_steps);
+    let optimized = run(optimized, inputs, max_steps);
+
+    // the optimized version may get further in the same number of steps
+    let same = if original.stop == Stop::OutOfSteps {
+        optimized.outputs.starts_with(&original.outputs)
+    } else {
+        original.outputs == optimized.outputs && original.stop == optimized.stop
+    };
+    if same {
+        Ok((original.steps, optimized.steps))
+    } else {
+        Err(Mismatch {
+            original,
+            optimized,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_simplify() {
+        let rewrite = |prog: &[isize]| match simplify(&decode(prog, 0).unwrap()) {
+            Rewrite::Replace(ins) => Some(ins.to_string()),
+            Rewrite::Nop => None,
+            Rewrite::Keep => Some("keep".to_string()),
+        };
+
+        assert_eq!(Some("add #5, #0 -> [9]".into()), rewrite(&[1101, 2, 3, 9]));
+        assert_eq!(Some("add #0, #0 -> [9]".into()), rewrite(&[1002, 8, 0, 9]));
+        assert_eq!(Some("add #1, #0 -> [9]".into()), rewrite(&[1107, 2, 3, 9]));
+        assert_eq!(Some("add [8], #0 -> [9]".into()), rewrite(&[1002, 8, 1, 9]));
+        assert_eq!(Some("add [8], #0 -> [9]".into()), rewrite(&[101, 0, 8, 9]));
+        assert_eq!(Some("keep".into()), rewrite(&[1001, 8, 0, 9]));
+        assert_eq!(None, rewrite(&[1001, 9, 0, 9]));
+        assert_eq!(None, rewrite(&[102, 1, 9, 9]));
+        assert_eq!(Some("jz #0, #7".into()), rewrite(&[1105, 3, 7]));
+        assert_eq!(Some("keep".into()), rewrite(&[1106, 0, 7]));
+        assert_eq!(None, rewrite(&[1105, 0, 7]));
+        assert_eq!(None, rewrite(&[1106, 1, 7]));
+        assert_eq!(Some("keep".into()), rewrite(&[1005, 9, 7]));
+        assert_eq!(Some("keep".into()), rewrite(&[1102, isize::MAX, 2, 9]));
+    }
+
+    #[test]
+    fn test_bypass_and_thread() {
+        let prog = &[
+            3, 30, // in -> [30]
+            1001, 30, 0, 30, // add [30], #0 -> [30]: nothing
+            1002, 30, 1, 30, // mul [30], #1 -> [30]: nothing
+            1105, 0, 99, // never jumps
+            1105, 1, 20, // always jumps to 20...
+            99, 0, 0, 0, //
+            1106, 0, 23, // ...which jumps to 23
+            1001, 30, 5, 31, // add [30], #5 -> [31]
+            4, 31, // out [31]
+            99, 0, 0, 0, 0,
+        ];
+        let (out, report) = optimize(prog).unwrap();
+
+        assert_eq!(
+            Report {
+                folded: 0,
+                moves: 2,
+                jumps: 2,
+                bypassed: 1,
+                threaded: 1,
+                saved: 4 + 1,
+            },
+            report
+        );
+        // the nops jump straight to 23, and so does the old jump to 20
+        assert_eq!("jz #0, #23", decode(&out, 2).unwrap().to_string());
+        assert_eq!("jz #0, #23", decode(&out, 13).unwrap().to_string());
+
+        let (before, after) = verify(prog, &out, &[37], 1000).unwrap();
+        assert_eq!((8, 4), (before, after));
+        assert_eq!(vec![42], run(&out, &[37], 1000).outputs);
+    }
+
+    #[test]
+    fn test_folding_and_data_reads() {
+        let prog = &[
+            1101, 2, 3, 13, // add #2, #3 -> [13]
+            1102, 4, 5, 14, // mul #4, #5 -> [14], but [6] is read below
+            2, 13, 6, 15, // mul [13], [6] -> [15]
+            99, 0, 0, 0,
+        ];
+        let (out, report) = optimize(prog).unwrap();
+
+        assert_eq!(1, report.folded);
+        assert_eq!(&[1101, 5, 0, 13][..], &out[..4]);
+        assert_eq!(&prog[4..], &out[4..]);
+
+        let mut original = Machine::new(prog, &[]);
+        let mut optimized = Machine::new(&out, &[]);
+        while original.step().unwrap() != State::Halt {}
+        while optimized.step().unwrap() != State::Halt {}
+        assert_eq!(&original.memory()[13..], &optimized.memory()[13..]);
+    }
+
+    #[test]
+    fn test_refusals() {
+        assert_eq!(Err(Refusal::Invalid(0)), optimize(&[42]).map(|_| ()));
+        assert_eq!(
+            Err(Refusal::IndirectJump(0)),
+            optimize(&[105, 1, 9, 99]).map(|_| ())
+        );
+        assert_eq!(
+            Err(Refusal::RelativeMode(0)),
+            optimize(&[204, 1, 99]).map(|_| ())
+        );
+        assert_eq!(
+            Err(Refusal::SelfModifying { pc: 0, addr: 4 }),
+            optimize(&[1101, 1, 98, 4, 1, 0, 0, 0, 99]).map(|_| ())
+        );
+
+        // a jump run past the end, then one into the middle of an add
+        assert_eq!(
+            Err(Refusal::Truncated(0)),
+            optimize(&[1105, 44]).map(|_| ())
+        );
+        let prog = &[
+            1105, 1, 5, 1101, 2, 3, 20, 4, 20, 1008, 20, 5, 21, 1005, 21, 19, 1105, 1, 3, 99, 0, 0,
+        ];
+        assert_eq!(
+            Err(Refusal::Overlapping { pc: 3, other: 5 }),
+            optimize(prog).map(|_| ())
+        );
+
+        // the repo's own day 5 input patches its own instructions
+        let day5 = include_str!("../../day5/src/input")
+            .parse::<crate::Program>()
+            .unwrap();
+        assert_eq!(
+            Err(Refusal::SelfModifying { pc: 2, addr: 6 }),
+            optimize(&day5).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_verify_catches_differences() {
+        let mismatch = verify(&[104, 1, 99], &[104, 2, 99], &[], 10).unwrap_err();
+        assert_eq!(vec![1], mismatch.original.outputs);
+        assert_eq!(vec![2], mismatch.optimized.outputs);
+
+        // outputs the original didn't get to in time are fine, others aren't
+        let original = [1101, 1101, 0, 0, 104, 1, 104, 2, 99];
+        assert!(verify(&original, &[104, 1, 104, 2, 99], &[], 2).is_ok());
+        assert!(verify(&original, &[104, 1, 104, 3, 99], &[], 2).is_ok());
+        assert!(verify(&original, &[104, 2, 104, 1, 99], &[], 2).is_err());
+        assert!(verify(&original, &[99], &[], 2).is_err());
+    }
+}
@@ -0,0 +1,150 @@
+//! Machines for running one program over and over without allocating a fresh
+//! image each time.
+
+use crate::machine::Machine;
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+type Setup = Box<dyn Fn(&mut Machine) + Send + Sync>;
+
+/// Hands out machines loaded with the same program. A machine goes back to
+/// the pool when its handle is dropped and is reset before it's handed out
+/// again, so only the pages a run wrote get copied.
+pub struct MachinePool {
+    prog: Vec<isize>,
+    idle: Mutex<Vec<Machine>>,
+    setup: Option<Setup>,
+}
+
+impl MachinePool {
+    pub fn new(prog: &[isize]) -> Self {
+        MachinePool {
+            prog: prog.to_vec(),
+            idle: Mutex::new(Vec::new()),
+            setup: None,
+        }
+    }
+
+    /// run `f` on each new machine, to register opcodes or set policies;
+    /// resetting keeps both so it only runs once per machine
+    pub fn setup<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&mut Machine) + Send + Sync + 'static,
+    {
+        self.setup = Some(Box::new(f));
+        self
+    }
+
+    /// a machine at the start of the program with no input queued
+    pub fn get(&self) -> Pooled<'_> {
+        let idle = self.idle.lock().unwrap().pop();
+        let machine = match idle {
+            Some(mut m) => {
+                m.reset(&self.prog);
+                m
+            }
+            None => {
+                let mut m = Machine::new(&self.prog, &[]);
+                if let Some(setup) = &self.setup {
+                    setup(&mut m);
+                }
+                m
+            }
+        };
+
+        Pooled {
+            pool: self,
+            machine: Some(machine),
+        }
+    }
+
+    /// how many machines are waiting to be reused
+    pub fn idle(&self) -> usize {
+        self.idle.lock().unwrap().len()
+    }
+}
+
+/// A machine on loan from a `MachinePool`.
+pub struct Pooled<'a> {
+    pool: &'a MachinePool,
+    machine: Option<Machine>,
+}
+
+impl Deref for Pooled<'_> {
+    type Target = Machine;
+
+    fn deref(&self) -> &Machine {
+        self.machine.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for Pooled<'_> {
+    fn deref_mut(&mut self) -> &mut Machine {
+        self.machine.as_mut().unwrap()
+    }
+}
+
+impl Drop for Pooled<'_> {
+    fn drop(&mut self) {
+        if let Some(m) = self.machine.take() {
+            self.pool.idle.lock().unwrap().push(m);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::machine::{NegativeAddresses, State};
+    use std::thread;
+
+    // output in * 2 via [-1]
+    const DOUBLE: &[isize] = &[3, -1, 1002, -1, 2, -1, 4, -1, 99];
+
+    fn double(pool: &MachinePool, x: isize) -> isize {
+        let mut m = pool.get();
+        m.push_input(x);
+        loop {
+            if let State::Output(o) = m.step().unwrap() {
+                return o;
+            }
+        }
+    }
+
+    #[test]
+    fn test_reuse() {
+        let pool = MachinePool::new(DOUBLE).setup(|m| {
+            m.negative_addresses(NegativeAddresses::Bank);
+        });
+
+        for x in 0..10 {
+            assert_eq!(x * 2, double(&pool, x));
+        }
+        assert_eq!(1, pool.idle());
+
+        let (a, b) = (pool.get(), pool.get());
+        assert_eq!(0, pool.idle());
+        assert_eq!((0, DOUBLE), (a.pc(), b.memory()));
+        drop((a, b));
+        assert_eq!(2, pool.idle());
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        let pool = MachinePool::new(DOUBLE).setup(|m| {
+            m.negative_addresses(NegativeAddresses::Bank);
+        });
+
+        thread::scope(|s| {
+            for t in 0..4 {
+                let pool = &pool;
+                s.spawn(move || {
+                    for x in 0..100 {
+                        assert_eq!((t * 100 + x) * 2, double(pool, t * 100 + x));
+                    }
+                });
+            }
+        });
+        assert!(pool.idle() <= 4);
+    }
+}
@@ -0,0 +1,316 @@
+//! Intcode program text: comma separated integers, with any whitespace or
+//! newlines between them and `#` starting a comment that runs to the end of
+//! the line. Programs can also be stored in the binary form from `image`.
+
+use crate::image::{DecodeError, Image};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Deref;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::{fs, io, path::Path};
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Program(Vec<isize>);
+
+impl Program {
+    pub fn new(words: Vec<isize>) -> Self {
+        Program(words)
+    }
+
+    /// read and parse a program file, text or binary
+    #[cfg(feature = "std")]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Program, LoadError> {
+        Image::load(path).map(|image| image.program)
+    }
+
+    /// Load the program named by the first command line argument, or the
+    /// one at `default` without one. A day's binary can't do anything
+    /// without its program, so failing prints why and exits.
+    #[cfg(feature = "std")]
+    pub fn load_arg_or<P: AsRef<Path>>(default: P) -> Program {
+        let path = std::env::args()
+            .nth(1)
+            .map_or_else(|| default.as_ref().to_path_buf(), Into::into);
+        Program::load(&path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path.display(), e);
+            std::process::exit(1)
+        })
+    }
+
+    /// the binary form, without an entry point or symbols
+    pub fn to_bytes(&self) -> Vec<u8> {
+        Image::from(self.clone()).to_bytes()
+    }
+
+    /// a program from its binary form, ignoring any entry point or symbols
+    pub fn from_bytes(bytes: &[u8]) -> Result<Program, DecodeError> {
+        Image::from_bytes(bytes).map(|image| image.program)
+    }
+
+    pub fn into_inner(self) -> Vec<isize> {
+        self.0
+    }
+}
+
+impl Deref for Program {
+    type Target = [isize];
+
+    fn deref(&self) -> &[isize] {
+        &self.0
+    }
+}
+
+impl From<Vec<isize>> for Program {
+    fn from(words: Vec<isize>) -> Self {
+        Program(words)
+    }
+}
+
+/// comma separated on one line, which reads back as the same program
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, word) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", word)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+    NotAnInteger,
+    /// a comma with nothing before it
+    Missing,
+    /// two tokens with only whitespace between them
+    MissingComma,
+    /// a `#!` line that isn't an entry point or symbol
+    BadDirective,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub reason: Reason,
+    /// which token, counting from 0
+    pub index: usize,
+    /// the offending text, empty when it's missing
+    pub token: String,
+    /// 1-based
+    pub line: usize,
+    /// 1-based, in characters
+    pub column: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match self.reason {
+            Reason::NotAnInteger => {
+                write!(f, "token {} `{}` is not an integer", self.index, self.token)
+            }
+            Reason::Missing => write!(f, "token {} is missing", self.index),
+            Reason::MissingComma => write!(
+                f,
+                "expected a comma before token {} `{}`",
+                self.index, self.token
+            ),
+            Reason::BadDirective => write!(f, "bad directive `{}`", self.token),
+        }
+    }
+}
+
+impl core::error::Error for ParseError {}
+
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Parse(ParseError),
+    Image(DecodeError),
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "{}", e),
+            LoadError::Parse(e) => write!(f, "{}", e),
+            LoadError::Image(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LoadError {}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Image {
+    /// read a program file, binary if it starts with `image::MAGIC` and
+    /// text otherwise
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Image, LoadError> {
+        let bytes = fs::read(path)?;
+        if bytes.starts_with(&crate::image::MAGIC) {
+            return Image::from_bytes(&bytes).map_err(LoadError::Image);
+        }
+        let text =
+            String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        text.parse().map_err(LoadError::Parse)
+    }
+}
+
+impl FromStr for Program {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Program, ParseError> {
+        let mut words = Vec::new();
+        // a comma needs a token before it; a trailing one is fine
+        let mut awaiting_token = false;
+
+        for (line_no, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap();
+            let mut chars = line.char_indices().peekable();
+
+            while let Some(&(start, c)) = chars.peek() {
+                let column = line[..start].chars().count() + 1;
+                let error = |reason, token: &str| ParseError {
+                    reason,
+                    index: words.len(),
+                    token: token.to_string(),
+                    line: line_no + 1,
+                    column,
+                };
+
+                if c.is_whitespace() {
+                    chars.next();
+                } else if c == ',' {
+                    if words.is_empty() || awaiting_token {
+                        return Err(error(Reason::Missing, ""));
+                    }
+                    awaiting_token = true;
+                    chars.next();
+                } else {
+                    let mut end = line.len();
+                    while let Some(&(i, c)) = chars.peek() {
+                        if c == ',' || c.is_whitespace() {
+                            end = i;
+                            break;
+                        }
+                        chars.next();
+                    }
+
+                    let token = &line[start..end];
+                    if !awaiting_token && !words.is_empty() {
+                        return Err(error(Reason::MissingComma, token));
+                    }
+                    words.push(
+                        token
+                            .parse()
+                            .map_err(|_| error(Reason::NotAnInteger, token))?,
+                    );
+                    awaiting_token = false;
+                }
+            }
+        }
+
+        Ok(Program(words))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let prog: Program = "1,9,10,3,2,3,11,0,99,30,40,50\n".parse().unwrap();
+        assert_eq!(&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50][..], &prog[..]);
+
+        let prog: Program = "# adds two numbers\n1101, 100, -1, 4, # store at 4\n\n  0,\n"
+            .parse()
+            .unwrap();
+        assert_eq!(&[1101, 100, -1, 4, 0][..], &prog[..]);
+
+        let prog: Program = "".parse().unwrap();
+        assert!(prog.is_empty());
+
+        let prog = Program::new(vec![1101, 100, -1, 4, 0]);
+        assert_eq!("1101,100,-1,4,0", prog.to_string());
+        assert_eq!(prog, prog.to_string().parse().unwrap());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = "1,2,x3,4".parse::<Program>().unwrap_err();
+        assert_eq!(
+            ParseError {
+                reason: Reason::NotAnInteger,
+                index: 2,
+                token: "x3".to_string(),
+                line: 1,
+                column: 5,
+            },
+            err
+        );
+        assert_eq!(
+            "line 1, column 5: token 2 `x3` is not an integer",
+            format!("{}", err)
+        );
+
+        let err = "1,\n2,,3".parse::<Program>().unwrap_err();
+        assert_eq!(
+            (2, "", 2, 3),
+            (err.index, &err.token[..], err.line, err.column)
+        );
+        assert_eq!("line 2, column 3: token 2 is missing", format!("{}", err));
+
+        let err = ",1".parse::<Program>().unwrap_err();
+        assert_eq!(Reason::Missing, err.reason);
+        assert_eq!((0, 1, 1), (err.index, err.line, err.column));
+
+        let err = "1, 2 3".parse::<Program>().unwrap_err();
+        assert_eq!((2, "3"), (err.index, &err.token[..]));
+        assert_eq!(
+            "line 1, column 6: expected a comma before token 2 `3`",
+            format!("{}", err)
+        );
+
+        let err = "1,99999999999999999999999".parse::<Program>().unwrap_err();
+        assert_eq!(1, err.index);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_load() {
+        let path = std::env::temp_dir().join(format!("intcode-test-{}.ic", std::process::id()));
+        fs::write(&path, "104,1125899906842624,99\n").unwrap();
+        let prog = Program::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(&[104, 1125899906842624, 99][..], &prog[..]);
+
+        match Program::load(&path) {
+            Err(LoadError::Io(_)) => {}
+            x => panic!("{:?}", x),
+        }
+
+        let mut image = Image::from(prog.clone());
+        image.entry = Some(1);
+        fs::write(&path, image.to_bytes()).unwrap();
+        assert_eq!(prog, Program::load(&path).unwrap());
+        assert_eq!(image, Image::load(&path).unwrap());
+        fs::write(&path, &image.to_bytes()[..9]).unwrap();
+        let err = Program::load(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!("byte 9: unexpected end", err.to_string());
+    }
+}
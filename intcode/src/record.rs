@@ -0,0 +1,451 @@
+//! Recording a session's inputs and outputs, and replaying them to check a
+//! program still behaves the same.
+//!
+//! A recording is a text file with one event per line, each tagged with how
+//! many instructions had run before it. A session that gave negative
+//! addresses memory of their own starts with a line saying so, since
+//! replaying it any other way would fault:
+//!
+//! ```text
+//! negative-addresses bank
+//! input 0 5
+//! output 3 10
+//! halt 4
+//! ```
+
+use crate::machine::{Error, Machine, NegativeAddresses, State};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    Input(isize),
+    Output(isize),
+    Halt,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Input(v) => write!(f, "input {}", v),
+            Event::Output(v) => write!(f, "output {}", v),
+            Event::Halt => write!(f, "halt"),
+        }
+    }
+}
+
+/// Events in the order they happened, with the step each happened on.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Recording {
+    /// the policy the session ran under, and that a replay uses
+    pub negative_addresses: NegativeAddresses,
+    pub events: Vec<(usize, Event)>,
+}
+
+impl Recording {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Recording, LoadError> {
+        fs::read_to_string(path)?.parse().map_err(LoadError::Parse)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    /// just the inputs, in order
+    pub fn inputs(&self) -> Vec<isize> {
+        self.events
+            .iter()
+            .filter_map(|(_, e)| match e {
+                Event::Input(v) => Some(*v),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// the header line for `policy`, if it isn't the default
+fn write_policy<W: Write>(w: &mut W, policy: NegativeAddresses) -> io::Result<()> {
+    match policy {
+        NegativeAddresses::Fault => Ok(()),
+        NegativeAddresses::Bank => writeln!(w, "negative-addresses bank"),
+    }
+}
+
+fn write_event<W: Write>(w: &mut W, step: usize, event: Event) -> io::Result<()> {
+    match event {
+        Event::Input(v) => writeln!(w, "input {} {}", step, v),
+        Event::Output(v) => writeln!(w, "output {} {}", step, v),
+        Event::Halt => writeln!(w, "halt {}", step),
+    }
+}
+
+impl fmt::Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut buf = Vec::new();
+        write_policy(&mut buf, self.negative_addresses).map_err(|_| fmt::Error)?;
+        for &(step, event) in &self.events {
+            write_event(&mut buf, step, event).map_err(|_| fmt::Error)?;
+        }
+        f.write_str(&String::from_utf8(buf).unwrap())
+    }
+}
+
+/// a line that isn't an event or the header; `line` is 1-based
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub text: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: `{}` is not an event", self.line, self.text)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl FromStr for Recording {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Recording, ParseError> {
+        let mut recording = Recording::default();
+
+        for (i, line) in s.lines().enumerate() {
+            let text = line.split('#').next().unwrap().trim();
+            if text.is_empty() {
+                continue;
+            }
+            // the header has to come before any event
+            if text == "negative-addresses bank" && recording.events.is_empty() {
+                recording.negative_addresses = NegativeAddresses::Bank;
+                continue;
+            }
+
+            let words: Vec<&str> = text.split_whitespace().collect();
+            let number = |n: usize| words.get(n).and_then(|w| w.parse::<isize>().ok());
+            let event = match (words[0], words.len(), number(1), number(2)) {
+                ("input", 3, Some(step), Some(v)) => Some((step, Event::Input(v))),
+                ("output", 3, Some(step), Some(v)) => Some((step, Event::Output(v))),
+                ("halt", 2, Some(step), _) => Some((step, Event::Halt)),
+                _ => None,
+            };
+
+            match event {
+                Some((step, event)) if step >= 0 => recording.events.push((step as usize, event)),
+                _ => {
+                    return Err(ParseError {
+                        line: i + 1,
+                        text: text.to_string(),
+                    })
+                }
+            }
+        }
+
+        Ok(recording)
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "{}", e),
+            LoadError::Parse(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+/// Steps a machine, writing every input it consumes and output it produces
+/// to `out` as it goes, so a session that dies part way still leaves its log.
+/// Only the built-in input instruction is recorded; inputs taken by
+/// registered opcodes aren't.
+pub struct Recorder<W: Write> {
+    machine: Machine,
+    out: W,
+    steps: usize,
+    halted: bool,
+    /// the first write that failed, reported by `finish`
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(machine: Machine, mut out: W) -> Self {
+        let error = write_policy(&mut out, machine.negative_address_policy()).err();
+        Recorder {
+            machine,
+            out,
+            steps: 0,
+            halted: false,
+            error,
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    pub fn push_input(&mut self, v: isize) {
+        self.machine.push_input(v);
+    }
+
+    /// instructions run so far
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    pub fn step(&mut self) -> Result<State, Error> {
+        let state = self.machine.step()?;
+        let event = match state {
+            // it was only just stored there, so the load can't fail
+            State::Input(dest) => Some(Event::Input(self.machine.load(dest).unwrap())),
+            State::Output(o) => Some(Event::Output(o)),
+            State::Halt if !self.halted => {
+                self.halted = true;
+                Some(Event::Halt)
+            }
+            _ => None,
+        };
+
+        if let Some(event) = event {
+            if self.error.is_none() {
+                self.error = write_event(&mut self.out, self.steps, event).err();
+            }
+        }
+        if state != State::Halt && state != State::NeedInput {
+            self.steps += 1;
+        }
+        Ok(state)
+    }
+
+    /// the machine and the writer, or the first error writing to it
+    pub fn finish(mut self) -> io::Result<(Machine, W)> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        self.out.flush()?;
+        Ok((self.machine, self.out))
+    }
+}
+
+/// where a replay parted ways with its recording
+#[derive(Debug, PartialEq)]
+pub struct Divergence {
+    pub step: usize,
+    /// what the recording has next, and on which step
+    pub expected: (usize, Event),
+    /// what the machine did instead
+    pub found: Found,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Found {
+    Event(Event),
+    /// ran an instruction with no input or output
+    Nothing,
+    /// wanted an input the recording doesn't give it here
+    NeedInput,
+    Error(Error),
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (step, event) = self.expected;
+        write!(f, "step {}: expected {} at step {}", self.step, event, step)?;
+        match &self.found {
+            Found::Event(event) => write!(f, ", found {}", event),
+            Found::Nothing => write!(f, ", found neither input nor output"),
+            Found::NeedInput => write!(f, ", found the program waiting for input"),
+            Found::Error(e) => write!(f, ", found {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+/// Run `machine` from where it stands, under the recording's negative address
+/// policy, feeding it the recorded inputs on the steps they were consumed and
+/// checking every input, output and the halt happen where the recording
+/// says. Stops after the last recorded event.
+pub fn replay(mut machine: Machine, recording: &Recording) -> Result<(), Divergence> {
+    machine.negative_addresses(recording.negative_addresses);
+    let mut events = recording.events.iter().copied().peekable();
+    let mut steps = 0;
+    let mut fed = false;
+
+    while let Some(expected) = events.peek().copied() {
+        let diverged = |found| Divergence {
+            step: steps,
+            expected,
+            found,
+        };
+
+        if let (step, Event::Input(v)) = expected {
+            if step == steps && !fed {
+                machine.push_input(v);
+                fed = true;
+            }
+        }
+
+        let found = match machine.step() {
+            Ok(State::Input(dest)) => Event::Input(machine.load(dest).unwrap()),
+            Ok(State::Output(o)) => Event::Output(o),
+            Ok(State::Halt) => Event::Halt,
+            Ok(State::NeedInput) => return Err(diverged(Found::NeedInput)),
+            Ok(_) if expected.0 > steps => {
+                steps += 1;
+                continue;
+            }
+            Ok(_) => return Err(diverged(Found::Nothing)),
+            Err(e) => return Err(diverged(Found::Error(e))),
+        };
+
+        if (steps, found) != expected {
+            return Err(diverged(Found::Event(found)));
+        }
+        events.next();
+        fed = false;
+        if found != Event::Halt {
+            steps += 1;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // add up inputs until one is zero, printing the running total
+    const TOTAL: &[isize] = &[
+        3, 15, 1006, 15, 14, 1, 15, 16, 16, 4, 16, 1105, 1, 0, 99, 0, 0,
+    ];
+
+    fn session() -> Vec<u8> {
+        let mut rec = Recorder::new(Machine::new(TOTAL, &[]), Vec::new());
+        let mut inputs = vec![0, 7, 5].into_iter();
+        loop {
+            match rec.step().unwrap() {
+                State::NeedInput => rec.push_input(inputs.next_back().unwrap()),
+                State::Halt => break,
+                _ => {}
+            }
+        }
+        assert_eq!(12, rec.steps());
+        rec.finish().unwrap().1
+    }
+
+    #[test]
+    fn test_record() {
+        assert_eq!(
+            "input 0 5\noutput 3 5\ninput 5 7\noutput 8 12\ninput 10 0\nhalt 12\n",
+            String::from_utf8(session()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let text = String::from_utf8(session()).unwrap();
+        let recording: Recording = text.parse().unwrap();
+        assert_eq!(6, recording.events.len());
+        assert_eq!(vec![5, 7, 0], recording.inputs());
+        assert_eq!(text, recording.to_string());
+
+        let path = std::env::temp_dir().join(format!("intcode-rec-{}.log", std::process::id()));
+        recording.save(&path).unwrap();
+        let loaded = Recording::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(recording, loaded);
+    }
+
+    #[test]
+    fn test_replay() {
+        let recording: Recording = String::from_utf8(session()).unwrap().parse().unwrap();
+        assert_eq!(Ok(()), replay(Machine::new(TOTAL, &[]), &recording));
+
+        // a program that doubles where the original added
+        let mut changed = TOTAL.to_vec();
+        changed[5] = 2;
+        let diverged = replay(Machine::new(&changed, &[]), &recording).unwrap_err();
+        assert_eq!(
+            Divergence {
+                step: 3,
+                expected: (3, Event::Output(5)),
+                found: Found::Event(Event::Output(0)),
+            },
+            diverged
+        );
+        assert_eq!(
+            "step 3: expected output 5 at step 3, found output 0",
+            diverged.to_string()
+        );
+
+        // inputs consumed later than recorded
+        let late: Recording = "input 1 5\n".parse().unwrap();
+        assert_eq!(
+            Found::NeedInput,
+            replay(Machine::new(TOTAL, &[]), &late).unwrap_err().found
+        );
+    }
+
+    #[test]
+    fn test_negative_bank() {
+        // store 11 below zero and print it back
+        let prog = &[1101, 5, 6, -1, 4, -1, 99];
+        let mut m = Machine::new(prog, &[]);
+        m.negative_addresses(NegativeAddresses::Bank);
+        let mut rec = Recorder::new(m, Vec::new());
+        while rec.step().unwrap() != State::Halt {}
+        let text = String::from_utf8(rec.finish().unwrap().1).unwrap();
+        assert_eq!("negative-addresses bank\noutput 1 11\nhalt 2\n", text);
+
+        let recording: Recording = text.parse().unwrap();
+        assert_eq!(NegativeAddresses::Bank, recording.negative_addresses);
+        assert_eq!(text, recording.to_string());
+        assert_eq!(Ok(()), replay(Machine::new(prog, &[]), &recording));
+
+        // without the header the replay faults
+        let recording: Recording = "output 1 11\nhalt 2\n".parse().unwrap();
+        assert_eq!(
+            Found::Error(Error::NegativeAddress(0)),
+            replay(Machine::new(prog, &[]), &recording)
+                .unwrap_err()
+                .found
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Err(ParseError {
+                line: 2,
+                text: "output 3".to_string()
+            }),
+            "# header\noutput 3\n".parse::<Recording>()
+        );
+        assert!("input -1 5".parse::<Recording>().is_err());
+        assert!("halt 1 2".parse::<Recording>().is_err());
+        assert!("jump 1".parse::<Recording>().is_err());
+        assert!("negative-addresses fault".parse::<Recording>().is_err());
+        assert!("halt 1\nnegative-addresses bank"
+            .parse::<Recording>()
+            .is_err());
+    }
+}
@@ -0,0 +1,349 @@
+//! Goal seeking: which patched memory cells and inputs make a program
+//! produce a given result.
+
+use crate::machine::{Machine, State};
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// how many assignments a worker claims at a time
+const BATCH: usize = 64;
+
+/// what a finished run left behind, for the predicate to judge
+pub struct Run<'a> {
+    pub memory: &'a [isize],
+    pub outputs: &'a [isize],
+}
+
+/// values chosen for each patched address and each input, in the order
+/// they were added to the search
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub patches: Vec<isize>,
+    pub inputs: Vec<isize>,
+}
+
+/// there are more combinations than can be counted
+#[derive(Debug, Clone, PartialEq)]
+pub struct TooLarge;
+
+impl fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "too many combinations to search")
+    }
+}
+
+impl std::error::Error for TooLarge {}
+
+/// Search over every combination of patched addresses and input values,
+/// running the program once per combination on a pool of threads.
+pub struct Search<'a> {
+    prog: &'a [isize],
+    patches: Vec<(usize, RangeInclusive<isize>)>,
+    inputs: Vec<RangeInclusive<isize>>,
+    threads: usize,
+    max_steps: Option<usize>,
+}
+
+impl<'a> Search<'a> {
+    pub fn new(prog: &'a [isize]) -> Self {
+        Search {
+            prog,
+            patches: Vec::new(),
+            inputs: Vec::new(),
+            threads: thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            max_steps: None,
+        }
+    }
+
+    /// try every value in `range` at `addr` before the program starts
+    pub fn patch(mut self, addr: usize, range: RangeInclusive<isize>) -> Self {
+        self.patches.push((addr, range));
+        self
+    }
+
+    /// feed the next input from `range`
+    pub fn input(mut self, range: RangeInclusive<isize>) -> Self {
+        self.inputs.push(range);
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// give up on a run (and count it as not matching) after this many steps
+    pub fn max_steps(mut self, steps: usize) -> Self {
+        self.max_steps = Some(steps);
+        self
+    }
+
+    fn ranges(&self) -> impl Iterator<Item = &RangeInclusive<isize>> {
+        self.patches
+            .iter()
+            .map(|(_, r)| r)
+            .chain(self.inputs.iter())
+    }
+
+    fn widths(&self) -> Result<Vec<usize>, TooLarge> {
+        self.ranges().map(width).collect()
+    }
+
+    /// how many combinations there are to try
+    pub fn len(&self) -> Result<usize, TooLarge> {
+        self.widths()?
+            .into_iter()
+            .try_fold(1usize, |n, w| n.checked_mul(w))
+            .ok_or(TooLarge)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == Ok(0)
+    }
+
+    /// combination number `i`, the last range turning fastest
+    fn assignment(&self, widths: &[usize], mut i: usize) -> Assignment {
+        let mut values: Vec<isize> = self.ranges().map(|r| *r.start()).collect();
+        for (v, w) in values.iter_mut().zip(widths).rev() {
+            // within the range, so it fits
+            *v += (i % w) as isize;
+            i /= w;
+        }
+
+        let inputs = values.split_off(self.patches.len());
+        Assignment {
+            patches: values,
+            inputs,
+        }
+    }
+
+    /// run one combination on `m`, None if it ran out of steps; running out
+    /// of input or into an error doesn't match
+    fn run<F>(&self, m: &mut Machine, assignment: &Assignment, pred: &F) -> Option<bool>
+    where
+        F: Fn(&Run) -> bool,
+    {
+        m.reset(self.prog);
+        for &v in &assignment.inputs {
+            m.push_input(v);
+        }
+        for ((addr, _), v) in self.patches.iter().zip(&assignment.patches) {
+            m.set(*addr, *v);
+        }
+
+        let mut outputs = Vec::new();
+        let mut steps = 0;
+        loop {
+            if self.max_steps.is_some_and(|max| steps >= max) {
+                return None;
+            }
+            steps += 1;
+
+            match m.step() {
+                Ok(State::Output(o)) => outputs.push(o),
+                Ok(State::Halt) => break,
+                Ok(State::NeedInput) | Err(_) => return Some(false),
+                Ok(_) => {}
+            }
+        }
+
+        Some(pred(&Run {
+            memory: m.memory(),
+            outputs: &outputs,
+        }))
+    }
+
+    /// Every combination that satisfies `pred`, in order.
+    pub fn find_all<F>(&self, pred: F) -> Result<Vec<Assignment>, TooLarge>
+    where
+        F: Fn(&Run) -> bool + Sync,
+    {
+        self.scan(&pred, false)
+    }
+
+    /// The first combination, in order, that satisfies `pred`. Workers stop
+    /// as soon as nothing they have left could come before a match.
+    pub fn find_first<F>(&self, pred: F) -> Result<Option<Assignment>, TooLarge>
+    where
+        F: Fn(&Run) -> bool + Sync,
+    {
+        Ok(self.scan(&pred, true)?.into_iter().next())
+    }
+
+    fn scan<F>(&self, pred: &F, first: bool) -> Result<Vec<Assignment>, TooLarge>
+    where
+        F: Fn(&Run) -> bool + Sync,
+    {
+        let widths = self.widths()?;
+        let total = self.len()?;
+        let next = AtomicUsize::new(0);
+        // lowest matching index so far, only consulted when stopping early
+        let earliest = AtomicUsize::new(usize::MAX);
+        let found = Mutex::new(Vec::new());
+
+        thread::scope(|s| {
+            for _ in 0..self.threads {
+                s.spawn(|| {
+                    // reused for every run, resetting only what the last one wrote
+                    let mut m = Machine::new(self.prog, &[]);
+                    loop {
+                        let start = next.fetch_add(BATCH, Ordering::Relaxed);
+                        if start >= total || (first && start > earliest.load(Ordering::Relaxed)) {
+                            return;
+                        }
+
+                        for i in start..(start + BATCH).min(total) {
+                            let assignment = self.assignment(&widths, i);
+                            if self.run(&mut m, &assignment, pred) == Some(true) {
+                                earliest.fetch_min(i, Ordering::Relaxed);
+                                found.lock().unwrap().push((i, assignment));
+                                if first {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut found = found.into_inner().unwrap();
+        found.sort_by_key(|(i, _)| *i);
+        Ok(found.into_iter().map(|(_, a)| a).collect())
+    }
+}
+
+fn width(r: &RangeInclusive<isize>) -> Result<usize, TooLarge> {
+    if r.is_empty() {
+        return Ok(0);
+    }
+    r.end()
+        .checked_sub(*r.start())
+        .and_then(|d| usize::try_from(d).ok())
+        .and_then(|d| d.checked_add(1))
+        .ok_or(TooLarge)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // [0] = [9] * 3 + [10]
+    const PROG: &[isize] = &[1002, 9, 3, 0, 1, 0, 10, 0, 99, 0, 0];
+
+    #[test]
+    fn test_find_all_patches() {
+        let found = Search::new(PROG)
+            .patch(9, 0..=9)
+            .patch(10, 0..=9)
+            .find_all(|run| run.memory[0] == 20)
+            .unwrap();
+        let pairs: Vec<Vec<isize>> = found.into_iter().map(|a| a.patches).collect();
+        assert_eq!(vec![vec![4, 8], vec![5, 5], vec![6, 2]], pairs);
+    }
+
+    #[test]
+    fn test_threads_agree() {
+        let expected = Search::new(PROG)
+            .patch(9, -50..=50)
+            .patch(10, -50..=50)
+            .threads(1)
+            .find_all(|run| run.memory[0] % 7 == 0)
+            .unwrap();
+
+        for threads in 2..=6 {
+            let found = Search::new(PROG)
+                .patch(9, -50..=50)
+                .patch(10, -50..=50)
+                .threads(threads)
+                .find_all(|run| run.memory[0] % 7 == 0)
+                .unwrap();
+            assert_eq!(expected, found);
+        }
+    }
+
+    #[test]
+    fn test_find_first() {
+        let first = Search::new(PROG)
+            .patch(9, 0..=99)
+            .patch(10, 0..=99)
+            .threads(4)
+            .find_first(|run| run.memory[0] == 100)
+            .unwrap();
+        assert_eq!(
+            Some(Assignment {
+                patches: vec![1, 97],
+                inputs: vec![]
+            }),
+            first
+        );
+
+        let none = Search::new(PROG)
+            .patch(9, 0..=3)
+            .find_first(|run| run.memory[0] == -1)
+            .unwrap();
+        assert_eq!(None, none);
+    }
+
+    #[test]
+    fn test_inputs_and_outputs() {
+        // output in1 < in2, then in1 + in2
+        let prog = &[
+            3, 17, 3, 18, 7, 17, 18, 19, 4, 19, 1, 17, 18, 19, 4, 19, 99, 0, 0, 0,
+        ];
+        let found = Search::new(prog)
+            .input(0..=4)
+            .input(0..=4)
+            .find_all(|run| run.outputs == [1, 5])
+            .unwrap();
+        let inputs: Vec<Vec<isize>> = found.into_iter().map(|a| a.inputs).collect();
+        assert_eq!(vec![vec![1, 4], vec![2, 3]], inputs);
+    }
+
+    #[test]
+    fn test_max_steps() {
+        // spins forever unless [5] is zero
+        let prog = &[1005, 5, 0, 99, 0, 0];
+        let found = Search::new(prog)
+            .patch(5, 0..=3)
+            .max_steps(100)
+            .find_all(|_| true)
+            .unwrap();
+        assert_eq!(
+            vec![Assignment {
+                patches: vec![0],
+                inputs: vec![]
+            }],
+            found
+        );
+        assert_eq!(Ok(4), Search::new(prog).patch(5, 0..=3).len());
+        assert!(Search::new(prog)
+            .patch(5, RangeInclusive::new(1, 0))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_too_large() {
+        let all = Search::new(PROG).patch(9, isize::MIN..=isize::MAX);
+        assert_eq!(Err(TooLarge), all.len());
+        assert_eq!(Err(TooLarge), all.find_all(|_| true));
+
+        let product = Search::new(PROG).patch(9, 0..=isize::MAX).patch(10, 0..=1);
+        assert_eq!(Err(TooLarge), product.len());
+        assert_eq!(Err(TooLarge), product.find_first(|_| true));
+    }
+
+    #[test]
+    fn test_too_few_inputs() {
+        // reads two inputs but only has one slot, which never matches
+        let prog = &[3, 7, 3, 7, 4, 7, 99, 0];
+        let found = Search::new(prog).input(0..=3).find_all(|_| true);
+        assert_eq!(Ok(vec![]), found);
+    }
+}
@@ -0,0 +1,710 @@
+//! Symbolic execution: run a program with some memory cells or inputs left
+//! as named symbols and get back expressions in terms of them, e.g. the
+//! value day2 leaves at address 0 as a formula in its noun and verb.
+
+use crate::machine::ParameterMode;
+use std::collections::{BTreeMap, VecDeque};
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Const(isize),
+    Sym(String),
+    Add(Rc<Expr>, Rc<Expr>),
+    Mul(Rc<Expr>, Rc<Expr>),
+    LessThan(Rc<Expr>, Rc<Expr>),
+    Equals(Rc<Expr>, Rc<Expr>),
+    /// read through an address that depends on a symbol, along with the
+    /// memory as it was at the time
+    Load(Rc<Expr>, Rc<Vec<Rc<Expr>>>),
+}
+
+impl Expr {
+    pub fn sym(name: &str) -> Rc<Expr> {
+        Rc::new(Expr::Sym(name.to_string()))
+    }
+
+    pub fn constant(&self) -> Option<isize> {
+        match self {
+            Expr::Const(c) => Some(*c),
+            _ => None,
+        }
+    }
+
+    /// None if both are constants and their sum overflows
+    fn add(a: &Rc<Expr>, b: &Rc<Expr>) -> Option<Rc<Expr>> {
+        Some(match (a.constant(), b.constant()) {
+            (Some(x), Some(y)) => Rc::new(Expr::Const(x.checked_add(y)?)),
+            (Some(0), _) => b.clone(),
+            (_, Some(0)) => a.clone(),
+            _ => Rc::new(Expr::Add(a.clone(), b.clone())),
+        })
+    }
+
+    /// None if both are constants and their product overflows
+    fn mul(a: &Rc<Expr>, b: &Rc<Expr>) -> Option<Rc<Expr>> {
+        Some(match (a.constant(), b.constant()) {
+            (Some(x), Some(y)) => Rc::new(Expr::Const(x.checked_mul(y)?)),
+            (Some(0), _) | (_, Some(0)) => Rc::new(Expr::Const(0)),
+            (Some(1), _) => b.clone(),
+            (_, Some(1)) => a.clone(),
+            _ => Rc::new(Expr::Mul(a.clone(), b.clone())),
+        })
+    }
+
+    fn less_than(a: &Rc<Expr>, b: &Rc<Expr>) -> Rc<Expr> {
+        match (a.constant(), b.constant()) {
+            (Some(x), Some(y)) => Rc::new(Expr::Const((x < y) as isize)),
+            _ if a == b => Rc::new(Expr::Const(0)),
+            _ => Rc::new(Expr::LessThan(a.clone(), b.clone())),
+        }
+    }
+
+    fn equals(a: &Rc<Expr>, b: &Rc<Expr>) -> Rc<Expr> {
+        match (a.constant(), b.constant()) {
+            (Some(x), Some(y)) => Rc::new(Expr::Const((x == y) as isize)),
+            _ if a == b => Rc::new(Expr::Const(1)),
+            _ => Rc::new(Expr::Equals(a.clone(), b.clone())),
+        }
+    }
+
+    /// value with every symbol bound from `env`, None if one isn't or the
+    /// arithmetic overflows
+    pub fn eval(&self, env: &BTreeMap<String, isize>) -> Option<isize> {
+        match self {
+            Expr::Const(c) => Some(*c),
+            Expr::Sym(name) => env.get(name).cloned(),
+            Expr::Add(a, b) => a.eval(env)?.checked_add(b.eval(env)?),
+            Expr::Mul(a, b) => a.eval(env)?.checked_mul(b.eval(env)?),
+            Expr::LessThan(a, b) => Some((a.eval(env)? < b.eval(env)?) as isize),
+            Expr::Equals(a, b) => Some((a.eval(env)? == b.eval(env)?) as isize),
+            Expr::Load(addr, mem) => {
+                let addr = usize::try_from(addr.eval(env)?).ok()?;
+                mem.get(addr).map_or(Some(0), |x| x.eval(env))
+            }
+        }
+    }
+
+    /// flatten into a polynomial in the symbols, as long as nothing in here
+    /// compares or dereferences a symbol and no coefficient overflows
+    pub fn to_poly(&self) -> Option<Poly> {
+        match self {
+            Expr::Const(c) => Some(Poly::constant(*c)),
+            Expr::Sym(name) => Some(Poly::symbol(name)),
+            Expr::Add(a, b) => a.to_poly()?.add(&b.to_poly()?),
+            Expr::Mul(a, b) => a.to_poly()?.mul(&b.to_poly()?),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Const(c) => write!(f, "{}", c),
+            Expr::Sym(name) => write!(f, "{}", name),
+            Expr::Add(a, b) => write!(f, "({} + {})", a, b),
+            Expr::Mul(a, b) => write!(f, "({} * {})", a, b),
+            Expr::LessThan(a, b) => write!(f, "({} < {})", a, b),
+            Expr::Equals(a, b) => write!(f, "({} == {})", a, b),
+            Expr::Load(addr, _) => write!(f, "mem[{}]", addr),
+        }
+    }
+}
+
+/// sum of terms, each a coefficient times a product of symbols
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Poly(BTreeMap<Vec<String>, isize>);
+
+impl Poly {
+    fn constant(c: isize) -> Poly {
+        Poly(
+            Some((Vec::new(), c))
+                .filter(|_| c != 0)
+                .into_iter()
+                .collect(),
+        )
+    }
+
+    fn symbol(name: &str) -> Poly {
+        Poly(Some((vec![name.to_string()], 1)).into_iter().collect())
+    }
+
+    /// None if the coefficient overflows
+    fn with_term(mut self, symbols: Vec<String>, coefficient: isize) -> Option<Poly> {
+        let c = self.0.entry(symbols).or_insert(0);
+        *c = c.checked_add(coefficient)?;
+        if *c == 0 {
+            self.0.retain(|_, c| *c != 0);
+        }
+        Some(self)
+    }
+
+    fn add(&self, other: &Poly) -> Option<Poly> {
+        other
+            .0
+            .iter()
+            .try_fold(self.clone(), |acc, (s, c)| acc.with_term(s.clone(), *c))
+    }
+
+    fn mul(&self, other: &Poly) -> Option<Poly> {
+        let mut acc = Poly::default();
+        for (s1, c1) in &self.0 {
+            for (s2, c2) in &other.0 {
+                let mut symbols: Vec<String> = s1.iter().chain(s2).cloned().collect();
+                symbols.sort();
+                acc = acc.with_term(symbols, c1.checked_mul(*c2)?)?;
+            }
+        }
+        Some(acc)
+    }
+
+    /// no term multiplies two symbols together
+    pub fn is_linear(&self) -> bool {
+        self.0.keys().all(|s| s.len() <= 1)
+    }
+
+    /// coefficient of the term that's just `name`
+    pub fn coefficient(&self, name: &str) -> isize {
+        self.0.get(&vec![name.to_string()]).cloned().unwrap_or(0)
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = &String> {
+        self.0.keys().flatten()
+    }
+
+    /// value with every symbol bound from `env`, None if one isn't or the
+    /// arithmetic overflows
+    pub fn eval(&self, env: &BTreeMap<String, isize>) -> Option<isize> {
+        self.0.iter().try_fold(0isize, |acc, (symbols, c)| {
+            let product = symbols
+                .iter()
+                .try_fold(*c, |p, s| p.checked_mul(*env.get(s)?))?;
+            acc.checked_add(product)
+        })
+    }
+}
+
+impl fmt::Display for Poly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "0");
+        }
+
+        // constant goes last, so `a*noun + verb + c` reads the usual way
+        let terms = self
+            .0
+            .iter()
+            .filter(|(s, _)| !s.is_empty())
+            .chain(self.0.iter().filter(|(s, _)| s.is_empty()));
+
+        for (i, (symbols, c)) in terms.enumerate() {
+            match (i, *c < 0) {
+                (0, true) => write!(f, "-")?,
+                (0, false) => {}
+                (_, true) => write!(f, " - ")?,
+                (_, false) => write!(f, " + ")?,
+            }
+
+            let magnitude = c.unsigned_abs();
+            if symbols.is_empty() {
+                write!(f, "{}", magnitude)?;
+            } else {
+                if magnitude != 1 {
+                    write!(f, "{}*", magnitude)?;
+                }
+                write!(f, "{}", symbols.join("*"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every assignment of the symbols in `domains` that makes `expr` come out
+/// to `target`, as values in the same order as `domains`.
+///
+/// When `expr` is linear the last symbol that matters is solved for
+/// directly and only the rest are enumerated; anything else is enumerated
+/// outright.
+pub fn solve(
+    expr: &Expr,
+    target: isize,
+    domains: &[(&str, RangeInclusive<isize>)],
+) -> Vec<Vec<isize>> {
+    let mut found = Vec::new();
+
+    let poly = expr.to_poly().filter(|p| p.is_linear());
+    let pivot = poly.as_ref().and_then(|p| {
+        domains
+            .iter()
+            .rposition(|(name, _)| p.coefficient(name) != 0)
+    });
+
+    if let (Some(poly), Some(k)) = (poly, pivot) {
+        if poly
+            .symbols()
+            .any(|s| domains.iter().all(|(name, _)| name != s))
+        {
+            // something in there we aren't allowed to choose
+            return found;
+        }
+
+        let (name, range) = &domains[k];
+        let coefficient = poly.coefficient(name);
+        let others: Vec<_> = domains
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != k)
+            .map(|(_, d)| d.clone())
+            .collect();
+
+        each_assignment(&others, |values| {
+            let mut env = bind(&others, values);
+            env.insert(name.to_string(), 0);
+            // what the pivot's term has to make up, if nothing overflows
+            let value = poly
+                .eval(&env)
+                .and_then(|v| target.checked_sub(v))
+                .filter(|rest| rest.checked_rem(coefficient) == Some(0))
+                .and_then(|rest| rest.checked_div(coefficient));
+
+            if let Some(value) = value.filter(|v| range.contains(v)) {
+                let mut solution = values.to_vec();
+                solution.insert(k, value);
+                found.push(solution);
+            }
+        });
+    } else {
+        each_assignment(domains, |values| {
+            if expr.eval(&bind(domains, values)) == Some(target) {
+                found.push(values.to_vec());
+            }
+        });
+    }
+
+    found
+}
+
+fn bind(domains: &[(&str, RangeInclusive<isize>)], values: &[isize]) -> BTreeMap<String, isize> {
+    domains
+        .iter()
+        .zip(values)
+        .map(|((name, _), v)| (name.to_string(), *v))
+        .collect()
+}
+
+/// call `f` with every combination of values from the ranges, in order
+fn each_assignment<F>(domains: &[(&str, RangeInclusive<isize>)], mut f: F)
+where
+    F: FnMut(&[isize]),
+{
+    if domains.iter().any(|(_, r)| r.is_empty()) {
+        return;
+    }
+
+    let mut values: Vec<isize> = domains.iter().map(|(_, r)| *r.start()).collect();
+    loop {
+        f(&values);
+
+        // odometer, last place turns fastest
+        let mut place = values.len();
+        loop {
+            if place == 0 {
+                return;
+            }
+            place -= 1;
+
+            let range = &domains[place].1;
+            if values[place] < *range.end() {
+                values[place] += 1;
+                break;
+            }
+            values[place] = *range.start();
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// a write, jump or relative base change depends on a symbol (at pc)
+    SymbolicAddress(usize),
+    /// whether to jump depends on a symbol
+    SymbolicBranch(usize),
+    /// the instruction itself depends on a symbol
+    SymbolicOpcode(usize),
+    NegativeAddress(usize),
+    InvalidMode(usize),
+    InvalidOpcode(usize, isize),
+    NoInput(usize),
+    /// constant arithmetic or the relative base overflowed
+    Overflow(usize),
+    /// still running after the step limit
+    OutOfSteps(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::SymbolicAddress(pc) => write!(f, "symbolic address at pc {}", pc),
+            Error::SymbolicBranch(pc) => write!(f, "symbolic branch at pc {}", pc),
+            Error::SymbolicOpcode(pc) => write!(f, "symbolic instruction at pc {}", pc),
+            Error::NegativeAddress(pc) => write!(f, "negative address at pc {}", pc),
+            Error::InvalidMode(pc) => write!(f, "invalid parameter mode at pc {}", pc),
+            Error::InvalidOpcode(pc, op) => write!(f, "invalid opcode {} at pc {}", op, pc),
+            Error::NoInput(pc) => write!(f, "no input left at pc {}", pc),
+            Error::Overflow(pc) => write!(f, "arithmetic overflow at pc {}", pc),
+            Error::OutOfSteps(pc) => write!(f, "step limit reached at pc {}", pc),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// An Intcode machine whose memory holds expressions rather than numbers.
+/// Arithmetic on symbols builds expression trees; anything that needs a
+/// concrete value to carry on (a write address, a branch) is an error.
+pub struct Machine {
+    mem: Vec<Rc<Expr>>,
+    pc: usize,
+    relative_base: isize,
+    input: VecDeque<Rc<Expr>>,
+    output: Vec<Rc<Expr>>,
+    max_steps: Option<usize>,
+}
+
+impl Machine {
+    pub fn new(prog: &[isize]) -> Self {
+        Machine {
+            mem: prog.iter().map(|&x| Rc::new(Expr::Const(x))).collect(),
+            pc: 0,
+            relative_base: 0,
+            input: VecDeque::new(),
+            output: Vec::new(),
+            max_steps: None,
+        }
+    }
+
+    /// give up with an error after this many steps rather than running forever
+    pub fn max_steps(&mut self, steps: usize) {
+        self.max_steps = Some(steps);
+    }
+
+    /// leave the cell at `addr` as a symbol
+    pub fn symbol(&mut self, addr: usize, name: &str) {
+        self.set(addr, Expr::sym(name));
+    }
+
+    pub fn push_input(&mut self, x: Rc<Expr>) {
+        self.input.push_back(x);
+    }
+
+    pub fn memory(&self) -> &[Rc<Expr>] {
+        &self.mem
+    }
+
+    pub fn output(&self) -> &[Rc<Expr>] {
+        &self.output
+    }
+
+    fn get(&self, addr: usize) -> Rc<Expr> {
+        self.mem
+            .get(addr)
+            .cloned()
+            .unwrap_or_else(|| Rc::new(Expr::Const(0)))
+    }
+
+    fn set(&mut self, addr: usize, x: Rc<Expr>) {
+        if self.mem.len() <= addr {
+            self.mem.resize_with(addr + 1, || Rc::new(Expr::Const(0)));
+        }
+        self.mem[addr] = x;
+    }
+
+    fn instruction(&self) -> Result<isize, Error> {
+        self.get(self.pc)
+            .constant()
+            .ok_or(Error::SymbolicOpcode(self.pc))
+    }
+
+    fn parameter_mode(&self, n: usize) -> Result<ParameterMode, Error> {
+        ParameterMode::of(self.instruction()?, n).ok_or(Error::InvalidMode(self.pc))
+    }
+
+    fn overflow(&self) -> Error {
+        Error::Overflow(self.pc)
+    }
+
+    fn address(&self, x: isize) -> Result<usize, Error> {
+        usize::try_from(x).map_err(|_| Error::NegativeAddress(self.pc))
+    }
+
+    /// value for parameter n, dereferenced according to its mode
+    fn parameter(&self, n: usize) -> Result<Rc<Expr>, Error> {
+        let raw = self.get(self.pc + n);
+        let addr = match self.parameter_mode(n)? {
+            ParameterMode::Immediate => return Ok(raw),
+            ParameterMode::Position => raw,
+            ParameterMode::Relative => Expr::add(&Rc::new(Expr::Const(self.relative_base)), &raw)
+                .ok_or_else(|| self.overflow())?,
+        };
+
+        match addr.constant() {
+            Some(a) => Ok(self.get(self.address(a)?)),
+            None => Ok(Rc::new(Expr::Load(addr, Rc::new(self.mem.clone())))),
+        }
+    }
+
+    /// address parameter n refers to
+    fn destination(&self, n: usize) -> Result<usize, Error> {
+        let raw = self
+            .get(self.pc + n)
+            .constant()
+            .ok_or(Error::SymbolicAddress(self.pc))?;
+
+        match self.parameter_mode(n)? {
+            ParameterMode::Position => self.address(raw),
+            ParameterMode::Immediate => Err(Error::InvalidMode(self.pc)),
+            ParameterMode::Relative => {
+                let addr = self.relative_base.checked_add(raw);
+                self.address(addr.ok_or_else(|| self.overflow())?)
+            }
+        }
+    }
+
+    fn concrete(&self, x: Rc<Expr>, err: fn(usize) -> Error) -> Result<isize, Error> {
+        x.constant().ok_or_else(|| err(self.pc))
+    }
+
+    /// run until the program halts
+    pub fn run(&mut self) -> Result<(), Error> {
+        let mut steps = 0;
+        loop {
+            if self.max_steps.is_some_and(|max| steps >= max) {
+                return Err(Error::OutOfSteps(self.pc));
+            }
+            steps += 1;
+
+            let op = self.instruction()? % 100;
+            match op {
+                1 | 2 | 7 | 8 => {
+                    let left = self.parameter(1)?;
+                    let right = self.parameter(2)?;
+                    let dest = self.destination(3)?;
+
+                    let acc = match op {
+                        1 => Expr::add(&left, &right).ok_or_else(|| self.overflow())?,
+                        2 => Expr::mul(&left, &right).ok_or_else(|| self.overflow())?,
+                        7 => Expr::less_than(&left, &right),
+                        _ => Expr::equals(&left, &right),
+                    };
+                    self.set(dest, acc);
+                    self.pc += 4;
+                }
+                3 => {
+                    let acc = self.input.pop_front().ok_or(Error::NoInput(self.pc))?;
+                    let dest = self.destination(1)?;
+
+                    self.set(dest, acc);
+                    self.pc += 2;
+                }
+                4 => {
+                    let acc = self.parameter(1)?;
+
+                    self.output.push(acc);
+                    self.pc += 2;
+                }
+                5 | 6 => {
+                    let acc = self.parameter(1)?;
+                    let acc = self.concrete(acc, Error::SymbolicBranch)?;
+
+                    if (acc != 0) == (op == 5) {
+                        let target = self.parameter(2)?;
+                        let target = self.concrete(target, Error::SymbolicAddress)?;
+                        self.pc = self.address(target)?;
+                    } else {
+                        self.pc += 3;
+                    }
+                }
+                9 => {
+                    let acc = self.parameter(1)?;
+
+                    let step = self.concrete(acc, Error::SymbolicAddress)?;
+                    self.relative_base = self
+                        .relative_base
+                        .checked_add(step)
+                        .ok_or_else(|| self.overflow())?;
+                    self.pc += 2;
+                }
+                99 => return Ok(()),
+                _ => return Err(Error::InvalidOpcode(self.pc, op)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_concrete() {
+        let mut m = Machine::new(&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        m.run().unwrap();
+        assert_eq!(Some(3500), m.memory()[0].constant());
+    }
+
+    #[test]
+    fn test_formula() {
+        // [19] = x + y; [0] = [19] * 7 - y
+        let mut m = Machine::new(&[
+            1, 17, 18, 19, 1002, 19, 7, 0, 102, -1, 18, 20, 1, 0, 20, 0, 99, 0, 0, 0, 0,
+        ]);
+        m.symbol(17, "x");
+        m.symbol(18, "y");
+        m.run().unwrap();
+
+        let formula = m.memory()[0].to_poly().unwrap();
+        assert_eq!("7*x + 6*y", format!("{}", formula));
+        assert_eq!("(((x + y) * 7) + (-1 * y))", format!("{}", m.memory()[0]));
+    }
+
+    #[test]
+    fn test_poly_display() {
+        let p = Poly::symbol("noun")
+            .mul(&Poly::constant(-3))
+            .and_then(|p| p.add(&Poly::symbol("verb")))
+            .and_then(|p| p.add(&Poly::constant(-5)))
+            .unwrap();
+        assert_eq!("-3*noun + verb - 5", format!("{}", p));
+
+        let p = Poly::symbol("x")
+            .mul(&Poly::symbol("x"))
+            .and_then(|p| p.add(&Poly::constant(2)))
+            .unwrap();
+        assert_eq!("x*x + 2", format!("{}", p));
+        assert!(!p.is_linear());
+
+        let minus_x = Poly::symbol("x").mul(&Poly::constant(-1)).unwrap();
+        let p = Poly::symbol("x").add(&minus_x).unwrap();
+        assert_eq!("0", format!("{}", p));
+
+        let p = Poly::constant(isize::MIN).mul(&Poly::symbol("x")).unwrap();
+        assert_eq!(format!("{}*x", isize::MIN), format!("{}", p));
+        assert_eq!(None, p.mul(&Poly::constant(2)));
+    }
+
+    #[test]
+    fn test_input_and_output() {
+        // output 2 * input
+        let mut m = Machine::new(&[3, 0, 1002, 0, 2, 0, 4, 0, 99]);
+        m.push_input(Expr::sym("in"));
+        m.run().unwrap();
+        assert_eq!("2*in", format!("{}", m.output()[0].to_poly().unwrap()));
+
+        let mut m = Machine::new(&[3, 0, 99]);
+        assert_eq!(Err(Error::NoInput(0)), m.run());
+    }
+
+    #[test]
+    fn test_max_steps() {
+        // jumps to itself forever
+        let mut m = Machine::new(&[1105, 1, 0]);
+        m.max_steps(100);
+        let err = m.run().unwrap_err();
+        assert_eq!(Error::OutOfSteps(0), err);
+        assert_eq!("step limit reached at pc 0", err.to_string());
+
+        let mut m = Machine::new(&[1101, 1, 2, 0, 99]);
+        m.max_steps(2);
+        assert_eq!(Ok(()), m.run());
+
+        let mut m = Machine::new(&[1101, 1, 2, 0, 99]);
+        m.max_steps(1);
+        assert_eq!(Err(Error::OutOfSteps(4)), m.run());
+    }
+
+    #[test]
+    fn test_symbolic_read_address() {
+        // [0] = [x] + 1, which depends on what's at x
+        let mut m = Machine::new(&[1001, 0, 1, 0, 99, 0, 0, 40]);
+        m.symbol(1, "x");
+        m.run().unwrap();
+
+        let out = &m.memory()[0];
+        assert_eq!(None, out.to_poly());
+        assert_eq!("(mem[x] + 1)", format!("{}", out));
+
+        let env = |x| vec![("x".to_string(), x)].into_iter().collect();
+        assert_eq!(Some(41), out.eval(&env(7)));
+        assert_eq!(Some(1002), out.eval(&env(0)));
+        assert_eq!(Some(1), out.eval(&env(1000)));
+        assert_eq!(vec![vec![7]], solve(out, 41, &[("x", 0..=10)]));
+    }
+
+    #[test]
+    fn test_symbolic_errors() {
+        let mut m = Machine::new(&[1101, 1, 1, 5, 99, 0]);
+        m.symbol(3, "dest");
+        assert_eq!(Err(Error::SymbolicAddress(0)), m.run());
+
+        let mut m = Machine::new(&[1005, 4, 0, 99, 0]);
+        m.symbol(4, "cond");
+        assert_eq!(Err(Error::SymbolicBranch(0)), m.run());
+
+        let mut m = Machine::new(&[1105, 1, 3, 0]);
+        m.symbol(3, "op");
+        assert_eq!(Err(Error::SymbolicOpcode(3)), m.run());
+    }
+
+    #[test]
+    fn test_solve() {
+        // [0] = 3x + y + 2
+        let mut m = Machine::new(&[1002, 13, 3, 0, 1, 0, 14, 0, 1001, 0, 2, 0, 99, 0, 0]);
+        m.symbol(13, "x");
+        m.symbol(14, "y");
+        m.run().unwrap();
+
+        let out = &m.memory()[0];
+        assert_eq!("3*x + y + 2", format!("{}", out.to_poly().unwrap()));
+        assert_eq!(
+            vec![vec![3, 9], vec![4, 6], vec![5, 3], vec![6, 0]],
+            solve(out, 20, &[("x", 0..=9), ("y", 0..=9)])
+        );
+        // solving for x instead of y finds the same things
+        assert_eq!(
+            vec![vec![0, 6], vec![3, 5], vec![6, 4], vec![9, 3]],
+            solve(out, 20, &[("y", 0..=9), ("x", 0..=9)])
+        );
+        assert!(solve(out, 20, &[("x", 0..=9)]).is_empty());
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut m = Machine::new(&[1101, isize::MAX, 1, 0, 99]);
+        assert_eq!(Err(Error::Overflow(0)), m.run());
+
+        let mut m = Machine::new(&[109, isize::MAX, 109, 1, 99]);
+        assert_eq!(Err(Error::Overflow(2)), m.run());
+
+        let x_plus_1 = Expr::Add(Expr::sym("x"), Rc::new(Expr::Const(1)));
+        let env = vec![("x".to_string(), isize::MAX)].into_iter().collect();
+        assert_eq!(None, x_plus_1.eval(&env));
+
+        // isize::MIN % -1 overflows too
+        let minus_x = Expr::mul(&Rc::new(Expr::Const(-1)), &Expr::sym("x")).unwrap();
+        assert!(solve(&minus_x, isize::MIN, &[("x", 0..=9)]).is_empty());
+        assert_eq!(vec![vec![3]], solve(&minus_x, -3, &[("x", 0..=9)]));
+    }
+
+    #[test]
+    fn test_solve_nonlinear() {
+        let xy = Expr::mul(&Expr::sym("x"), &Expr::sym("y")).unwrap();
+        assert_eq!(
+            vec![vec![2, 6], vec![3, 4], vec![4, 3], vec![6, 2]],
+            solve(&xy, 12, &[("x", 1..=6), ("y", 1..=6)])
+        );
+    }
+}